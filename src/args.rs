@@ -3,6 +3,13 @@ pub enum OutputFormat {
 	Verify,
 	Bvh,
 	Obj,
+	/// The scene's intermediate representation, for debugging
+	Ir,
+	/// The world-space vertices of every triangle, one per row
+	Csv,
+	/// A Graphviz graph of the objects and sequences in the scene, linked as they reference each
+	/// other
+	Dot,
 }
 
 impl OutputFormat {
@@ -11,13 +18,23 @@ impl OutputFormat {
 			Self::Verify => "verify",
 			Self::Bvh => "bvh",
 			Self::Obj => "obj",
+			Self::Ir => "ir",
+			Self::Csv => "csv",
+			Self::Dot => "dot",
 		}
 	}
 }
 
 impl clap::ValueEnum for OutputFormat {
 	fn value_variants<'a>() -> &'a [Self] {
-		&[Self::Verify, Self::Bvh, Self::Obj]
+		&[
+			Self::Verify,
+			Self::Bvh,
+			Self::Obj,
+			Self::Ir,
+			Self::Csv,
+			Self::Dot,
+		]
 	}
 
 	fn to_possible_value(&self) -> Option<clap::builder::PossibleValue> {
@@ -32,56 +49,651 @@ impl fmt::Display for OutputFormat {
 	}
 }
 
+/// How the children of a box are split in two by --auto-bvh
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AutoBvhMode {
+	/// Split where the surface area heuristic estimates traversal to be cheapest
+	Sah,
+	/// Split at the median centroid along the longest axis, which is faster to build
+	Median,
+}
+
+impl AutoBvhMode {
+	pub fn to_str(self) -> &'static str {
+		match self {
+			Self::Sah => "sah",
+			Self::Median => "median",
+		}
+	}
+}
+
+impl clap::ValueEnum for AutoBvhMode {
+	fn value_variants<'a>() -> &'a [Self] {
+		&[Self::Sah, Self::Median]
+	}
+
+	fn to_possible_value(&self) -> Option<clap::builder::PossibleValue> {
+		Some(clap::builder::PossibleValue::new(self.to_str()))
+	}
+}
+
+impl fmt::Display for AutoBvhMode {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{}", self.to_str())
+	}
+}
+
+/// The order in which the nodes of each kind are written to BVH output
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BvhLayout {
+	/// The order in which the nodes were created
+	Aos,
+	/// Breadth-first from the TLAS, so that the children of each box are contiguous
+	Bfs,
+}
+
+impl BvhLayout {
+	pub fn to_str(self) -> &'static str {
+		match self {
+			Self::Aos => "aos",
+			Self::Bfs => "bfs",
+		}
+	}
+}
+
+impl clap::ValueEnum for BvhLayout {
+	fn value_variants<'a>() -> &'a [Self] {
+		&[Self::Aos, Self::Bfs]
+	}
+
+	fn to_possible_value(&self) -> Option<clap::builder::PossibleValue> {
+		Some(clap::builder::PossibleValue::new(self.to_str()))
+	}
+}
+
+impl fmt::Display for BvhLayout {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{}", self.to_str())
+	}
+}
+
+/// How `primitive_index` is given to the triangles split from a strip
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SplitIndex {
+	/// Each triangle takes the strip's index plus its own position within the strip
+	Increment,
+	/// The index is removed, so each triangle is given its default index
+	Drop,
+	/// Each triangle takes the strip's index unchanged
+	Copy,
+}
+
+impl SplitIndex {
+	pub fn to_str(self) -> &'static str {
+		match self {
+			Self::Increment => "increment",
+			Self::Drop => "drop",
+			Self::Copy => "copy",
+		}
+	}
+}
+
+impl clap::ValueEnum for SplitIndex {
+	fn value_variants<'a>() -> &'a [Self] {
+		&[Self::Increment, Self::Drop, Self::Copy]
+	}
+
+	fn to_possible_value(&self) -> Option<clap::builder::PossibleValue> {
+		Some(clap::builder::PossibleValue::new(self.to_str()))
+	}
+}
+
+impl fmt::Display for SplitIndex {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{}", self.to_str())
+	}
+}
+
+/// The unit in which the `rotate` fields of instances and arrays are given
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AngleUnit {
+	Degrees,
+	Radians,
+}
+
+impl AngleUnit {
+	pub fn to_str(self) -> &'static str {
+		match self {
+			Self::Degrees => "degrees",
+			Self::Radians => "radians",
+		}
+	}
+}
+
+impl clap::ValueEnum for AngleUnit {
+	fn value_variants<'a>() -> &'a [Self] {
+		&[Self::Degrees, Self::Radians]
+	}
+
+	fn to_possible_value(&self) -> Option<clap::builder::PossibleValue> {
+		Some(clap::builder::PossibleValue::new(self.to_str()))
+	}
+}
+
+impl fmt::Display for AngleUnit {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{}", self.to_str())
+	}
+}
+
+/// The unit of indentation in BVH output
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Indent {
+	Tabs,
+	Two,
+	Four,
+}
+
+impl Indent {
+	pub fn to_str(self) -> &'static str {
+		match self {
+			Self::Tabs => "tabs",
+			Self::Two => "2",
+			Self::Four => "4",
+		}
+	}
+
+	/// The text of one level of indentation
+	pub fn unit(self) -> &'static str {
+		match self {
+			Self::Tabs => "\t",
+			Self::Two => "  ",
+			Self::Four => "    ",
+		}
+	}
+}
+
+impl clap::ValueEnum for Indent {
+	fn value_variants<'a>() -> &'a [Self] {
+		&[Self::Tabs, Self::Two, Self::Four]
+	}
+
+	fn to_possible_value(&self) -> Option<clap::builder::PossibleValue> {
+		Some(clap::builder::PossibleValue::new(self.to_str()))
+	}
+}
+
+impl fmt::Display for Indent {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{}", self.to_str())
+	}
+}
+
+/// The axis which points up in the input. Output is always y-up.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UpAxis {
+	Y,
+	Z,
+}
+
+impl UpAxis {
+	pub fn to_str(self) -> &'static str {
+		match self {
+			Self::Y => "y",
+			Self::Z => "z",
+		}
+	}
+}
+
+impl clap::ValueEnum for UpAxis {
+	fn value_variants<'a>() -> &'a [Self] {
+		&[Self::Y, Self::Z]
+	}
+
+	fn to_possible_value(&self) -> Option<clap::builder::PossibleValue> {
+		Some(clap::builder::PossibleValue::new(self.to_str()))
+	}
+}
+
+impl fmt::Display for UpAxis {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{}", self.to_str())
+	}
+}
+
+/// Parse three comma-separated numbers, such as "1,-2.5,0"
+fn parse_triple(text: &str) -> Result<[f64; 3], String> {
+	let parts: Vec<&str> = text.split(',').collect();
+	if parts.len() != 3 {
+		return Err(format!(
+			"expected 3 comma-separated values but found {}",
+			parts.len()
+		));
+	}
+	let mut res = [0.0; 3];
+	for (i, part) in parts.iter().enumerate() {
+		res[i] = match part.trim().parse::<f64>() {
+			Ok(val) => val,
+			Err(_) => return Err(format!("\"{}\" is not a number", part.trim())),
+		};
+	}
+	Ok(res)
+}
+
+/// Parse an RGB color given as three comma-separated channels, each from 0 to 255
+fn parse_color(text: &str) -> Result<[f64; 3], String> {
+	let color = parse_triple(text)?;
+	if color.iter().any(|c| !(0.0..=255.0).contains(c)) {
+		return Err("each color channel must be between 0 and 255".to_string());
+	}
+	Ok(color)
+}
+
 /// Compile scene yaml files into BVH or OBJ format
 #[derive(clap::Parser, Debug)]
 #[command(version, about, long_about = None)]
 pub struct Args {
-	/// YAML file path to read scene data from
-	#[arg(required = true)]
-	pub input: String,
+	/// YAML file path(s) to read scene data from. If multiple are given, each file may reference the
+	/// top-level names of the files before it, and the last file's world is used unless
+	/// --world-from is present.
+	#[arg(required = true, num_args = 1..)]
+	pub input: Vec<String>,
+
+	/// The unit of every `rotate` field in the input.
+	#[arg(long, default_value_t = AngleUnit::Degrees)]
+	pub angle_unit: AngleUnit,
+
+	/// Partition the children of each box into a tree of nested boxes. Boxes holding no more than
+	/// --box-size children are not partitioned further. See --auto-bvh-mode.
+	#[arg(long, action)]
+	pub auto_bvh: bool,
+
+	/// How --auto-bvh splits the children of a box. Without --box-size, "sah" stops splitting
+	/// where it estimates no benefit, but "median" splits down to single children.
+	#[arg(long, default_value_t = AutoBvhMode::Sah)]
+	pub auto_bvh_mode: AutoBvhMode,
 
 	/// The maximum number of children that a single box node can have. 0 indicates unbounded size.
 	#[arg(short = 's', long, default_value_t = 0)]
 	pub box_size: u8,
 
+	/// Only write the wireframes of boxes and procedurals to OBJ output, skipping all the geometry
+	/// within them.
+	#[arg(long, action)]
+	pub boxes_only: bool,
+
+	/// The order of the nodes within each array of BVH output: "aos" keeps the order in which they
+	/// were created, and "bfs" orders them breadth-first from the TLAS for memory locality.
+	/// References between nodes are numbered to match.
+	#[arg(long, default_value_t = BvhLayout::Aos)]
+	pub bvh_layout: BvhLayout,
+
+	/// Print statistics about the quality of the BVH structure (to stderr) when generating BVH
+	/// output.
+	#[arg(long, action)]
+	pub bvh_stats: bool,
+
+	/// Also write the `translate`, `rotate` (in degrees), and `scale` of each instance to BVH
+	/// output, beside its matrix.
+	#[arg(long, action)]
+	pub bvh_trs: bool,
+
+	/// Read BVH output back once it is generated to check that every reference between nodes names
+	/// a node which exists, failing otherwise.
+	#[arg(long, action)]
+	pub bvh_validate_indices: bool,
+
+	/// Translate the scene such that the center of its bounds is at the origin. Applies after
+	/// --flatten, baking the translation into the flattened geometry.
+	#[arg(long, action)]
+	pub center: bool,
+
+	/// Compare the generated output against the existing file(s) at --out instead of writing, and
+	/// fail on the first line which differs.
+	#[arg(long, action)]
+	pub check: bool,
+
+	/// Minimum corner (as x,y,z) of the region to clip the scene to. Any node whose bounds lie
+	/// entirely outside the region is removed. Requires --clip-max.
+	#[arg(long, value_name = "X,Y,Z", value_parser = parse_triple, allow_hyphen_values = true, requires = "clip_max")]
+	pub clip_min: Option<[f64; 3]>,
+
+	/// Maximum corner (as x,y,z) of the region to clip the scene to. Requires --clip-min.
+	#[arg(long, value_name = "X,Y,Z", value_parser = parse_triple, allow_hyphen_values = true, requires = "clip_min")]
+	pub clip_max: Option<[f64; 3]>,
+
+	/// Color each box wireframe in OBJ output by its depth in the box hierarchy instead of its own
+	/// color. Boxes at the same depth share a color.
+	#[arg(long, action)]
+	pub color_by_depth: bool,
+
+	/// Write the `comment` field of each object to the output: as a comment line above the object
+	/// in OBJ, or as a "comment" key on the node in BVH.
+	#[arg(long, action)]
+	pub comments: bool,
+
+	/// Color (as r,g,b with channels from 0 to 255) used in OBJ output for any object without a
+	/// color of its own.
+	#[arg(long, value_name = "R,G,B", value_parser = parse_color, default_value = "0,0,0")]
+	pub default_color: [f64; 3],
+
+	/// Whether triangles and procedurals without an `opaque` field are opaque in BVH output. Omit
+	/// to keep the defaults of each: opaque triangles and non-opaque procedurals.
+	#[arg(long, value_name = "BOOL")]
+	pub default_opaque: Option<bool>,
+
 	/// Each box holding multiple nodes is converted into a box holding single-child boxes. In
 	/// other words, transforms the scene such that every box either holds one child of any type
 	/// OR holds multiple boxes
 	#[arg(short, long, action)]
 	pub double: bool,
 
+	/// Draw the unit x, y, and z axes of each instance's space in OBJ output, as red, green, and
+	/// blue lines, to debug its transform.
+	#[arg(long, action)]
+	pub draw_axes: bool,
+
+	/// Remove any degenerate (zero-area) triangles from strips.
+	#[arg(long, action)]
+	pub drop_degenerate: bool,
+
+	/// Write each instance's object-to-world transform (as `obj_to_world`) next to its
+	/// world-to-object transform in BVH output.
+	#[arg(long, action)]
+	pub emit_forward_transform: bool,
+
+	/// Pad each box by this amount on both sides of any axis along which it has no extent, so that
+	/// rays grazing flat geometry are not missed. See --epsilon-all.
+	#[arg(long, default_value_t = 0.0)]
+	pub epsilon: f64,
+
+	/// Apply the --epsilon padding along every axis of every box, not only those without extent.
+	#[arg(long, action)]
+	pub epsilon_all: bool,
+
+	/// Name (by its `name` field) of a mapping or instance in the world to write alone, as if it were
+	/// the world. A named instance gives what it affects, untransformed, unless
+	/// --keep-world-transform is used.
+	#[arg(long, value_name = "NAME")]
+	pub extract: Option<String>,
+
+	/// Interpret every strip with the default `tri_strip` topology as a triangle fan instead, as if
+	/// given `topology: tri_fan`.
+	#[arg(long, action)]
+	pub fan: bool,
+
+	/// Apply the transforms of all instances directly to the geometry they affect, removing those
+	/// instances from the scene.
+	#[arg(long, action)]
+	pub flatten: bool,
+
+	/// Remove each box which holds only one child (and has no other fields, such as `opaque`),
+	/// holding the child directly in its place. The root box is kept.
+	#[arg(long, action)]
+	pub flatten_single_child_boxes: bool,
+
+	/// Reverse the winding order of every face in OBJ output. See --negate-z.
+	#[arg(long, action)]
+	pub flip_winding: bool,
+
 	/// Output format to compile to. Omit to verify scene only.
 	#[arg(short, long, default_value_t = OutputFormat::Verify)]
 	pub format: OutputFormat,
 
+	/// The unit of indentation in BVH output: a tab, or 2 or 4 spaces.
+	#[arg(long, default_value_t = Indent::Tabs)]
+	pub indent: Indent,
+
 	/// Verify the output uses no more than the given number of instance levels, fail if not. 0
 	/// indicates unbounded. 1 is no instancing. 2 is for two levels: root may use instance. 3
 	/// allows an instance to use an instance. Et cetera.
 	#[arg(short, long, action, default_value_t = 0)]
 	pub instancing: u8,
 
-	/// File to output result to. Omit to output to stdout. Output format will be guessed from the
-	/// path's file extension and used unless --format is present.
-	#[arg(short, long, default_value_t = String::from(""))]
-	pub out: String,
+	/// Print each material used by OBJ output (with its color and how many objects use it) to stdout
+	/// instead of writing output.
+	#[arg(long, action)]
+	pub list_materials: bool,
+
+	/// Move each strip vertex by a pseudo-random offset of up to this amount in each component. 0
+	/// disables jitter. See --seed.
+	#[arg(long, default_value_t = 0.0)]
+	pub jitter: f64,
+
+	/// With --extract, keep the transform which the extracted object had in the world.
+	#[arg(long, action)]
+	pub keep_world_transform: bool,
+
+	/// Only descend this many boxes deep in OBJ and BVH output, where boxes at the limit are written
+	/// without their contents. 0 writes only the root. Omit for no limit.
+	#[arg(long, value_name = "N")]
+	pub max_depth: Option<usize>,
+
+	/// Fail if splitting strips would produce more than this many triangles in total. Omit for no
+	/// limit.
+	#[arg(long)]
+	pub max_triangles: Option<usize>,
+
+	/// Collapse each box whose only child is a box with the same bounds (and no fields of its own
+	/// which differ) into one, saving a traversal step in the BVH.
+	#[arg(long, action)]
+	pub merge_coincident_boxes: bool,
+
+	/// Mirror the scene across the xy plane (negating every z coordinate) to convert between left-
+	/// and right-handed coordinate systems. Often paired with --flip-winding.
+	#[arg(long, action)]
+	pub negate_z: bool,
+
+	/// Only add the default material (see --default-color) to OBJ output once it must replace
+	/// another, leaving objects drawn before any color to the viewer's default.
+	#[arg(long, action)]
+	pub no_default_material: bool,
+
+	/// Scale the direction of each ray to unit length, so its `min` and `max` are distances.
+	#[arg(long, action)]
+	pub normalize_rays: bool,
+
+	/// File(s) to output result to, separated by commas. Omit to output to stdout. The format of
+	/// each will be guessed from its file extension and used unless --format is present.
+	#[arg(short, long, value_delimiter = ',')]
+	pub out: Vec<String>,
+
+	/// The number of significant digits to write vertices, bounds, and matrices with. Omit to write
+	/// every number in full.
+	#[arg(long, value_parser = clap::value_parser!(u8).range(1..=17))]
+	pub precision: Option<u8>,
+
+	/// Read default field values from the YAML file at this path: a mapping from field names
+	/// (`custom_index`, `geometry_index`, `mask`, `opaque`, or `sbt_record_offset`) to the values
+	/// written in BVH output for objects which do not give them. --default-opaque takes precedence.
+	#[arg(long)]
+	pub profile: Option<String>,
+
+	/// Print the percentage done of splitting strips and writing output to stderr as it happens.
+	#[arg(long, action)]
+	pub progress: bool,
+
+	/// Print no warnings or other messages, only errors.
+	#[arg(long, action)]
+	pub quiet: bool,
+
+	/// Length of the cone drawn by --ray-arrows, as a fraction of the ray's length.
+	#[arg(long, default_value_t = 0.05)]
+	pub ray_arrow_size: f64,
+
+	/// Draw the head of each ray as a solid cone (instead of lines) in OBJ output.
+	#[arg(long, action)]
+	pub ray_arrows: bool,
 
 	/// Generate no boxes (cannot be used in generating BVH output!).
 	#[arg(short = 'a', long, action)]
 	pub raw: bool,
 
+	/// Swap the components of any procedural's `min` and `max` which are inverted instead of
+	/// failing.
+	#[arg(long, action)]
+	pub repair_bounds: bool,
+
+	/// In verification mode, print a JSON report of the outcome (including any warnings and
+	/// statistics about the scene) to stdout.
+	#[arg(long, action)]
+	pub report_json: bool,
+
 	/// Box the root, even if a single node would suffice.
 	#[arg(short, long, action)]
 	pub root: bool,
 
+	/// Seed for the pseudo-random offsets of --jitter. The same seed always gives the same output.
+	#[arg(long, default_value_t = 0)]
+	pub seed: u64,
+
+	/// Put all geometry in OBJ output in one object with the given name, rather than an object for
+	/// each. Materials are still used as usual.
+	#[arg(long)]
+	pub single_object: Option<String>,
+
+	/// Put the faces of each strip and sphere in OBJ output which has no `smooth` field in smoothing
+	/// group 1, so that viewers interpolate their normals.
+	#[arg(long, action)]
+	pub smooth_all: bool,
+
+	/// Draw the edges of each non-opaque box in OBJ output as tubes of this thickness (in the space
+	/// of the box), made of triangles, rather than as lines, which some viewers do not show.
+	#[arg(long, value_name = "THICKNESS")]
+	pub solid_wireframe: Option<f64>,
+
+	/// Number of latitude bands used to tessellate each sphere in OBJ output. Twice as many
+	/// longitude segments are used.
+	#[arg(long, default_value_t = 8, value_parser = clap::value_parser!(u8).range(2..))]
+	pub sphere_subdiv: u8,
+
 	/// Split tri-strips into individual triangles. Enabled implicitly when generating BVH target
 	#[arg(short = 'p', long, action)]
 	pub split: bool,
 
-	/// Whether ray and point objects affect dimensions of their containing box
+	/// How the `primitive_index` of a strip is given to the triangles split from it. Other fields
+	/// (including `geometry_index`) are always copied unchanged.
+	#[arg(long, default_value_t = SplitIndex::Increment)]
+	pub split_index: SplitIndex,
+
+	/// Write each object in the world's data to its own OBJ file (named by the object's `name`, else
+	/// its position) in the directory given by --out.
+	#[arg(long, action)]
+	pub split_output: bool,
+
+	/// Print statistics about the scene (after any transformations) to stderr.
+	#[arg(long, action)]
+	pub stats: bool,
+
+	/// Fail (after reporting all messages) if any warning was reported, rather than writing output.
+	#[arg(long, action)]
+	pub strict: bool,
+
+	/// Whether ray, line, and point objects affect dimensions of their containing box
 	#[arg(short, long, action, default_value_t = false)]
 	pub total_box: bool,
 
+	/// Print the hierarchy of the world (after any transformations) to stdout instead of writing
+	/// output, showing the kind and name (or index) of each object and the bounds of each box.
+	#[arg(long, action)]
+	pub tree: bool,
+
+	/// The axis which points up in the input. With z, the scene is rotated to be y-up as viewers
+	/// expect, such that +z becomes +y and +y becomes -z.
+	#[arg(long, default_value_t = UpAxis::Y)]
+	pub up_axis: UpAxis,
+
+	/// Warn about each field of an object which no output uses, such as a misspelled `opaque`.
+	#[arg(long, action)]
+	pub warn_unknown_fields: bool,
+
+	/// Top-level name (from any input) of the object to use as the world, instead of the world of
+	/// the input given by --world-from.
+	#[arg(long)]
+	pub world: Option<String>,
+
+	/// Input file whose world should be used as the root of the scene. Omit to use the last input.
+	#[arg(long, default_value_t = String::from(""))]
+	pub world_from: String,
+
 	/// Force instance nodes to hold only boxes directly.
 	#[arg(short, long, action)]
 	pub wrap: bool,
 }
+
+/// The options of a command line giving none, and so (unlike any accepted one) no input
+impl Default for Args {
+	fn default() -> Args {
+		Args {
+			input: vec![],
+			angle_unit: AngleUnit::Degrees,
+			auto_bvh: false,
+			auto_bvh_mode: AutoBvhMode::Sah,
+			box_size: 0,
+			boxes_only: false,
+			bvh_layout: BvhLayout::Aos,
+			bvh_stats: false,
+			bvh_trs: false,
+			bvh_validate_indices: false,
+			center: false,
+			check: false,
+			clip_min: None,
+			clip_max: None,
+			color_by_depth: false,
+			comments: false,
+			default_color: [0.0; 3],
+			default_opaque: None,
+			double: false,
+			draw_axes: false,
+			drop_degenerate: false,
+			emit_forward_transform: false,
+			epsilon: 0.0,
+			epsilon_all: false,
+			extract: None,
+			fan: false,
+			flatten: false,
+			flatten_single_child_boxes: false,
+			flip_winding: false,
+			format: OutputFormat::Verify,
+			indent: Indent::Tabs,
+			instancing: 0,
+			list_materials: false,
+			jitter: 0.0,
+			keep_world_transform: false,
+			max_depth: None,
+			max_triangles: None,
+			merge_coincident_boxes: false,
+			negate_z: false,
+			no_default_material: false,
+			normalize_rays: false,
+			out: vec![],
+			precision: None,
+			profile: None,
+			progress: false,
+			quiet: false,
+			ray_arrow_size: 0.05,
+			ray_arrows: false,
+			raw: false,
+			repair_bounds: false,
+			report_json: false,
+			root: false,
+			seed: 0,
+			single_object: None,
+			smooth_all: false,
+			solid_wireframe: None,
+			sphere_subdiv: 8,
+			split: false,
+			split_index: SplitIndex::Increment,
+			split_output: false,
+			stats: false,
+			strict: false,
+			total_box: false,
+			tree: false,
+			up_axis: UpAxis::Y,
+			warn_unknown_fields: false,
+			world: None,
+			world_from: String::new(),
+			wrap: false,
+		}
+	}
+}
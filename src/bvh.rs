@@ -1,4 +1,13 @@
-use crate::ir::{Node, Scene};
+use std::collections::{HashMap, VecDeque};
+
+use crate::CompileError;
+use crate::args::{Args, BvhLayout, Indent};
+use crate::ir::{
+	Fields, Instance, Mapping, Node, Point3D, Scene, Sequence, Strip, TransformMat, fmt_float,
+	is_hidden, new_point,
+};
+use crate::load::{Marked, Value, load_json_from_str};
+use crate::report::{Reporter, json_string};
 
 #[derive(Clone)]
 enum MapType {
@@ -7,34 +16,15 @@ enum MapType {
 	Procedural(usize),
 }
 
-fn calculate_dead_delta(dead: &[usize], idx: &usize) -> Option<usize> {
-	let mut delta = 0;
-	for dead_idx in dead {
-		match (*dead_idx).cmp(idx) {
-			std::cmp::Ordering::Less => {
-				delta += 1;
-			},
-			std::cmp::Ordering::Equal => return None,
-			std::cmp::Ordering::Greater => break,
-		}
-	}
-	Some(delta)
-}
-
 fn in_dead(dead: &[usize], idx: &usize) -> bool {
 	dead.binary_search(idx).is_ok()
 }
 
-fn to_major_minor(
-	node: &Node,
-	mappings: &[MapType],
-	dead_insts: &[usize],
-	dead_strips: &[usize],
-) -> Option<(usize, usize)> {
+fn to_major_minor(node: &Node, layout: &Layout) -> Option<(usize, usize)> {
 	match node {
-		Node::Strip(idx) => calculate_dead_delta(dead_strips, idx).map(|delta| (2, *idx - delta)),
-		Node::Instance(idx) => calculate_dead_delta(dead_insts, idx).map(|delta| (1, *idx - delta)),
-		Node::Mapping(idx) => match mappings[*idx] {
+		Node::Strip(idx) => layout.strip_nums[*idx].map(|num| (2, num)),
+		Node::Instance(idx) => layout.inst_nums[*idx].map(|num| (1, num)),
+		Node::Mapping(idx) => match layout.mappings[*idx] {
 			MapType::Unused => None,
 			MapType::Box(i) => Some((0, i)),
 			MapType::Procedural(i) => Some((3, i)),
@@ -43,13 +33,23 @@ fn to_major_minor(
 	}
 }
 
+/// Mark each mapping reachable from `node` as live. Mappings are only reached through the `data`
+/// of other mappings and the `affected` of instances (at any depth of nesting), since no other field
+/// may hold an object. A live mapping is not walked again, so shared subtrees are only walked once.
+/// Hidden nodes are not walked, so nothing only they hold is live.
 fn track_live_mappings(scene: &Scene, mappings: &mut Vec<MapType>, node: &Node) {
+	if is_hidden(scene, node) {
+		return;
+	}
 	match node {
 		Node::Instance(idx) => {
 			let inst = &scene.instances[*idx];
 			track_live_mappings(scene, mappings, &inst.affected);
 		},
 		Node::Mapping(idx) => {
+			if !matches!(mappings[*idx], MapType::Unused) {
+				return;
+			}
 			mappings[*idx] = MapType::Box(0); // use default 0 which will be replaced later
 			let map = &scene.mappings[*idx];
 			if let Some(Node::Sequence(idx)) = map.fields.get("data") {
@@ -65,9 +65,275 @@ fn track_live_mappings(scene: &Scene, mappings: &mut Vec<MapType>, node: &Node)
 	}
 }
 
-pub fn to_bvh(scene: &Scene) -> Vec<String> {
+fn track_live_instances(scene: &Scene, live: &mut Vec<bool>, node: &Node) {
+	if is_hidden(scene, node) {
+		return;
+	}
+	match node {
+		Node::Instance(idx) => {
+			if !live[*idx] {
+				live[*idx] = true;
+				track_live_instances(scene, live, &scene.instances[*idx].affected);
+			}
+		},
+		Node::Mapping(idx) => {
+			if let Some(Node::Sequence(idx)) = scene.mappings[*idx].fields.get("data") {
+				for node in scene.sequences[*idx].vals.iter() {
+					track_live_instances(scene, live, node);
+				}
+			}
+		},
+		_ => {},
+	}
+}
+
+/// Get the value of the numeric field `name`, or `default` if the field is absent (or not a number,
+/// which is warned about).
+fn number_field(fields: &Fields, name: &str, default: usize, reporter: &mut Reporter) -> usize {
+	match fields.get(name) {
+		Some(Node::Number(v)) => *v as usize,
+		Some(node) => {
+			reporter.warn(&format!(
+				"`{name}` is not a number as expected! Got {node} instead."
+			));
+			default
+		},
+		None => default,
+	}
+}
+
+/// The fields which a profile may give defaults for
+const PROFILE_FIELDS: &[&str] = &[
+	"custom_index",
+	"geometry_index",
+	"mask",
+	"opaque",
+	"sbt_record_offset",
+];
+
+/// Read the profile at `path`: a mapping from field names to the values written in BVH output for
+/// objects which do not give those fields themselves.
+pub fn load_profile(path: &str) -> Result<Fields, CompileError> {
+	let doc = crate::load::load_file(path)?;
+	profile_fields(path, doc).map_err(CompileError::Scene)
+}
+
+/// The field defaults given by the document of the profile at `path`
+fn profile_fields(path: &str, doc: Marked) -> Result<Fields, String> {
+	let Value::Hash(entries) = doc.value else {
+		return Err(format!(
+			"Profile \"{path}\" must hold a mapping of field defaults!"
+		));
+	};
+	let mut fields = Fields::new();
+	for (key, value) in entries {
+		let name = match &key.value {
+			Value::String(name) => PROFILE_FIELDS.iter().find(|field| *field == name),
+			_ => None,
+		};
+		let Some(name) = name else {
+			return Err(format!(
+				"Profile \"{path}\" gives a default at {} for a field other than those allowed: \
+				 {}!",
+				key.loc,
+				PROFILE_FIELDS.join(", ")
+			));
+		};
+		let node = match value.value {
+			Value::Boolean(val) if *name == "opaque" => Node::Bool(val),
+			Value::Integer(val) if *name != "opaque" && val >= 0 => Node::Number(val as f64),
+			_ => {
+				let expected = if *name == "opaque" {
+					"a bool"
+				} else {
+					"a nonnegative integer"
+				};
+				return Err(format!(
+					"The default of `{name}` in profile \"{path}\" at {} must be {expected}!",
+					value.loc
+				));
+			},
+		};
+		fields.insert((*name).into(), node);
+	}
+	Ok(fields)
+}
+
+/// The default of the numeric field `name` from the profile, if it gives one, else `default`
+fn profile_number(scene: &Scene, name: &str, default: usize) -> usize {
+	match scene.profile.get(name) {
+		Some(Node::Number(v)) => *v as usize,
+		_ => default,
+	}
+}
+
+/// Whether nodes without an `opaque` field are opaque, given those of their kind are by `default`
+fn default_opaque(scene: &Scene, args: &Args, default: bool) -> bool {
+	let profile = match scene.profile.get("opaque") {
+		Some(Node::Bool(v)) => Some(*v),
+		_ => None,
+	};
+	args.default_opaque.or(profile).unwrap_or(default)
+}
+
+/// Format the three numbers as a JSON array, with numbers of the given precision
+fn triple(vals: [f64; 3], precision: Option<u8>) -> String {
+	format!(
+		"[ {}, {}, {} ]",
+		fmt_float(vals[0], precision),
+		fmt_float(vals[1], precision),
+		fmt_float(vals[2], precision)
+	)
+}
+
+/// Push an instance's field holding the given transform, written column by column.
+fn push_matrix(res: &mut Vec<String>, name: &str, trans: &TransformMat, precision: Option<u8>) {
+	res.push(format!("\t\t\t\"{name}\" : ["));
+	for i in 0..4 {
+		let sep = if i == 3 { "" } else { "," };
+		let col = [trans[(0, i)], trans[(1, i)], trans[(2, i)]];
+		res.push(format!("\t\t\t\t{}{sep}", triple(col, precision)));
+	}
+	res.push("\t\t\t],".to_string());
+}
+
+/// Push the node's `comment` field (if any) as a JSON string.
+fn push_comment(res: &mut Vec<String>, fields: &Fields, scene: &Scene, reporter: &mut Reporter) {
+	match fields.get("comment") {
+		Some(Node::String(str_at)) => res.push(format!(
+			"\t\t\t\"comment\" : {},",
+			json_string(&scene.strings[*str_at])
+		)),
+		Some(node) => reporter.warn(&format!(
+			"`comment` is not a string as expected! Got {node} instead."
+		)),
+		None => {},
+	}
+}
+
+/// Check that the world may be the TLAS, which must be a box or an instance. Other kinds of node
+/// are rejected by some loaders as the root.
+pub fn check_tlas(scene: &Scene) -> Result<(), String> {
+	let kind = match scene.world {
+		Node::Strip(_) => "triangle",
+		Node::Sphere(_) => "sphere",
+		Node::Mapping(idx) if scene.mappings[idx].fields.contains_key("min") => "procedural",
+		_ => return Ok(()),
+	};
+	Err(format!(
+		"The BVH TLAS must be a box or an instance, not a {kind}! Use option 'root' to box the \
+		 world."
+	))
+}
+
+/// How the nodes of a scene are numbered in BVH output, and which are left out of it
+struct Layout {
+	mappings: Vec<MapType>,
+	boxes: Vec<usize>,
+	procs: Vec<usize>,
+	/// The instances and strips written, in order
+	insts: Vec<usize>,
+	strips: Vec<usize>,
+	/// The number in output of each instance and strip, if it is written
+	inst_nums: Vec<Option<usize>>,
+	strip_nums: Vec<Option<usize>>,
+	/// Boxes at the maximum depth, whose children are not written
+	leaves: Vec<usize>,
+}
+
+/// Record which strips are reachable from `node`, and which of those are reachable without passing
+/// through a hidden node. `walked` marks the mappings already walked, when hidden and not.
+fn track_visible_strips(
+	scene: &Scene,
+	node: &Node,
+	hidden: bool,
+	walked: &mut Vec<[bool; 2]>,
+	strips: &mut Vec<(bool, bool)>,
+) {
+	let hidden = hidden || is_hidden(scene, node);
+	match node {
+		Node::Strip(idx) => {
+			strips[*idx].0 = true;
+			strips[*idx].1 |= !hidden;
+		},
+		Node::Instance(idx) => {
+			let affected = &scene.instances[*idx].affected;
+			track_visible_strips(scene, affected, hidden, walked, strips);
+		},
+		Node::Mapping(idx) => {
+			if walked[*idx][hidden as usize] {
+				return;
+			}
+			walked[*idx][hidden as usize] = true;
+			if let Some(Node::Sequence(seq)) = scene.mappings[*idx].fields.get("data") {
+				for node in scene.sequences[*seq].vals.iter() {
+					track_visible_strips(scene, node, hidden, walked, strips);
+				}
+			}
+		},
+		_ => {},
+	}
+}
+
+/// The least number of boxes enclosing each mapping, instance, and strip reachable from the world
+/// (without passing through a hidden node). Unreached nodes have depth `usize::MAX`.
+struct Depths {
+	mappings: Vec<usize>,
+	instances: Vec<usize>,
+	strips: Vec<usize>,
+}
+
+fn track_depths(scene: &Scene, node: &Node, depth: usize, depths: &mut Depths) {
+	if is_hidden(scene, node) {
+		return;
+	}
+	match node {
+		Node::Strip(idx) => depths.strips[*idx] = depths.strips[*idx].min(depth),
+		Node::Instance(idx) => {
+			if depth < depths.instances[*idx] {
+				depths.instances[*idx] = depth;
+				track_depths(scene, &scene.instances[*idx].affected, depth, depths);
+			}
+		},
+		Node::Mapping(idx) => {
+			if depth < depths.mappings[*idx] {
+				depths.mappings[*idx] = depth;
+				let map = &scene.mappings[*idx];
+				if let Some(Node::Sequence(seq)) = map.fields.get("data") {
+					for node in scene.sequences[*seq].vals.iter() {
+						track_depths(scene, node, depth + map.is_box as usize, depths);
+					}
+				}
+			}
+		},
+		_ => {},
+	}
+}
+
+/// Whether the node is left out of BVH output along with any instances of it
+fn is_inert(scene: &Scene, node: &Node) -> bool {
+	if is_hidden(scene, node) {
+		return true;
+	}
+	match node {
+		Node::Ray(_) | Node::Line(_) | Node::Point(_) => true,
+		Node::Instance(idx) => is_inert(scene, &scene.instances[*idx].affected),
+		_ => false,
+	}
+}
+
+fn layout(scene: &Scene, max_depth: Option<usize>, order: BvhLayout) -> Layout {
 	// We need to check some conditions about mappings and instances before we can start printing
 
+	// 0) Anything deeper than the maximum depth (if given) is left out
+	let mut depths = Depths {
+		mappings: vec![usize::MAX; scene.mappings.len()],
+		instances: vec![usize::MAX; scene.instances.len()],
+		strips: vec![usize::MAX; scene.strips.len()],
+	};
+	track_depths(scene, &scene.world, 0, &mut depths);
+	let too_deep = |depth: usize| max_depth.is_some_and(|max| depth > max);
+
 	// 1) Determine how to handle each mapping. Each can be one of: ignored, box, procedural, dead.
 	//    We must know the category each fits in before we start printing any nodes.
 	let mut mappings = vec![MapType::Unused; scene.mappings.len()];
@@ -82,6 +348,10 @@ pub fn to_bvh(scene: &Scene) -> Vec<String> {
 		if let MapType::Unused = map_type {
 			continue; // skip over dead maps
 		}
+		if too_deep(depths.mappings[i]) {
+			*map_type = MapType::Unused;
+			continue;
+		}
 
 		let mapping = &scene.mappings[i];
 		if mapping.is_box {
@@ -99,57 +369,195 @@ pub fn to_bvh(scene: &Scene) -> Vec<String> {
 		}
 	}
 
-	// 2) Rays are removed in the BVH target, so we must delete any instance nodes which have ray
-	//    children (since they cannot exist independently).
+	// 2) Rays, lines, and points are removed in the BVH target, so we must delete any instance
+	//    nodes which have them as children (since they cannot exist independently), as well as any
+	//    instances of those instances.
+	//    Instances which cannot be reached from the world (such as those removed by flattening) are
+	//    likewise deleted.
+	let mut live_insts = vec![false; scene.instances.len()];
+	track_live_instances(scene, &mut live_insts, &scene.world);
 	let mut dead_insts = vec![];
 	for (inst_idx, instance) in scene.instances.iter().enumerate() {
-		if let Node::Ray(_) = instance.affected {
+		if !live_insts[inst_idx]
+			|| is_inert(scene, &instance.affected)
+			|| too_deep(depths.instances[inst_idx])
+		{
 			dead_insts.push(inst_idx);
 		}
 	}
 
-	// 3) Strips with more than 3 vertices must have been killed and replaced with triangles
+	// 3) Strips with more than 3 vertices must have been killed and replaced with triangles. Those
+	//    which are only reachable through hidden nodes are likewise deleted.
+	let mut reached = vec![(false, false); scene.strips.len()];
+	let mut walked = vec![[false; 2]; scene.mappings.len()];
+	track_visible_strips(scene, &scene.world, false, &mut walked, &mut reached);
 	let mut dead_strips = vec![];
 	for (strip_idx, tri) in scene.strips.iter().enumerate() {
-		if tri.vals.len() > 3 {
+		let (reachable, visible) = reached[strip_idx];
+		if tri.vals.len() > 3 || (reachable && !visible) || too_deep(depths.strips[strip_idx]) {
 			dead_strips.push(strip_idx);
 		}
 	}
 
+	let leaves = boxes
+		.iter()
+		.copied()
+		.filter(|idx| max_depth.is_some_and(|max| depths.mappings[*idx] >= max))
+		.collect();
+
+	let insts: Vec<usize> = (0..scene.instances.len())
+		.filter(|idx| !in_dead(&dead_insts, idx))
+		.collect();
+	let strips: Vec<usize> = (0..scene.strips.len())
+		.filter(|idx| !in_dead(&dead_strips, idx))
+		.collect();
+	let mut layout = Layout {
+		mappings,
+		boxes,
+		procs,
+		inst_nums: numbering(&insts, scene.instances.len()),
+		strip_nums: numbering(&strips, scene.strips.len()),
+		insts,
+		strips,
+		leaves,
+	};
+	if order == BvhLayout::Bfs {
+		breadth_first(scene, &mut layout);
+	}
+	layout
+}
+
+/// The position of each of `len` indices within `order`, if it is there
+fn numbering(order: &[usize], len: usize) -> Vec<Option<usize>> {
+	let mut nums = vec![None; len];
+	for (num, idx) in order.iter().enumerate() {
+		nums[*idx] = Some(num);
+	}
+	nums
+}
+
+/// Reorder the nodes written so that each kind is numbered breadth-first from the TLAS, with all
+/// children of a box numbered together. Nodes not reached from the TLAS follow in the order they
+/// had before.
+fn breadth_first(scene: &Scene, layout: &mut Layout) {
+	// The new order of each kind, by major number, holding what each node was numbered before
+	let mut orders: [Vec<usize>; 4] = Default::default();
+	let mut seen: [Vec<bool>; 4] = [
+		vec![false; layout.boxes.len()],
+		vec![false; layout.insts.len()],
+		vec![false; layout.strips.len()],
+		vec![false; layout.procs.len()],
+	];
+	let mut queue = VecDeque::new();
+	let mut visit = |node: &Node, queue: &mut VecDeque<Node>, layout: &Layout| {
+		if let Some((major, minor)) = to_major_minor(node, layout)
+			&& !seen[major][minor]
+		{
+			seen[major][minor] = true;
+			orders[major].push(minor);
+			queue.push_back(*node);
+		}
+	};
+	visit(&scene.world, &mut queue, layout);
+	while let Some(node) = queue.pop_front() {
+		match node {
+			Node::Instance(idx) => visit(&scene.instances[idx].affected, &mut queue, layout),
+			Node::Mapping(idx) if !layout.leaves.contains(&idx) => {
+				if let Some(Node::Sequence(seq)) = scene.mappings[idx].fields.get("data") {
+					for child in scene.sequences[*seq].vals.iter() {
+						visit(child, &mut queue, layout);
+					}
+				}
+			},
+			_ => {},
+		}
+	}
+	for (major, order) in orders.iter_mut().enumerate() {
+		order.extend((0..seen[major].len()).filter(|minor| !seen[major][*minor]));
+	}
+
+	let [boxes, insts, strips, procs] = orders;
+	layout.boxes = boxes.iter().map(|old| layout.boxes[*old]).collect();
+	layout.procs = procs.iter().map(|old| layout.procs[*old]).collect();
+	layout.insts = insts.iter().map(|old| layout.insts[*old]).collect();
+	layout.strips = strips.iter().map(|old| layout.strips[*old]).collect();
+	for (num, idx) in layout.boxes.iter().enumerate() {
+		layout.mappings[*idx] = MapType::Box(num);
+	}
+	for (num, idx) in layout.procs.iter().enumerate() {
+		layout.mappings[*idx] = MapType::Procedural(num);
+	}
+	layout.inst_nums = numbering(&layout.insts, scene.instances.len());
+	layout.strip_nums = numbering(&layout.strips, scene.strips.len());
+}
+
+/// Check that the child of every instance in BVH output is itself in the output: a box, triangle,
+/// procedural, or another instance.
+pub fn check_instances(scene: &Scene) -> Result<(), String> {
+	let layout = layout(scene, None, BvhLayout::Aos);
+	for inst_idx in layout.insts.iter() {
+		let child = &scene.instances[*inst_idx].affected;
+		if to_major_minor(child, &layout).is_none() {
+			return Err(format!(
+				"Instance {inst_idx} has a child ({child}) which cannot be written to BVH! The child \
+				 must be a box, triangle, procedural, or another instance."
+			));
+		}
+	}
+	Ok(())
+}
+
+pub fn to_bvh(scene: &Scene, args: &Args, reporter: &mut Reporter) -> Vec<String> {
+	let layout = layout(scene, args.max_depth, args.bvh_layout);
+
 	// Finally, print all nodes, using the numbering determined before to convert all references
-	let mut res = vec!["{".to_string()];
-	match to_major_minor(&scene.world, &mappings, &dead_insts, &dead_strips) {
+	// Name the version of the emitter, so that readers can tell which format to expect
+	let mut res = vec![
+		"{".to_string(),
+		"\t\"generator\" : \"scene-builder\",".to_string(),
+	];
+	let version = format!("\t\"version\" : {}", json_string(env!("CARGO_PKG_VERSION")));
+	match to_major_minor(&scene.world, &layout) {
 		Some((major, minor)) => {
+			res.push(format!("{version},"));
 			res.push(format!("\t\"tlas\" : [ {}, {} ],", major, minor));
 		},
 		None => {
+			res.push(version);
 			res.push("}".to_string());
+			reindent(&mut res, args.indent);
 			return res;
 		},
 	};
 
+	let steps = layout.boxes.len() + layout.insts.len() + layout.strips.len() + layout.procs.len();
+	reporter.start_stage("Writing BVH", steps);
 	res.push("\t\"box_nodes\" : [".to_string());
-	for (i, box_idx) in boxes.iter().enumerate() {
+	for (i, box_idx) in layout.boxes.iter().enumerate() {
+		reporter.step();
 		res.push("\t\t{".to_string());
 		let boxx = &scene.mappings[*box_idx];
+		if args.comments {
+			push_comment(&mut res, &boxx.fields, scene, reporter);
+		}
 
 		res.push(format!(
-			"\t\t\t\"min_bounds\" : [ {}, {}, {} ],",
-			boxx.min.x, boxx.min.y, boxx.min.z
+			"\t\t\t\"min_bounds\" : {},",
+			triple(boxx.min.into(), args.precision)
 		));
 		res.push(format!(
-			"\t\t\t\"max_bounds\" : [ {}, {}, {} ],",
-			boxx.max.x, boxx.max.y, boxx.max.z
+			"\t\t\t\"max_bounds\" : {},",
+			triple(boxx.max.into(), args.precision)
 		));
 
 		res.push("\t\t\t\"child_nodes\" : [".to_string());
-		if let Some(Node::Sequence(idx)) = scene.mappings[*box_idx].fields.get("data") {
+		if let Some(Node::Sequence(idx)) = scene.mappings[*box_idx].fields.get("data")
+			&& !layout.leaves.contains(box_idx)
+		{
 			let data = &scene.sequences[*idx];
 			let mut kids = vec![];
 			for node in data.vals.iter() {
-				if let Some((major, minor)) =
-					to_major_minor(node, &mappings, &dead_insts, &dead_strips)
-				{
+				if let Some((major, minor)) = to_major_minor(node, &layout) {
 					kids.push((major, minor));
 				}
 			}
@@ -164,7 +572,7 @@ pub fn to_bvh(scene: &Scene) -> Vec<String> {
 		}
 		res.push("\t\t\t]".to_string());
 
-		if i + 1 == boxes.len() {
+		if i + 1 == layout.boxes.len() {
 			res.push("\t\t}".to_string());
 		} else {
 			res.push("\t\t},".to_string());
@@ -173,66 +581,80 @@ pub fn to_bvh(scene: &Scene) -> Vec<String> {
 	res.push("\t],".to_string());
 
 	res.push("\t\"instance_nodes\" : [".to_string());
-	for (inst_idx, instance) in scene.instances.iter().enumerate() {
-		// If this is an instance of a ray, do NOT print it!
-		if in_dead(&dead_insts, &inst_idx) {
-			continue;
-		}
+	for (i, inst_idx) in layout.insts.iter().enumerate() {
+		reporter.step();
+		let inst_idx = *inst_idx;
+		let instance = &scene.instances[inst_idx];
 		res.push("\t\t{".to_string());
+		if args.comments {
+			push_comment(&mut res, &instance.fields, scene, reporter);
+		}
 
-		let trans = instance.world_to_obj();
-		res.push("\t\t\t\"world_to_obj\" : [".to_string());
-		for i in 0..4 {
-			if i == 3 {
-				res.push(format!(
-					"\t\t\t\t[ {}, {}, {} ]",
-					trans[(0, i)],
-					trans[(1, i)],
-					trans[(2, i)]
-				))
-			} else {
+		push_matrix(
+			&mut res,
+			"world_to_obj",
+			&instance.world_to_obj(),
+			args.precision,
+		);
+		if args.emit_forward_transform {
+			push_matrix(
+				&mut res,
+				"obj_to_world",
+				&instance.obj_to_world(),
+				args.precision,
+			);
+		}
+		// A baked transform has no components to write
+		if args.bvh_trs && instance.baked.is_none() {
+			for (name, vals) in [
+				("translate", instance.translate),
+				("rotate", instance.rotate),
+				("scale", instance.scale),
+			] {
 				res.push(format!(
-					"\t\t\t\t[ {}, {}, {} ],",
-					trans[(0, i)],
-					trans[(1, i)],
-					trans[(2, i)]
-				))
+					"\t\t\t\"{name}\" : {},",
+					triple(vals.into(), args.precision)
+				));
 			}
 		}
-		res.push("\t\t\t],".to_string());
 
-		match to_major_minor(&instance.affected, &mappings, &dead_insts, &dead_strips) {
+		match to_major_minor(&instance.affected, &layout) {
 			Some((major, minor)) => {
 				res.push(format!("\t\t\t\"child_node\" : [ {}, {} ],", major, minor));
 			},
-			None => panic!("Instance without legal child should have already been filtered!"),
+			None => panic!(
+				"Instance without legal child should have been rejected by `check_instances`!"
+			),
 		};
 
-		let mut id = inst_idx;
-		if let Some(Node::Number(v)) = instance.fields.get("id") {
-			id = *v as usize;
-		}
+		let id = number_field(&instance.fields, "id", inst_idx, reporter);
 		res.push(format!("\t\t\t\"id\" : {id},"));
 
-		let mut custom_index = 0;
-		if let Some(Node::Number(v)) = instance.fields.get("custom_index") {
-			custom_index = *v as usize;
-		}
+		let custom_index = number_field(
+			&instance.fields,
+			"custom_index",
+			profile_number(scene, "custom_index", 0),
+			reporter,
+		);
 		res.push(format!("\t\t\t\"custom_index\" : {custom_index},"));
 
-		let mut mask = 255;
-		if let Some(Node::Number(v)) = instance.fields.get("mask") {
-			mask = *v as usize;
-		}
+		let mask = number_field(
+			&instance.fields,
+			"mask",
+			profile_number(scene, "mask", 255),
+			reporter,
+		);
 		res.push(format!("\t\t\t\"mask\" : {mask},"));
 
-		let mut sbt_record_offset = 0;
-		if let Some(Node::Number(v)) = instance.fields.get("sbt_record_offset") {
-			sbt_record_offset = *v as usize;
-		}
+		let sbt_record_offset = number_field(
+			&instance.fields,
+			"sbt_record_offset",
+			profile_number(scene, "sbt_record_offset", 0),
+			reporter,
+		);
 		res.push(format!("\t\t\t\"sbt_record_offset\" : {sbt_record_offset}"));
 
-		if inst_idx + 1 == scene.instances.len() {
+		if i + 1 == layout.insts.len() {
 			res.push("\t\t}".to_string());
 		} else {
 			res.push("\t\t},".to_string());
@@ -241,25 +663,27 @@ pub fn to_bvh(scene: &Scene) -> Vec<String> {
 	res.push("\t],".to_string());
 
 	res.push("\t\"triangle_nodes\" : [".to_string());
-	for (tri_idx, tri) in scene.strips.iter().enumerate() {
-		if in_dead(&dead_strips, &tri_idx) {
-			continue;
-		}
+	for (i, tri_idx) in layout.strips.iter().enumerate() {
+		reporter.step();
+		let tri_idx = *tri_idx;
+		let tri = &scene.strips[tri_idx];
 		res.push("\t\t{".to_string());
-
-		let mut geom_index = 0;
-		if let Some(Node::Number(v)) = tri.fields.get("geometry_index") {
-			geom_index = *v as usize
+		if args.comments {
+			push_comment(&mut res, &tri.fields, scene, reporter);
 		}
+
+		let geom_index = number_field(
+			&tri.fields,
+			"geometry_index",
+			profile_number(scene, "geometry_index", 0),
+			reporter,
+		);
 		res.push(format!("\t\t\t\"geometry_index\" : {geom_index},"));
 
-		let mut prim_index = tri_idx;
-		if let Some(Node::Number(v)) = tri.fields.get("primitive_index") {
-			prim_index = *v as usize;
-		}
+		let prim_index = number_field(&tri.fields, "primitive_index", tri_idx, reporter);
 		res.push(format!("\t\t\t\"primitive_index\" : {prim_index},"));
 
-		let mut opaque = true;
+		let mut opaque = default_opaque(scene, args, true);
 		if let Some(Node::Bool(v)) = tri.fields.get("opaque") {
 			opaque = *v;
 		}
@@ -268,14 +692,20 @@ pub fn to_bvh(scene: &Scene) -> Vec<String> {
 		res.push("\t\t\t\"vertices\" : [".to_string());
 		for (i, vert) in tri.vals.iter().enumerate() {
 			if i + 1 == tri.vals.len() {
-				res.push(format!("\t\t\t\t[ {}, {}, {} ]", vert.x, vert.y, vert.z));
+				res.push(format!(
+					"\t\t\t\t{}",
+					triple((*vert).into(), args.precision)
+				));
 			} else {
-				res.push(format!("\t\t\t\t[ {}, {}, {} ],", vert.x, vert.y, vert.z));
+				res.push(format!(
+					"\t\t\t\t{},",
+					triple((*vert).into(), args.precision)
+				));
 			}
 		}
 		res.push("\t\t\t]".to_string());
 
-		if tri_idx + 1 == scene.strips.len() {
+		if i + 1 == layout.strips.len() {
 			res.push("\t\t}".to_string());
 		} else {
 			res.push("\t\t},".to_string());
@@ -284,45 +714,905 @@ pub fn to_bvh(scene: &Scene) -> Vec<String> {
 	res.push("\t],".to_string());
 
 	res.push("\t\"procedural_nodes\" : [".to_string());
-	for (i, proc_idx) in procs.iter().enumerate() {
+	for (i, proc_idx) in layout.procs.iter().enumerate() {
+		reporter.step();
 		res.push("\t\t{".to_string());
 		let proc = &scene.mappings[*proc_idx];
+		if args.comments {
+			push_comment(&mut res, &proc.fields, scene, reporter);
+		}
 
 		res.push(format!(
-			"\t\t\t\"min_bounds\" : [ {}, {}, {} ],",
-			proc.min.x, proc.min.y, proc.min.z
+			"\t\t\t\"min_bounds\" : {},",
+			triple(proc.min.into(), args.precision)
 		));
 		res.push(format!(
-			"\t\t\t\"max_bounds\" : [ {}, {}, {} ],",
-			proc.max.x, proc.max.y, proc.max.z
+			"\t\t\t\"max_bounds\" : {},",
+			triple(proc.max.into(), args.precision)
 		));
 
-		let mut opaque = false;
+		let mut opaque = default_opaque(scene, args, false);
 		if let Some(Node::Bool(v)) = proc.fields.get("opaque") {
 			opaque = *v;
 		}
 		res.push(format!("\t\t\t\"opaque\" : {opaque},"));
 
-		let mut geom_index = 0;
-		if let Some(Node::Number(v)) = proc.fields.get("geometry_index") {
-			geom_index = *v as usize
-		}
+		let geom_index = number_field(
+			&proc.fields,
+			"geometry_index",
+			profile_number(scene, "geometry_index", 0),
+			reporter,
+		);
 		res.push(format!("\t\t\t\"geometry_index\" : {geom_index},"));
 
-		let mut prim_index = *proc_idx;
-		if let Some(Node::Number(v)) = proc.fields.get("primitive_index") {
-			prim_index = *v as usize;
-		}
+		let prim_index = number_field(&proc.fields, "primitive_index", *proc_idx, reporter);
 		res.push(format!("\t\t\t\"primitive_index\" : {prim_index}"));
 
-		if i + 1 == procs.len() {
+		if i + 1 == layout.procs.len() {
 			res.push("\t\t}".to_string());
 		} else {
 			res.push("\t\t},".to_string());
 		}
 	}
 	res.push("\t]".to_string());
+	reporter.end_stage();
 
 	res.push("}".to_string());
+
+	if args.bvh_stats {
+		report_structure(scene, &layout, reporter);
+	}
+	reindent(&mut res, args.indent);
 	res
 }
+
+/// Replace the leading tabs of each line, which the output is built with, by the indent unit. Tabs
+/// within strings are always escaped, so only indentation is changed.
+fn reindent(lines: &mut [String], indent: Indent) {
+	if indent == Indent::Tabs {
+		return;
+	}
+	for line in lines.iter_mut() {
+		let body = line.trim_start_matches('\t');
+		let depth = line.len() - body.len();
+		*line = format!("{}{body}", indent.unit().repeat(depth));
+	}
+}
+
+/// The number of BVH nodes on the longest path from `node` down to a leaf
+fn max_depth(scene: &Scene, node: &Node, layout: &Layout) -> usize {
+	if to_major_minor(node, layout).is_none() {
+		return 0;
+	}
+	let children = match node {
+		Node::Instance(idx) => vec![scene.instances[*idx].affected],
+		Node::Mapping(idx) if matches!(layout.mappings[*idx], MapType::Box(_)) => {
+			match scene.mappings[*idx].fields.get("data") {
+				Some(Node::Sequence(seq_at)) => scene.sequences[*seq_at].vals.clone(),
+				_ => vec![],
+			}
+		},
+		_ => vec![],
+	};
+	let deepest = children
+		.iter()
+		.map(|child| max_depth(scene, child, layout))
+		.max();
+	1 + deepest.unwrap_or(0)
+}
+
+/// Report statistics about the quality of the BVH structure: its maximum depth, how many nodes of
+/// each type it holds, the average number of children in leaf boxes (those holding no other box),
+/// and the total surface area of its internal boxes (those holding another box, as a proxy for the
+/// cost of traversal).
+fn report_structure(scene: &Scene, layout: &Layout, reporter: &mut Reporter) {
+	let mut boxes = 0;
+	let mut procedurals = 0;
+	let mut leaves = 0;
+	let mut leaf_children = 0;
+	let mut area = 0.0;
+	for (map_idx, map_type) in layout.mappings.iter().enumerate() {
+		match map_type {
+			MapType::Unused => {},
+			MapType::Procedural(_) => procedurals += 1,
+			MapType::Box(_) => {
+				boxes += 1;
+				let map = &scene.mappings[map_idx];
+				let Some(Node::Sequence(seq_at)) = map.fields.get("data") else {
+					continue;
+				};
+				let kids: Vec<(usize, usize)> = scene.sequences[*seq_at]
+					.vals
+					.iter()
+					.filter_map(|node| to_major_minor(node, layout))
+					.collect();
+				if kids.iter().all(|(major, _)| *major != 0) {
+					leaves += 1;
+					leaf_children += kids.len();
+				} else {
+					area += crate::transform::surface_area(&map.min, &map.max);
+				}
+			},
+		}
+	}
+	let occupancy = if leaves == 0 {
+		0.0
+	} else {
+		leaf_children as f64 / leaves as f64
+	};
+	let depth = max_depth(scene, &scene.world, layout);
+	reporter.info(&format!("bvh depth: {depth}"));
+	reporter.info(&format!("bvh box nodes: {boxes}"));
+	reporter.info(&format!("bvh instance nodes: {}", layout.insts.len()));
+	reporter.info(&format!("bvh triangle nodes: {}", layout.strips.len()));
+	reporter.info(&format!("bvh procedural nodes: {procedurals}"));
+	reporter.info(&format!("bvh leaf box occupancy: {occupancy}"));
+	reporter.info(&format!("bvh internal box surface area: {area}"));
+}
+
+/// A value parsed from BVH JSON. Numbers are kept as `f64` so that values such as `-0` survive a
+/// round trip exactly.
+enum Json {
+	Number(f64),
+	Bool(bool),
+	#[allow(dead_code)] // no string values are read back yet, but they must still be parsed
+	String(String),
+	Array(Vec<Json>),
+	Object(Vec<(String, Json)>),
+}
+
+impl Json {
+	fn get(&self, key: &str) -> Option<&Json> {
+		match self {
+			Json::Object(entries) => entries.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+			_ => None,
+		}
+	}
+
+	fn field(&self, key: &str) -> Result<&Json, String> {
+		match self.get(key) {
+			Some(val) => Ok(val),
+			None => Err(format!("Missing key \"{key}\" in BVH input!")),
+		}
+	}
+
+	fn as_array(&self, what: &str) -> Result<&[Json], String> {
+		match self {
+			Json::Array(vals) => Ok(vals),
+			_ => Err(format!("Expected {what} to be an array in BVH input!")),
+		}
+	}
+
+	fn as_number(&self, what: &str) -> Result<f64, String> {
+		match self {
+			Json::Number(val) => Ok(*val),
+			_ => Err(format!("Expected {what} to be a number in BVH input!")),
+		}
+	}
+
+	fn as_bool(&self, what: &str) -> Result<bool, String> {
+		match self {
+			Json::Bool(val) => Ok(*val),
+			_ => Err(format!("Expected {what} to be a boolean in BVH input!")),
+		}
+	}
+
+	fn as_3d(&self, what: &str) -> Result<Point3D, String> {
+		let vals = self.as_array(what)?;
+		if vals.len() != 3 {
+			return Err(format!(
+				"Expected {what} to have 3 components in BVH input!"
+			));
+		}
+		let mut ret = new_point(0.0);
+		for i in 0..3 {
+			ret[i] = vals[i].as_number(what)?;
+		}
+		Ok(ret)
+	}
+
+	/// Interpret as a `[ major, minor ]` node reference
+	fn as_reference(&self, what: &str) -> Result<(usize, usize), String> {
+		let vals = self.as_array(what)?;
+		if vals.len() != 2 {
+			return Err(format!(
+				"Expected {what} to be a [major, minor] pair in BVH input!"
+			));
+		}
+		Ok((vals[0].as_index(what)?, vals[1].as_index(what)?))
+	}
+
+	/// Interpret as an index, which must be a nonnegative integer
+	fn as_index(&self, what: &str) -> Result<usize, String> {
+		let val = self.as_number(what)?;
+		if val < 0.0 || val.fract() != 0.0 || val >= usize::MAX as f64 {
+			return Err(format!(
+				"Expected {what} to hold nonnegative integers in BVH input!"
+			));
+		}
+		Ok(val as usize)
+	}
+}
+
+/// Parse BVH JSON with the same parser as JSON scene input, keeping only the values which BVH
+/// output can hold.
+fn parse_json(text: &str) -> Result<Json, String> {
+	to_json(load_json_from_str(text).map_err(|err| format!("In BVH input: {err}"))?)
+}
+
+fn to_json(doc: Marked) -> Result<Json, String> {
+	Ok(match doc.value {
+		Value::Real(val) => match val.parse::<f64>() {
+			Ok(val) => Json::Number(val),
+			Err(_) => {
+				return Err(format!(
+					"Could not parse number at {} of BVH input!",
+					doc.loc
+				));
+			},
+		},
+		Value::Integer(val) => Json::Number(val as f64),
+		Value::Boolean(val) => Json::Bool(val),
+		Value::String(val) => Json::String(val),
+		Value::Array(vals) => Json::Array(vals.into_iter().map(to_json).collect::<Result<_, _>>()?),
+		Value::Hash(entries) => {
+			let mut fields = vec![];
+			for (key, val) in entries {
+				let Value::String(key) = key.value else {
+					return Err(format!(
+						"Expected a string key at {} of BVH input!",
+						key.loc
+					));
+				};
+				fields.push((key, to_json(val)?));
+			}
+			Json::Object(fields)
+		},
+		Value::Null => return Err(format!("Unexpected null at {} of BVH input!", doc.loc)),
+	})
+}
+
+/// Check that every reference in BVH output (as produced by `to_bvh`), from the TLAS, boxes, and
+/// instances, names a node of a known kind within the array of that kind.
+pub fn validate_indices(json: &str) -> Result<(), String> {
+	let root = parse_json(json)?;
+	let Some(tlas) = root.get("tlas") else {
+		return Ok(()); // an empty scene has no nodes to reference
+	};
+	let mut counts = vec![];
+	for kind in [
+		"box_nodes",
+		"instance_nodes",
+		"triangle_nodes",
+		"procedural_nodes",
+	] {
+		counts.push(root.field(kind)?.as_array(kind)?.len());
+	}
+	let check = |(major, minor): (usize, usize), from: &str| -> Result<(), String> {
+		match counts.get(major) {
+			None => Err(format!(
+				"BVH output references [ {major}, {minor} ] from {from}, but there is no node kind \
+				 {major}!"
+			)),
+			Some(count) if minor >= *count => Err(format!(
+				"BVH output references [ {major}, {minor} ] from {from}, but there are only \
+				 {count} nodes of kind {major}!"
+			)),
+			Some(_) => Ok(()),
+		}
+	};
+	check(tlas.as_reference("tlas")?, "the TLAS")?;
+	let boxes = root.field("box_nodes")?.as_array("box_nodes")?;
+	for (i, boxx) in boxes.iter().enumerate() {
+		for child in boxx.field("child_nodes")?.as_array("child_nodes")? {
+			check(child.as_reference("child_nodes")?, &format!("box {i}"))?;
+		}
+	}
+	let instances = root.field("instance_nodes")?.as_array("instance_nodes")?;
+	for (i, instance) in instances.iter().enumerate() {
+		let child = instance.field("child_node")?.as_reference("child_node")?;
+		check(child, &format!("instance {i}"))?;
+	}
+	Ok(())
+}
+
+/// Reconstruct a scene from BVH output (as produced by `to_bvh`). Every value which `to_bvh` would
+/// otherwise derive (such as indices and masks) is set explicitly, so that the reconstructed scene
+/// generates the same BVH output again.
+pub fn from_bvh(json: &str) -> Result<Scene, String> {
+	let root = parse_json(json)?;
+	let mut scene = Scene::new();
+	let tlas = match root.get("tlas") {
+		Some(tlas) => tlas.as_reference("tlas")?,
+		None => return Ok(scene), // an empty scene has no nodes
+	};
+
+	let boxes = root.field("box_nodes")?.as_array("box_nodes")?;
+	let instances = root.field("instance_nodes")?.as_array("instance_nodes")?;
+	let triangles = root.field("triangle_nodes")?.as_array("triangle_nodes")?;
+	let procs = root
+		.field("procedural_nodes")?
+		.as_array("procedural_nodes")?;
+
+	// Boxes and procedurals are both mappings. Boxes are created first, then procedurals, which
+	// preserves the relative order of each.
+	let to_node = |(major, minor): (usize, usize)| -> Result<Node, String> {
+		let (node, count) = match major {
+			0 => (Node::Mapping(minor), boxes.len()),
+			1 => (Node::Instance(minor), instances.len()),
+			2 => (Node::Strip(minor), triangles.len()),
+			3 => (Node::Mapping(boxes.len() + minor), procs.len()),
+			_ => return Err(format!("Unknown node type {major} in BVH input!")),
+		};
+		if minor >= count {
+			return Err(format!(
+				"Reference [ {major}, {minor} ] is out of bounds in BVH input!"
+			));
+		}
+		Ok(node)
+	};
+	let number = |scene: &mut Scene, vals: &[f64]| -> Node {
+		let seq_at = scene.sequences.len();
+		let mut seq = Sequence::new();
+		seq.vals = vals.iter().map(|v| Node::Number(*v)).collect();
+		scene.sequences.push(seq);
+		Node::Sequence(seq_at)
+	};
+
+	for boxx in boxes {
+		let mut mapping = Mapping::new();
+		let min = boxx.field("min_bounds")?.as_3d("min_bounds")?;
+		let max = boxx.field("max_bounds")?.as_3d("max_bounds")?;
+		mapping.as_box(&min, &max);
+		let mut seq = Sequence::new();
+		for child in boxx.field("child_nodes")?.as_array("child_nodes")? {
+			seq.vals.push(to_node(child.as_reference("child node")?)?);
+		}
+		let seq_at = scene.sequences.len();
+		scene.sequences.push(seq);
+		mapping.fields.insert("data".into(), Node::Sequence(seq_at));
+		scene.mappings.push(mapping);
+	}
+
+	for proc in procs {
+		let mut mapping = Mapping::new();
+		let min = proc.field("min_bounds")?.as_3d("min_bounds")?;
+		let max = proc.field("max_bounds")?.as_3d("max_bounds")?;
+		mapping.as_box(&min, &max);
+		let min_node = number(&mut scene, min.as_slice());
+		let max_node = number(&mut scene, max.as_slice());
+		mapping.fields.insert("min".into(), min_node);
+		mapping.fields.insert("max".into(), max_node);
+		mapping.fields.insert(
+			"opaque".into(),
+			Node::Bool(proc.field("opaque")?.as_bool("opaque")?),
+		);
+		for key in ["geometry_index", "primitive_index"] {
+			let val = proc.field(key)?.as_number(key)?;
+			mapping.fields.insert(key.into(), Node::Number(val));
+		}
+		scene.mappings.push(mapping);
+	}
+
+	for inst in instances {
+		let rows = inst.field("world_to_obj")?.as_array("world_to_obj")?;
+		if rows.len() != 4 {
+			return Err("Expected world_to_obj to have 4 rows in BVH input!".to_string());
+		}
+		let mut baked = TransformMat::zeros();
+		for (c, row) in rows.iter().enumerate() {
+			let col = row.as_3d("world_to_obj row")?;
+			for r in 0..3 {
+				baked[(r, c)] = col[r];
+			}
+		}
+		let mut fields = HashMap::new();
+		for key in ["id", "custom_index", "mask", "sbt_record_offset"] {
+			let val = inst.field(key)?.as_number(key)?;
+			fields.insert(key.into(), Node::Number(val));
+		}
+		scene.instances.push(Instance {
+			affected: to_node(inst.field("child_node")?.as_reference("child_node")?)?,
+			scale: new_point(1.0),
+			rotate: new_point(0.0),
+			translate: new_point(0.0),
+			baked: Some(baked),
+			fields,
+			bounds: None,
+		});
+	}
+
+	for tri in triangles {
+		let mut strip = Strip::new();
+		for vert in tri.field("vertices")?.as_array("vertices")? {
+			strip.vals.push(vert.as_3d("vertex")?);
+		}
+		if strip.vals.len() != 3 {
+			return Err("Expected each triangle to have 3 vertices in BVH input!".to_string());
+		}
+		strip.fields.insert(
+			"opaque".into(),
+			Node::Bool(tri.field("opaque")?.as_bool("opaque")?),
+		);
+		for key in ["geometry_index", "primitive_index"] {
+			let val = tri.field(key)?.as_number(key)?;
+			strip.fields.insert(key.into(), Node::Number(val));
+		}
+		scene.strips.push(strip);
+	}
+
+	scene.world = to_node(tlas)?;
+	Ok(scene)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::ir::homogenize;
+	use clap::Parser;
+
+	fn args(extra: &[&str]) -> Args {
+		Args::parse_from(["scene-builder", "in.yaml"].iter().chain(extra))
+	}
+
+	fn round_trip(expected: &str) {
+		let scene = from_bvh(expected).unwrap();
+		let mut got = to_bvh(&scene, &args(&[]), &mut Reporter::new()).join("\n");
+		got.push('\n');
+		assert_eq!(got, expected);
+	}
+
+	#[test]
+	fn json_values() {
+		let json = parse_json("{\"zero\": -0, \"big\": 1e3, \"name\": \"\\u00e9\\ud83d\\ude00\"}")
+			.unwrap();
+		let zero = json.field("zero").unwrap().as_number("zero").unwrap();
+		assert!(zero == 0.0 && zero.is_sign_negative());
+		assert_eq!(json.field("big").unwrap().as_number("big").unwrap(), 1000.0);
+		assert!(matches!(json.get("name"), Some(Json::String(name)) if name == "\u{e9}\u{1f600}"));
+		assert!(
+			parse_json("{\"tlas\": null}")
+				.err()
+				.unwrap()
+				.contains("Unexpected null")
+		);
+	}
+
+	#[test]
+	fn round_trip_boxes() {
+		round_trip(include_str!("../examples/combination/pyramid/out.json"));
+	}
+
+	#[test]
+	fn round_trip_instances() {
+		round_trip(include_str!(
+			"../examples/single/instance/combination/out.json"
+		));
+	}
+
+	#[test]
+	fn round_trip_procedural() {
+		round_trip(include_str!("../examples/combination/intersect/out.json"));
+	}
+
+	#[test]
+	fn tlas_kind() {
+		let docs = crate::load::load_from_str("strip: [[0, 0, 0], [1, 0, 0], [0, 1, 0]]").unwrap();
+		for (extra, valid) in [(&[][..], false), (&["--root"][..], true)] {
+			let mut scene = crate::ir::to_ir(&docs[0]).unwrap();
+			let args = args(&[&["--format", "bvh"][..], extra].concat());
+			crate::transform::transform(&mut scene, &args, true, &mut Reporter::new()).unwrap();
+			assert_eq!(check_tlas(&scene).is_ok(), valid);
+		}
+	}
+
+	#[test]
+	fn nested_procedural_live() {
+		let text = "proc:\n  min: [0, 0, 0]\n  max: [1, 1, 1]\ndata:\n- instance:\n    instance: proc\n    \
+		            translate: [1, 0, 0]\n  scale: 2\n- instance: proc\n  translate: [0, 3, 0]";
+		let docs = crate::load::load_from_str(text).unwrap();
+		let mut scene = crate::ir::to_ir(&docs[0]).unwrap();
+		let args = args(&["--format", "bvh"]);
+		crate::transform::transform(&mut scene, &args, true, &mut Reporter::new()).unwrap();
+		let json = to_bvh(&scene, &args, &mut Reporter::new()).join("\n");
+		let out = parse_json(&json).unwrap();
+		let procs = out.field("procedural_nodes").unwrap();
+		// The procedural is shared by both paths, so it appears once
+		assert_eq!(procs.as_array("procedural_nodes").unwrap().len(), 1);
+		let insts = out.field("instance_nodes").unwrap();
+		assert_eq!(insts.as_array("instance_nodes").unwrap().len(), 3);
+	}
+
+	#[test]
+	fn space_indent() {
+		let text = "instance:\n  strip: [[0, 0, 0], [1, 0, 0], [0, 1, 0]]\ncomment: \"a\ttab\"";
+		let docs = crate::load::load_from_str(text).unwrap();
+		let mut scene = crate::ir::to_ir(&docs[0]).unwrap();
+		let args = args(&["--format", "bvh", "--root", "--comments", "--indent", "2"]);
+		crate::transform::transform(&mut scene, &args, true, &mut Reporter::new()).unwrap();
+		let lines = to_bvh(&scene, &args, &mut Reporter::new());
+		assert_eq!(lines[3], "  \"tlas\" : [ 0, 0 ],");
+		assert!(
+			lines
+				.iter()
+				.any(|line| line == "      \"comment\" : \"a\\ttab\",")
+		);
+		assert!(lines.iter().all(|line| !line.contains('\t')));
+		assert!(parse_json(&lines.join("\n")).is_ok());
+	}
+
+	#[test]
+	fn version_key() {
+		let docs = crate::load::load_from_str("strip: [[0, 0, 0], [1, 0, 0], [0, 1, 0]]").unwrap();
+		let mut scene = crate::ir::to_ir(&docs[0]).unwrap();
+		let args = args(&["--format", "bvh"]);
+		crate::transform::transform(&mut scene, &args, true, &mut Reporter::new()).unwrap();
+		let json = to_bvh(&scene, &args, &mut Reporter::new()).join("\n");
+		let out = parse_json(&json).unwrap();
+		let version = out.field("version").unwrap();
+		assert!(matches!(version, Json::String(v) if v == env!("CARGO_PKG_VERSION")));
+	}
+
+	#[test]
+	fn max_depth() {
+		let text = "data:\n- strip: [[0, 0, 0], [1, 0, 0], [0, 1, 0]]\n- data:\n  - strip: [[0, 0, 1], [1, \
+		            0, 1], [0, 1, 1]]\n  - data:\n    - strip: [[0, 0, 2], [1, 0, 2], [0, 1, 2]]";
+		let docs = crate::load::load_from_str(text).unwrap();
+		let mut scene = crate::ir::to_ir(&docs[0]).unwrap();
+		let args = args(&["--format", "bvh", "--max-depth", "1"]);
+		crate::transform::transform(&mut scene, &args, true, &mut Reporter::new()).unwrap();
+		let json = to_bvh(&scene, &args, &mut Reporter::new()).join("\n");
+		let out = parse_json(&json).unwrap();
+		// The root holds the first strip and the second box, which is written without its contents
+		let boxes = out
+			.field("box_nodes")
+			.unwrap()
+			.as_array("box_nodes")
+			.unwrap();
+		assert_eq!(boxes.len(), 2);
+		let kids = |i: usize| {
+			boxes[i]
+				.field("child_nodes")
+				.unwrap()
+				.as_array("")
+				.unwrap()
+				.len()
+		};
+		assert_eq!((kids(0), kids(1)), (2, 0));
+		let tris = out.field("triangle_nodes").unwrap();
+		assert_eq!(tris.as_array("triangle_nodes").unwrap().len(), 1);
+
+		let obj = crate::obj::to_obj(&scene, &args, &mut Reporter::new());
+		let objects: Vec<&String> = obj.iter().filter(|line| line.starts_with("o ")).collect();
+		assert_eq!(objects, ["o box0", "o strip0", "o box2"]);
+	}
+
+	#[test]
+	fn breadth_first_layout() {
+		let text = "deep:\n  strip: [[0, 0, 2], [1, 0, 2], [0, 1, 2]]\ndata:\n- data:\n  - data:\n    - \
+		            deep\n  - strip: [[0, 0, 1], [1, 0, 1], [0, 1, 1]]\n- strip: [[0, 0, 0], [1, 0, 0], \
+		            [0, 1, 0]]";
+		let docs = crate::load::load_from_str(text).unwrap();
+		let mut scene = crate::ir::to_ir(&docs[0]).unwrap();
+		let bfs = args(&["--format", "bvh", "--bvh-layout", "bfs"]);
+		crate::transform::transform(&mut scene, &bfs, true, &mut Reporter::new()).unwrap();
+		let mut json = to_bvh(&scene, &bfs, &mut Reporter::new()).join("\n");
+		json.push('\n');
+		assert_ne!(
+			json,
+			to_bvh(&scene, &args(&[]), &mut Reporter::new()).join("\n") + "\n"
+		);
+
+		let out = parse_json(&json).unwrap();
+		assert_eq!(
+			out.field("tlas").unwrap().as_reference("tlas").unwrap(),
+			(0, 0)
+		);
+		// Each level of triangles is numbered before the next deeper level
+		let tris = out.field("triangle_nodes").unwrap();
+		let z: Vec<f64> = tris
+			.as_array("triangle_nodes")
+			.unwrap()
+			.iter()
+			.map(|tri| {
+				tri.field("vertices").unwrap().as_array("").unwrap()[0]
+					.as_3d("")
+					.unwrap()
+					.z
+			})
+			.collect();
+		assert_eq!(z, [0.0, 1.0, 2.0]);
+
+		// Reading the output back gives a scene whose creation order is the breadth-first order, so
+		// the default layout writes it unchanged if every reference was renumbered consistently
+		round_trip(&json);
+	}
+
+	#[test]
+	fn hidden_nodes() {
+		let text = "data:\n- strip: [[0, 0, 0], [1, 0, 0], [0, 1, 0]]\n- strip: [[0, 0, 1], [1, 0, 1], [0, \
+		            1, 1]]\n  visible: false\n- visible: false\n  data:\n  - strip: [[0, 0, 2], [1, 0, 2], \
+		            [0, 1, 2]]\n  - instance:\n      strip: [[0, 0, 3], [1, 0, 3], [0, 1, 3]]";
+		let docs = crate::load::load_from_str(text).unwrap();
+		let mut scene = crate::ir::to_ir(&docs[0]).unwrap();
+		let args = args(&["--format", "bvh"]);
+		crate::transform::transform(&mut scene, &args, true, &mut Reporter::new()).unwrap();
+		assert!(check_instances(&scene).is_ok());
+		// Hidden nodes remain in the scene, but only the first strip is output
+		assert_eq!(scene.strips.len(), 4);
+		let json = to_bvh(&scene, &args, &mut Reporter::new()).join("\n");
+		let out = parse_json(&json).unwrap();
+		for (field, count) in [
+			("box_nodes", 1),
+			("instance_nodes", 0),
+			("triangle_nodes", 1),
+		] {
+			assert_eq!(
+				out.field(field).unwrap().as_array(field).unwrap().len(),
+				count
+			);
+		}
+		let obj = crate::obj::to_obj(&scene, &args, &mut Reporter::new());
+		assert_eq!(
+			obj.iter()
+				.filter(|line| line.starts_with("o strip"))
+				.count(),
+			1
+		);
+		// Nor is the hidden box drawn
+		assert!(!obj.iter().any(|line| line.contains("box3")));
+
+		let docs =
+			crate::load::load_from_str("strip: [[0, 0, 0], [1, 0, 0], [0, 1, 0]]\nvisible: 0")
+				.unwrap();
+		assert!(crate::ir::to_ir(&docs[0]).is_err());
+	}
+
+	#[test]
+	fn illegal_instance_child() {
+		let text = "data:\n- strip: [[0, 0, 0], [1, 0, 0], [0, 1, 0]]\n- instance:\n    foo: 1";
+		let docs = crate::load::load_from_str(text).unwrap();
+		let mut scene = crate::ir::to_ir(&docs[0]).unwrap();
+		let args = args(&["--format", "bvh"]);
+		crate::transform::transform(&mut scene, &args, true, &mut Reporter::new()).unwrap();
+		assert!(
+			check_instances(&scene)
+				.unwrap_err()
+				.starts_with("Instance 0 has a child (Mapping")
+		);
+
+		// An instance of an instance of a ray is removed along with it
+		let text = "data:\n- strip: [[0, 0, 0], [1, 0, 0], [0, 1, 0]]\n- instance:\n    instance:\n      \
+		            origin: [0, 0, 0]\n      direction: [1, 0, 0]\n      max: 1";
+		let docs = crate::load::load_from_str(text).unwrap();
+		let mut scene = crate::ir::to_ir(&docs[0]).unwrap();
+		crate::transform::transform(&mut scene, &args, true, &mut Reporter::new()).unwrap();
+		assert!(check_instances(&scene).is_ok());
+		let json = to_bvh(&scene, &args, &mut Reporter::new()).join("\n");
+		let out = parse_json(&json).unwrap();
+		let insts = out.field("instance_nodes").unwrap();
+		assert!(insts.as_array("instance_nodes").unwrap().is_empty());
+	}
+
+	#[test]
+	fn default_opaque() {
+		let text = "data:\n- strip: [[0, 0, 0], [1, 0, 0], [0, 1, 0]]\n- strip: [[0, 0, 1], [1, 0, 1], [0, \
+		            1, 1]]\n  opaque: true\n- min: [0, 0, 0]\n  max: [1, 1, 1]";
+		let docs = crate::load::load_from_str(text).unwrap();
+		let opaques = |extra: &[&str]| {
+			let mut scene = crate::ir::to_ir(&docs[0]).unwrap();
+			let args = args(&[&["--format", "bvh"][..], extra].concat());
+			crate::transform::transform(&mut scene, &args, true, &mut Reporter::new()).unwrap();
+			let lines = to_bvh(&scene, &args, &mut Reporter::new());
+			lines
+				.iter()
+				.filter_map(|line| line.strip_prefix("\t\t\t\"opaque\" : "))
+				.map(|val| val.to_string())
+				.collect::<Vec<String>>()
+		};
+		assert_eq!(opaques(&[]), vec!["true,", "true,", "false,"]);
+		assert_eq!(
+			opaques(&["--default-opaque", "false"]),
+			vec!["false,", "true,", "false,"]
+		);
+		assert_eq!(
+			opaques(&["--default-opaque", "true"]),
+			vec!["true,", "true,", "true,"]
+		);
+	}
+
+	#[test]
+	fn forward_transform_inverts() {
+		let text = "instance:\n  strip: [[0, 0, 0], [1, 0, 0], [0, 1, 0]]\nscale: [2, 1.5, 0.5]\nrotate: \
+		            [20, 60, 100]\ntranslate: [-1, 2, 3]";
+		let docs = crate::load::load_from_str(text).unwrap();
+		let mut scene = crate::ir::to_ir(&docs[0]).unwrap();
+		let args = args(&["--format", "bvh", "--emit-forward-transform"]);
+		crate::transform::transform(&mut scene, &args, true, &mut Reporter::new()).unwrap();
+		let json = to_bvh(&scene, &args, &mut Reporter::new()).join("\n");
+		let out = parse_json(&json).unwrap();
+
+		let inst = &out
+			.field("instance_nodes")
+			.unwrap()
+			.as_array("instance_nodes")
+			.unwrap()[0];
+		let matrix = |name: &str| {
+			let rows = inst.field(name).unwrap().as_array(name).unwrap();
+			let mut mat = TransformMat::zeros();
+			for (c, row) in rows.iter().enumerate() {
+				let col = row.as_3d(name).unwrap();
+				for r in 0..3 {
+					mat[(r, c)] = col[r];
+				}
+			}
+			homogenize(&mat)
+		};
+		let product = matrix("world_to_obj") * matrix("obj_to_world");
+		assert!((product - nalgebra::Matrix4::identity()).abs().max() < 1e-9);
+	}
+
+	#[test]
+	fn mask_from_reference() {
+		let text = "defaultMask: 3\ndata:\n- instance:\n    strip: [[0, 0, 0], [1, 0, 0], [0, 1, \
+		            0]]\n  mask: defaultMask";
+		let docs = crate::load::load_from_str(text).unwrap();
+		let mut scene = crate::ir::to_ir(&docs[0]).unwrap();
+		let args = args(&["--format", "bvh"]);
+		let mut reporter = Reporter::new();
+		crate::transform::transform(&mut scene, &args, true, &mut reporter).unwrap();
+		let lines = to_bvh(&scene, &args, &mut reporter);
+		assert!(lines.iter().any(|line| line == "\t\t\t\"mask\" : 3,"));
+		assert!(reporter.warnings().is_empty());
+	}
+
+	#[test]
+	fn profile_defaults() {
+		let dir = std::env::temp_dir().join(format!(
+			"scene-builder-{}-profile-defaults",
+			std::process::id()
+		));
+		std::fs::create_dir_all(&dir).unwrap();
+		let path = dir.join("profile.yaml");
+		std::fs::write(&path, "mask: 7\ngeometry_index: 2\n").unwrap();
+		let profile = load_profile(path.to_str().unwrap()).unwrap();
+		std::fs::remove_file(&path).unwrap();
+
+		let text = "data:\n- instance:\n    strip: [[0, 0, 0], [1, 0, 0], [0, 1, 0]]\n- instance:\n    \
+		            strip: [[0, 0, 1], [1, 0, 1], [0, 1, 1]]\n  mask: 1";
+		let docs = crate::load::load_from_str(text).unwrap();
+		let mut scene = crate::ir::to_ir(&docs[0]).unwrap();
+		scene.profile = profile;
+		let args = args(&["--format", "bvh"]);
+		crate::transform::transform(&mut scene, &args, true, &mut Reporter::new()).unwrap();
+		let lines = to_bvh(&scene, &args, &mut Reporter::new());
+		let count = |text: &str| lines.iter().filter(|line| *line == text).count();
+		// The instance which gives its own mask keeps it
+		assert_eq!(count("\t\t\t\"mask\" : 7,"), 1);
+		assert_eq!(count("\t\t\t\"mask\" : 1,"), 1);
+		assert_eq!(count("\t\t\t\"geometry_index\" : 2,"), 2);
+
+		std::fs::write(&path, "mask: true\n").unwrap();
+		let err = load_profile(path.to_str().unwrap()).err().unwrap();
+		std::fs::remove_dir_all(&dir).unwrap();
+		assert!(
+			err.message().contains("must be a nonnegative integer"),
+			"{err:?}"
+		);
+	}
+
+	#[test]
+	fn stable_output() {
+		let text = "data:\n- strip: [[0, 0, 0], [1, 0, 0], [0, 1, 0], [1, 1, 0]]\n  id: 4\n  comment: \
+		            first\n- instance:\n    sphere: [0, 0, 0]\n    radius: 1\n  translate: [2, 0, 0]\n  \
+		            mask: 7";
+		let compile = || {
+			let docs = crate::load::load_from_str(text).unwrap();
+			let mut scene = crate::ir::to_ir(&docs[0]).unwrap();
+			let args = args(&["--format", "bvh", "--comments"]);
+			let mut reporter = Reporter::new();
+			crate::transform::transform(&mut scene, &args, true, &mut reporter).unwrap();
+			to_bvh(&scene, &args, &mut reporter)
+		};
+		let first = compile();
+		for _ in 0..8 {
+			assert_eq!(compile(), first);
+		}
+	}
+
+	#[test]
+	fn trs_components() {
+		let text = "instance:\n  strip: [[0, 0, 0], [1, 0, 0], [0, 1, 0]]\nscale: [2, 2, 2]\nrotate: [0, 90, 0]";
+		let docs = crate::load::load_from_str(text).unwrap();
+		let mut scene = crate::ir::to_ir(&docs[0]).unwrap();
+		let args = args(&["--format", "bvh", "--root", "--bvh-trs"]);
+		let mut reporter = Reporter::new();
+		crate::transform::transform(&mut scene, &args, true, &mut reporter).unwrap();
+		let lines = to_bvh(&scene, &args, &mut reporter);
+		assert!(
+			lines
+				.iter()
+				.any(|line| line == "\t\t\t\"scale\" : [ 2, 2, 2 ],")
+		);
+		assert!(
+			lines
+				.iter()
+				.any(|line| line == "\t\t\t\"rotate\" : [ 0, 90, 0 ],")
+		);
+		// The matrix is still written, so the output can be read back
+		assert!(
+			lines
+				.iter()
+				.any(|line| line == "\t\t\t\"world_to_obj\" : [")
+		);
+		let read = from_bvh(&lines.join("\n")).unwrap();
+		assert_eq!(read.instances.len(), 1);
+	}
+
+	#[test]
+	fn structure_stats() {
+		let text = "data:\n- data:\n  - strip: [[0, 0, 0], [1, 0, 0], [0, 1, 0]]\n  - strip: [[0, 0, \
+		            1], [1, 0, 1], [0, 1, 1]]\n- instance:\n    strip: [[0, 0, 0], [1, 0, 0], [0, 1, 0]]\n  \
+		            translate: [0, 0, 2]";
+		let docs = crate::load::load_from_str(text).unwrap();
+		let mut scene = crate::ir::to_ir(&docs[0]).unwrap();
+		let args = args(&["--format", "bvh", "--bvh-stats"]);
+		let mut reporter = Reporter::new();
+		crate::transform::transform(&mut scene, &args, true, &mut reporter).unwrap();
+		to_bvh(&scene, &args, &mut reporter);
+		assert_eq!(
+			reporter.infos(),
+			vec![
+				"bvh depth: 3",
+				"bvh box nodes: 2",
+				"bvh instance nodes: 1",
+				"bvh triangle nodes: 3",
+				"bvh procedural nodes: 0",
+				"bvh leaf box occupancy: 2",
+				"bvh internal box surface area: 10",
+			]
+		);
+	}
+
+	#[test]
+	fn comment_escaped() {
+		let text = "strip: [[0, 0, 0], [1, 0, 0], [0, 1, 0]]\ncomment: \"the \\\"hero\\\" asset\"";
+		let docs = crate::load::load_from_str(text).unwrap();
+		let mut scene = crate::ir::to_ir(&docs[0]).unwrap();
+		let args = args(&["--format", "bvh", "--comments", "--root"]);
+		crate::transform::transform(&mut scene, &args, true, &mut Reporter::new()).unwrap();
+		let lines = to_bvh(&scene, &args, &mut Reporter::new());
+		assert!(lines.contains(&"\t\t\t\"comment\" : \"the \\\"hero\\\" asset\",".to_string()));
+	}
+
+	#[test]
+	fn validate_corrupted_index() {
+		let json = include_str!("../examples/combination/pyramid/out.json");
+		assert_eq!(validate_indices(json), Ok(()));
+		let corrupted = json.replacen("[ 2, 0 ]", "[ 2, 9 ]", 1);
+		let err = validate_indices(&corrupted).unwrap_err();
+		assert!(err.contains("[ 2, 9 ]"), "{err}");
+	}
+
+	#[test]
+	fn reference_not_index() {
+		let json = include_str!("../examples/combination/pyramid/out.json");
+		for reference in ["[ 2, -1 ]", "[ 2, 0.5 ]", "[ -0.5, 0 ]", "[ 2, 1e300 ]"] {
+			let corrupted = json.replacen("[ 2, 0 ]", reference, 1);
+			let err = from_bvh(&corrupted).err().unwrap();
+			assert!(
+				err.ends_with("to hold nonnegative integers in BVH input!"),
+				"{err}"
+			);
+			assert!(validate_indices(&corrupted).is_err());
+		}
+	}
+
+	#[test]
+	fn round_trip_empty() {
+		round_trip(&format!(
+			"{{\n\t\"generator\" : \"scene-builder\",\n\t\"version\" : \"{}\"\n}}\n",
+			env!("CARGO_PKG_VERSION")
+		));
+	}
+}
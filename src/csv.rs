@@ -0,0 +1,95 @@
+use crate::args::Args;
+use crate::ir::{Node, Scene, SquareMat, fmt_float, homogenize, homogenize_pt, is_hidden};
+use crate::obj::object_name;
+use crate::report::Reporter;
+
+/// Quote the text for use as a CSV field if it holds any character which would break the row
+fn csv_field(text: &str) -> String {
+	if text.contains([',', '"', '\n']) {
+		format!("\"{}\"", text.replace('"', "\"\""))
+	} else {
+		text.to_string()
+	}
+}
+
+fn handle_node(
+	node: &Node,
+	rows: &mut Vec<String>,
+	scene: &Scene,
+	args: &Args,
+	transform: &SquareMat,
+	reporter: &mut Reporter,
+) {
+	if is_hidden(scene, node) {
+		return;
+	}
+	match node {
+		Node::Strip(idx) => {
+			let strip = &scene.strips[*idx];
+			let name = object_name(&strip.fields, scene, "strip", *idx, reporter);
+			let name = csv_field(&name);
+			for (i, tri) in strip.triangles().iter().enumerate() {
+				for vert in tri.iter() {
+					let point = transform * homogenize_pt(vert);
+					rows.push(format!(
+						"{name},{i},{},{},{}",
+						fmt_float(point.x, args.precision),
+						fmt_float(point.y, args.precision),
+						fmt_float(point.z, args.precision)
+					));
+				}
+			}
+		},
+		Node::Instance(idx) => {
+			let instance = &scene.instances[*idx];
+			let inner = transform * homogenize(&instance.obj_to_world());
+			handle_node(&instance.affected, rows, scene, args, &inner, reporter);
+		},
+		Node::Mapping(idx) => {
+			if let Some(Node::Sequence(seq)) = scene.mappings[*idx].fields.get("data") {
+				for child in scene.sequences[*seq].vals.iter() {
+					handle_node(child, rows, scene, args, transform, reporter);
+				}
+			}
+		},
+		_ => {}, // Only triangles are listed, so rays, points, and spheres are skipped
+	}
+}
+
+/// List the world-space vertices of every triangle in the scene, one per row, with the name of the
+/// object holding the triangle and the triangle's position within it.
+pub fn to_csv(scene: &Scene, args: &Args, reporter: &mut Reporter) -> Vec<String> {
+	let mut rows = vec!["object,tri_index,vx,vy,vz".to_string()];
+	handle_node(
+		&scene.world,
+		&mut rows,
+		scene,
+		args,
+		&SquareMat::identity(),
+		reporter,
+	);
+	rows
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn row_per_vertex() {
+		use clap::Parser;
+		let args = Args::parse_from(["scene-builder", "in.yaml", "-f", "csv"]);
+		let docs = crate::load::load_from_str(
+			"data:\n- strip: [[0, 0, 0], [1, 0, 0], [0, 1, 0], [1, 1, 0]]\n  name: a,b\n- instance:\n    \
+			 strip: [[0, 0, 0], [1, 0, 0], [1, 1, 0], [0, 1, 0]]\n    topology: polygon\n  translate: \
+			 [0, 0, 2]\n- origin: [0, 0, 0]\n  direction: [1, 0, 0]\n  max: 1",
+		)
+		.unwrap();
+		let scene = crate::ir::to_ir(&docs[0]).unwrap();
+		let rows = to_csv(&scene, &args, &mut Reporter::new());
+		// Both strips hold two triangles
+		assert_eq!(rows.len(), 1 + 3 * 4);
+		assert_eq!(rows[4], "\"a,b\",1,0,1,0");
+		assert_eq!(rows[7], "strip1,0,0,0,2");
+	}
+}
@@ -0,0 +1,134 @@
+use std::collections::HashSet;
+
+use crate::ir::{Fields, Node, Scene, sorted_fields};
+use crate::report::json_string;
+
+/// The fields of the node, if it is an object which may have any
+fn node_fields<'a>(node: &Node, scene: &'a Scene) -> Option<&'a Fields> {
+	match node {
+		Node::Strip(idx) => Some(&scene.strips[*idx].fields),
+		Node::Point(idx) => Some(&scene.points[*idx].fields),
+		Node::Ray(idx) => Some(&scene.rays[*idx].fields),
+		Node::Line(idx) => Some(&scene.lines[*idx].fields),
+		Node::Sphere(idx) => Some(&scene.spheres[*idx].fields),
+		Node::Instance(idx) => Some(&scene.instances[*idx].fields),
+		Node::Mapping(idx) => Some(&scene.mappings[*idx].fields),
+		_ => None,
+	}
+}
+
+/// The label of the graph node for `node`: its kind and index, followed by its name if it has one
+fn label(node: &Node, scene: &Scene) -> String {
+	let (kind, idx) = match node {
+		Node::Sequence(idx) => ("sequence", *idx),
+		Node::Strip(idx) => ("strip", *idx),
+		Node::Point(idx) => ("point", *idx),
+		Node::Ray(idx) => ("ray", *idx),
+		Node::Line(idx) => ("line", *idx),
+		Node::Sphere(idx) => ("sphere", *idx),
+		Node::Instance(idx) => ("instance", *idx),
+		Node::Array(idx) => ("array", *idx),
+		Node::Mapping(idx) => {
+			let map = &scene.mappings[*idx];
+			if !map.is_box {
+				("mapping", *idx)
+			} else if map.fields.contains_key("min") {
+				("procedural", *idx)
+			} else {
+				("box", *idx)
+			}
+		},
+		_ => unreachable!("Only linked values are given graph nodes!"),
+	};
+	match node_fields(node, scene).and_then(|fields| fields.get("name")) {
+		Some(Node::String(str_at)) => format!("{kind} {idx}\n{}", scene.strings[*str_at]),
+		_ => format!("{kind} {idx}"),
+	}
+}
+
+/// Add the graph node for `node` (if it is linked and not yet added), followed by those of its
+/// children with an edge to each.
+fn handle_node(node: &Node, lines: &mut Vec<String>, scene: &Scene, visited: &mut HashSet<String>) {
+	// Linked values are identified by their display in the IR, which is also their graph name
+	if matches!(node, Node::Number(_) | Node::Bool(_) | Node::String(_))
+		|| !visited.insert(node.to_string())
+	{
+		return;
+	}
+	lines.push(format!(
+		"\t{node} [label={}];",
+		json_string(&label(node, scene))
+	));
+
+	let mut children: Vec<(String, &Node)> = vec![];
+	match node {
+		Node::Sequence(idx) => {
+			for (i, val) in scene.sequences[*idx].vals.iter().enumerate() {
+				children.push((i.to_string(), val));
+			}
+		},
+		Node::Instance(idx) => {
+			children.push(("affected".to_string(), &scene.instances[*idx].affected))
+		},
+		_ => {},
+	}
+	if let Some(fields) = node_fields(node, scene) {
+		for (name, val) in sorted_fields(fields) {
+			children.push((name.to_string(), val));
+		}
+	}
+	for (name, child) in children {
+		if matches!(child, Node::Number(_) | Node::Bool(_) | Node::String(_)) {
+			continue;
+		}
+		lines.push(format!(
+			"\t{node} -> {child} [label={}];",
+			json_string(&name)
+		));
+		handle_node(child, lines, scene, visited);
+	}
+}
+
+/// Describe the scene as a Graphviz directed graph, with a node for each object and sequence
+/// reachable from the world and an edge for each reference one makes to another. Edges are labeled
+/// by the field (or position in a sequence) which makes the reference, so a node referenced more
+/// than once has an edge for each reference.
+pub fn to_dot(scene: &Scene) -> Vec<String> {
+	let mut lines = vec!["digraph scene {".to_string()];
+	handle_node(&scene.world, &mut lines, scene, &mut HashSet::new());
+	lines.push("}".to_string());
+	lines
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn shared_strip() {
+		let docs = crate::load::load_from_str(
+			"data:\n- &tri\n  strip: [[0, 0, 0], [1, 0, 0], [0, 1, 0]]\n  name: tri\n- instance: \
+			 *tri\n  translate: [0, 0, 1]",
+		)
+		.unwrap();
+		let scene = crate::ir::to_ir(&docs[0]).unwrap();
+		let lines = to_dot(&scene);
+		assert_eq!(lines.first().unwrap(), "digraph scene {");
+		assert!(
+			lines.contains(&"\tStrip0 [label=\"strip 0\\ntri\"];".to_string()),
+			"{lines:?}"
+		);
+		let incoming: Vec<&String> = lines
+			.iter()
+			.filter(|line| line.contains("-> Strip0 "))
+			.collect();
+		assert_eq!(incoming.len(), 2, "{lines:?}");
+		assert_eq!(
+			lines
+				.iter()
+				.filter(|line| line.starts_with("\tStrip0 ["))
+				.count(),
+			1
+		);
+	}
+}
@@ -0,0 +1,60 @@
+// Decompression of gzip files (RFC 1952), which may hold several members one after another.
+
+use std::io::Read;
+
+use flate2::read::MultiGzDecoder;
+
+/// The most bytes which gzip data may decompress to. Data which expands beyond this is refused
+/// rather than exhausting memory.
+const MAX_SIZE: u64 = 1 << 30;
+
+/// Whether the data begins with the gzip magic header
+pub fn is_gzip(data: &[u8]) -> bool {
+	data.starts_with(&[0x1f, 0x8b])
+}
+
+/// Decompress gzip data, which may hold several members one after another.
+pub fn decompress(data: &[u8]) -> Result<Vec<u8>, String> {
+	decompress_at_most(data, MAX_SIZE)
+}
+
+fn decompress_at_most(data: &[u8], limit: u64) -> Result<Vec<u8>, String> {
+	let mut out = vec![];
+	// Read one byte past the limit to tell data which reaches it from data which exceeds it
+	let mut decoder = MultiGzDecoder::new(data).take(limit + 1);
+	if let Err(e) = decoder.read_to_end(&mut out) {
+		return Err(format!("Data is not valid gzip: {e}!"));
+	}
+	if out.len() as u64 > limit {
+		return Err(format!(
+			"Decompressed data exceeds the limit of {limit} bytes!"
+		));
+	}
+	Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn stored_and_fixed_blocks() {
+		// "hi" in a stored block, then "hello hello" with fixed codes, as two members
+		let data = [
+			0x1f, 0x8b, 8, 0, 0, 0, 0, 0, 4, 3, 1, 2, 0, 0xfd, 0xff, 0x68, 0x69, 0xac, 0x2a, 0x93,
+			0xd8, 2, 0, 0, 0, 0x1f, 0x8b, 8, 0, 0, 0, 0, 0, 2, 3, 0xcb, 0x48, 0xcd, 0xc9, 0xc9,
+			0x57, 0xc8, 0, 0x91, 0, 0x40, 0xa6, 0x2d, 1, 0xb, 0, 0, 0,
+		];
+		assert_eq!(decompress(&data).unwrap(), b"hihello hello");
+		assert!(decompress(&data[..20]).is_err());
+
+		// A bad checksum is refused
+		let mut corrupt = data;
+		corrupt[19] ^= 1;
+		assert!(decompress(&corrupt).is_err());
+
+		assert_eq!(decompress_at_most(&data, 13).unwrap(), b"hihello hello");
+		let err = decompress_at_most(&data, 12).err().unwrap();
+		assert!(err.contains("exceeds the limit of 12 bytes"), "{err}");
+	}
+}
@@ -1,35 +1,134 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
 
-#[derive(Copy, Clone, PartialEq)]
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub enum Node {
 	// literal values
 	Number(f64),
 	Bool(bool),
+	String(usize),
 	// link to some other value held by the scene
 	Sequence(usize),
 	Strip(usize),
 	Point(usize),
 	Ray(usize),
+	Line(usize),
+	Sphere(usize),
 	Instance(usize),
+	Array(usize),
 	Mapping(usize),
 }
 
+/// The fields of an object by name. Names are interned by the scene, since the same few are used by
+/// most objects.
+pub type Fields = HashMap<Name, Node>;
+
+/// The name of a field. Names from the scene's text share the scene's single copy of the name with
+/// every other object using it, and the names which the compiler itself gives need no copy at all.
+#[derive(Clone, Debug)]
+pub struct Name(NameRepr);
+
+#[derive(Clone, Debug)]
+enum NameRepr {
+	Static(&'static str),
+	Shared(Rc<str>),
+}
+
+impl Name {
+	pub fn as_str(&self) -> &str {
+		match &self.0 {
+			NameRepr::Static(name) => name,
+			NameRepr::Shared(name) => name,
+		}
+	}
+}
+
+impl From<&'static str> for Name {
+	fn from(name: &'static str) -> Name {
+		Name(NameRepr::Static(name))
+	}
+}
+
+impl PartialEq for Name {
+	fn eq(&self, other: &Name) -> bool {
+		self.as_str() == other.as_str()
+	}
+}
+
+impl Eq for Name {}
+
+impl std::hash::Hash for Name {
+	fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+		// Must hash as the str does, since maps of names are searched by str
+		self.as_str().hash(state);
+	}
+}
+
+impl PartialOrd for Name {
+	fn partial_cmp(&self, other: &Name) -> Option<std::cmp::Ordering> {
+		Some(self.cmp(other))
+	}
+}
+
+impl Ord for Name {
+	fn cmp(&self, other: &Name) -> std::cmp::Ordering {
+		self.as_str().cmp(other.as_str())
+	}
+}
+
+impl std::ops::Deref for Name {
+	type Target = str;
+	fn deref(&self) -> &str {
+		self.as_str()
+	}
+}
+
+impl std::borrow::Borrow<str> for Name {
+	fn borrow(&self) -> &str {
+		self.as_str()
+	}
+}
+
+impl std::fmt::Display for Name {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		f.write_str(self.as_str())
+	}
+}
+
+impl PartialEq<str> for Name {
+	fn eq(&self, other: &str) -> bool {
+		self.as_str() == other
+	}
+}
+
+impl PartialEq<&str> for Name {
+	fn eq(&self, other: &&str) -> bool {
+		self.as_str() == *other
+	}
+}
+
 use std::fmt;
 impl fmt::Display for Node {
 	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
 		match self {
 			Node::Number(v) => write!(f, "{}", v),
 			Node::Bool(v) => write!(f, "{}", v),
+			Node::String(i) => write!(f, "String{}", i),
 			Node::Sequence(i) => write!(f, "Sequence{}", i),
 			Node::Strip(i) => write!(f, "Strip{}", i),
 			Node::Point(i) => write!(f, "Point{}", i),
 			Node::Ray(i) => write!(f, "Ray{}", i),
+			Node::Line(i) => write!(f, "Line{}", i),
+			Node::Sphere(i) => write!(f, "Sphere{}", i),
 			Node::Instance(i) => write!(f, "Instance{}", i),
+			Node::Array(i) => write!(f, "Array{}", i),
 			Node::Mapping(i) => write!(f, "Mapping{}", i),
 		}
 	}
 }
 
+#[derive(Clone)]
 pub struct Sequence {
 	pub vals: Vec<Node>,
 }
@@ -44,9 +143,10 @@ pub fn new_point(val: f64) -> Point3D {
 	Point3D::new(val, val, val)
 }
 
+#[derive(Clone)]
 pub struct Point {
 	pub loc: Point3D,
-	pub fields: HashMap<String, Node>,
+	pub fields: Fields,
 }
 impl Point {
 	pub fn new() -> Point {
@@ -57,31 +157,152 @@ impl Point {
 	}
 }
 
+#[derive(Copy, Clone, PartialEq)]
+pub enum Topology {
+	/// Each set of three adjacent vertices forms a triangle
+	TriStrip,
+	/// Each consecutive set of four vertices forms a quadrilateral
+	Quad,
+	/// All vertices together form a single convex polygon
+	Polygon,
+	/// Each consecutive set of three vertices forms an independent triangle
+	TriList,
+	/// Every triangle shares the first vertex, joined with each adjacent pair of those after it
+	TriFan,
+}
+
+impl Topology {
+	pub fn to_str(self) -> &'static str {
+		match self {
+			Self::TriStrip => "tri_strip",
+			Self::Quad => "quad",
+			Self::Polygon => "polygon",
+			Self::TriList => "triangles",
+			Self::TriFan => "tri_fan",
+		}
+	}
+
+	pub fn from_str(name: &str) -> Option<Topology> {
+		match name {
+			"tri_strip" => Some(Self::TriStrip),
+			"quad" => Some(Self::Quad),
+			"polygon" => Some(Self::Polygon),
+			"tri_fan" => Some(Self::TriFan),
+			_ => None,
+		}
+	}
+}
+
+#[derive(Clone)]
 pub struct Strip {
 	pub vals: Vec<Point3D>,
-	pub fields: HashMap<String, Node>,
+	pub topology: Topology,
+	/// The index of the first vertex of each run after the first, ascending. Each run is
+	/// disconnected from the others, as if it were a strip of its own.
+	pub restarts: Vec<usize>,
+	pub fields: Fields,
 }
 impl Strip {
 	pub fn new() -> Strip {
 		Strip {
 			vals: vec![],
+			topology: Topology::TriStrip,
+			restarts: vec![],
 			fields: HashMap::new(),
 		}
 	}
+
+	/// The vertices of each run of the strip, in order
+	pub fn runs(&self) -> Vec<&[Point3D]> {
+		let mut runs = vec![];
+		let mut start = 0;
+		for end in self.restarts.iter().chain([&self.vals.len()]) {
+			runs.push(&self.vals[start..*end]);
+			start = *end;
+		}
+		runs
+	}
+
+	/// Decompose the strip into its component triangles, each wound consistently with the first of
+	/// its run.
+	pub fn triangles(&self) -> Vec<[Point3D; 3]> {
+		self.triangle_indices()
+			.iter()
+			.map(|tri| tri.map(|i| self.vals[i]))
+			.collect()
+	}
+
+	/// The indices of the vertices of each triangle, as in `triangles`
+	pub fn triangle_indices(&self) -> Vec<[usize; 3]> {
+		let mut tris = vec![];
+		let mut start = 0;
+		for end in self.restarts.iter().chain([&self.vals.len()]) {
+			self.run_triangles(start, *end, &mut tris);
+			start = *end;
+		}
+		tris
+	}
+
+	/// The triangles of the run of vertices from `start` up to `end`
+	fn run_triangles(&self, start: usize, end: usize, tris: &mut Vec<[usize; 3]>) {
+		match self.topology {
+			Topology::TriStrip => {
+				for i in start + 2..end {
+					if (i - start).is_multiple_of(2) {
+						tris.push([i - 2, i - 1, i]);
+					} else {
+						tris.push([i - 1, i - 2, i]);
+					}
+				}
+			},
+			Topology::Quad => {
+				for quad in (start..end).step_by(4).filter(|i| i + 4 <= end) {
+					tris.push([quad, quad + 1, quad + 2]);
+					tris.push([quad, quad + 2, quad + 3]);
+				}
+			},
+			Topology::Polygon | Topology::TriFan => {
+				for i in start + 2..end {
+					tris.push([start, i - 1, i]);
+				}
+			},
+			Topology::TriList => {
+				for tri in (start..end).step_by(3).filter(|i| i + 3 <= end) {
+					tris.push([tri, tri + 1, tri + 2]);
+				}
+			},
+		}
+	}
 }
 
+#[derive(Clone)]
 pub struct Ray {
 	pub origin: Point3D,
 	pub direction: Point3D,
 	pub extent: f64,
 	pub min: f64,
-	pub fields: HashMap<String, Node>,
+	pub fields: Fields,
+}
+
+/// A polyline through a sequence of points, drawn for debugging (and not present in BVH output)
+#[derive(Clone)]
+pub struct Line {
+	pub vals: Vec<Point3D>,
+	pub fields: Fields,
+}
+
+#[derive(Clone)]
+pub struct Sphere {
+	pub center: Point3D,
+	pub radius: f64,
+	pub fields: Fields,
 }
 
 pub type TransformMat = nalgebra::Matrix3x4<f64>;
 pub type SquareMat = nalgebra::Matrix4<f64>;
 pub type HomoPoint = nalgebra::Vector4<f64>;
 
+#[derive(Clone)]
 pub struct Instance {
 	pub affected: Node,
 	/// The scale factor of x, y, z axes. 1.0 is no scaling.
@@ -89,10 +310,21 @@ pub struct Instance {
 	/// the rotation in x, y, z axes. In degrees.
 	pub rotate: Point3D,
 	pub translate: Point3D,
-	pub fields: HashMap<String, Node>,
+	/// A world-to-object transform given directly (such as when read back from BVH output). If
+	/// present, it takes precedence over the scale, rotate, and translate components.
+	pub baked: Option<TransformMat>,
+	pub fields: Fields,
+	/// The bounds of the affected (once transformed) as last set, if still valid
+	pub bounds: Option<(Point3D, Point3D)>,
 }
 impl Instance {
 	pub fn obj_to_world(&self) -> TransformMat {
+		if let Some(baked) = &self.baked {
+			return match homogenize(baked).try_inverse() {
+				Some(inverse) => inverse.fixed_view::<3, 4>(0, 0).into_owned(),
+				None => TransformMat::from_element(f64::NAN),
+			};
+		}
 		let scale_mat = matrix![
 			self.scale.x, 0.0, 0.0;
 			0.0, self.scale.y, 0.0;
@@ -129,6 +361,9 @@ impl Instance {
 	}
 
 	pub fn world_to_obj(&self) -> TransformMat {
+		if let Some(baked) = &self.baked {
+			return *baked;
+		}
 		let scale_mat = matrix![
 			1.0 / self.scale.x, 0.0, 0.0;
 			0.0, 1.0 / self.scale.y, 0.0;
@@ -166,6 +401,19 @@ impl Instance {
 	}
 }
 
+/// A grid of copies of some object. The copy at grid position (i, j, k) is translated by i steps in x,
+/// j steps in y, and k steps in z. Each step along an axis likewise adds that axis's component of
+/// `rotate` and multiplies by that axis's component of `scale`.
+#[derive(Clone)]
+pub struct Array {
+	pub affected: Node,
+	pub count: [usize; 3],
+	pub scale: Point3D,
+	pub rotate: Point3D,
+	pub translate: Point3D,
+	pub fields: Fields,
+}
+
 pub fn homogenize(m: &TransformMat) -> SquareMat {
 	matrix![
 		m[(0, 0)], m[(0, 1)], m[(0, 2)], m[(0, 3)];
@@ -179,11 +427,15 @@ pub fn homogenize_pt(p: &Point3D) -> HomoPoint {
 	HomoPoint::new(p.x, p.y, p.z, 1.0)
 }
 
+#[derive(Clone)]
 pub struct Mapping {
-	pub fields: HashMap<String, Node>,
+	pub fields: Fields,
 	pub is_box: bool,
 	pub min: Point3D,
 	pub max: Point3D,
+	/// The bounds as last set (which, unlike `min` and `max`, are kept whether or not this is a
+	/// box), if still valid
+	pub bounds: Option<(Point3D, Point3D)>,
 }
 impl Mapping {
 	pub fn new() -> Mapping {
@@ -192,6 +444,7 @@ impl Mapping {
 			is_box: false,
 			min: new_point(0.0),
 			max: new_point(0.0),
+			bounds: None,
 		}
 	}
 
@@ -202,14 +455,291 @@ impl Mapping {
 	}
 }
 
+#[derive(Clone)]
 pub struct Scene {
 	pub world: Node,
 	pub sequences: Vec<Sequence>,
 	pub strips: Vec<Strip>,
 	pub points: Vec<Point>,
 	pub rays: Vec<Ray>,
+	pub lines: Vec<Line>,
+	pub spheres: Vec<Sphere>,
 	pub instances: Vec<Instance>,
+	pub arrays: Vec<Array>,
 	pub mappings: Vec<Mapping>,
+	pub strings: Vec<String>,
+	/// The names which some value has referenced, which may be variables rather than fields
+	pub referenced: HashSet<Name>,
+	/// The values of fields in BVH output for objects which do not give them, read from --profile
+	pub profile: Fields,
+	/// The single copy of each name which some object's fields use
+	names: HashSet<Name>,
+}
+impl Scene {
+	pub fn new() -> Scene {
+		Scene {
+			world: Node::Bool(false),
+			sequences: vec![],
+			strips: vec![],
+			points: vec![],
+			rays: vec![],
+			lines: vec![],
+			spheres: vec![],
+			instances: vec![],
+			arrays: vec![],
+			mappings: vec![],
+			strings: vec![],
+			referenced: HashSet::new(),
+			profile: HashMap::new(),
+			names: HashSet::new(),
+		}
+	}
+
+	/// The scene's copy of the name, shared by every object using the name
+	pub fn intern(&mut self, name: &str) -> Name {
+		match self.names.get(name) {
+			Some(interned) => interned.clone(),
+			None => {
+				let interned = Name(NameRepr::Shared(Rc::from(name)));
+				self.names.insert(interned.clone());
+				interned
+			},
+		}
+	}
+
+	/// The number of triangles in the strips reachable from the world, as decomposed by each strip's
+	/// topology
+	#[cfg_attr(not(test), allow(dead_code))]
+	pub fn triangle_count(&self) -> usize {
+		crate::stats::reachable(self).triangles
+	}
+
+	/// The number of instances reachable from the world
+	#[cfg_attr(not(test), allow(dead_code))]
+	pub fn instance_count(&self) -> usize {
+		crate::stats::reachable(self).instances
+	}
+
+	/// The number of boxes (not procedurals) reachable from the world. Only meaningful once bounds
+	/// have been set, since that is what marks mappings as boxes.
+	#[cfg_attr(not(test), allow(dead_code))]
+	pub fn box_count(&self) -> usize {
+		crate::stats::reachable(self).boxes
+	}
+
+	/// The bounds of the world, set (marking boxes as a side effect) only if not already known. Each
+	/// component is NaN if nothing in the world has bounds.
+	pub fn world_bounds(&mut self, bounding: &crate::transform::Bounding) -> (Point3D, Point3D) {
+		let world = self.world;
+		world.bounds(self, bounding)
+	}
+
+	/// Forget the bounds of all mappings and instances, as must be done after any change to the
+	/// geometry they hold.
+	pub fn invalidate_bounds(&mut self) {
+		for map in self.mappings.iter_mut() {
+			map.bounds = None;
+		}
+		for instance in self.instances.iter_mut() {
+			instance.bounds = None;
+		}
+	}
+}
+
+/// Format the number with the given count of significant digits, or with as many as are needed to
+/// represent it exactly if `None`. Trailing zeros are omitted either way.
+pub fn fmt_float(val: f64, precision: Option<u8>) -> String {
+	match precision {
+		Some(digits) if val.is_finite() => {
+			// Round by way of scientific notation, then print the rounded value in full
+			let rounded: f64 = format!("{:.*e}", digits as usize - 1, val).parse().unwrap();
+			rounded.to_string()
+		},
+		_ => val.to_string(),
+	}
+}
+
+/// Format a point as a sequence of its components
+fn fmt_point(pt: &Point3D) -> String {
+	format!("[{}, {}, {}]", pt.x, pt.y, pt.z)
+}
+
+/// The fields sorted by name. The order of a hash map differs between runs, so fields must be
+/// visited in this order wherever the order can affect the output (or which error is found first).
+pub fn sorted_fields(fields: &Fields) -> Vec<(&Name, &Node)> {
+	let mut sorted: Vec<(&Name, &Node)> = fields.iter().collect();
+	sorted.sort_by_key(|(name, _)| *name);
+	sorted
+}
+
+/// Write the fields of an object (sorted by name) in the form " {name: value, ...}", if any.
+fn fmt_fields(f: &mut fmt::Formatter, fields: &Fields) -> fmt::Result {
+	if fields.is_empty() {
+		return Ok(());
+	}
+	let entries: Vec<String> = sorted_fields(fields)
+		.iter()
+		.map(|(name, val)| format!("{name}: {val}"))
+		.collect();
+	write!(f, " {{{}}}", entries.join(", "))
+}
+
+impl fmt::Display for Sequence {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		let vals: Vec<String> = self.vals.iter().map(|val| val.to_string()).collect();
+		write!(f, "[{}]", vals.join(", "))
+	}
+}
+
+impl fmt::Display for Strip {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		let runs: Vec<String> = self
+			.runs()
+			.iter()
+			.map(|run| {
+				let vals: Vec<String> = run.iter().map(fmt_point).collect();
+				format!("[{}]", vals.join(", "))
+			})
+			.collect();
+		write!(f, "{} {}", self.topology.to_str(), runs.join(" "))?;
+		fmt_fields(f, &self.fields)
+	}
+}
+
+impl fmt::Display for Point {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{}", fmt_point(&self.loc))?;
+		fmt_fields(f, &self.fields)
+	}
+}
+
+impl fmt::Display for Ray {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(
+			f,
+			"origin {} direction {} min {} max {}",
+			fmt_point(&self.origin),
+			fmt_point(&self.direction),
+			self.min,
+			self.extent
+		)?;
+		fmt_fields(f, &self.fields)
+	}
+}
+
+impl fmt::Display for Line {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		let vals: Vec<String> = self.vals.iter().map(fmt_point).collect();
+		write!(f, "[{}]", vals.join(", "))?;
+		fmt_fields(f, &self.fields)
+	}
+}
+
+impl fmt::Display for Sphere {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(
+			f,
+			"center {} radius {}",
+			fmt_point(&self.center),
+			self.radius
+		)?;
+		fmt_fields(f, &self.fields)
+	}
+}
+
+impl fmt::Display for Instance {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "of {}", self.affected)?;
+		match &self.baked {
+			Some(baked) => {
+				let rows: Vec<String> = (0..3)
+					.map(|r| {
+						let row: Vec<String> = (0..4).map(|c| baked[(r, c)].to_string()).collect();
+						format!("[{}]", row.join(", "))
+					})
+					.collect();
+				write!(f, " world_to_obj [{}]", rows.join(", "))?;
+			},
+			None => write!(
+				f,
+				" scale {} rotate {} translate {}",
+				fmt_point(&self.scale),
+				fmt_point(&self.rotate),
+				fmt_point(&self.translate)
+			)?,
+		}
+		fmt_fields(f, &self.fields)
+	}
+}
+
+impl fmt::Display for Array {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(
+			f,
+			"of {} count [{}, {}, {}] scale {} rotate {} translate {}",
+			self.affected,
+			self.count[0],
+			self.count[1],
+			self.count[2],
+			fmt_point(&self.scale),
+			fmt_point(&self.rotate),
+			fmt_point(&self.translate)
+		)?;
+		fmt_fields(f, &self.fields)
+	}
+}
+
+impl fmt::Display for Mapping {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		if self.is_box {
+			let kind = if self.fields.contains_key("min") {
+				"procedural"
+			} else {
+				"box"
+			};
+			write!(
+				f,
+				"{kind} {} to {}",
+				fmt_point(&self.min),
+				fmt_point(&self.max)
+			)?;
+		} else {
+			write!(f, "mapping")?;
+		}
+		fmt_fields(f, &self.fields)
+	}
+}
+
+impl fmt::Display for Scene {
+	/// Write the world node followed by every object held by the scene, one per line.
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		writeln!(f, "world: {}", self.world)?;
+		fn section<T: fmt::Display>(
+			f: &mut fmt::Formatter,
+			name: &str,
+			kind: &str,
+			items: &[T],
+		) -> fmt::Result {
+			if items.is_empty() {
+				return Ok(());
+			}
+			writeln!(f, "{name}:")?;
+			for (i, item) in items.iter().enumerate() {
+				writeln!(f, "\t{kind}{i}: {item}")?;
+			}
+			Ok(())
+		}
+		section(f, "sequences", "Sequence", &self.sequences)?;
+		section(f, "strips", "Strip", &self.strips)?;
+		section(f, "points", "Point", &self.points)?;
+		section(f, "rays", "Ray", &self.rays)?;
+		section(f, "lines", "Line", &self.lines)?;
+		section(f, "spheres", "Sphere", &self.spheres)?;
+		section(f, "instances", "Instance", &self.instances)?;
+		section(f, "arrays", "Array", &self.arrays)?;
+		section(f, "mappings", "Mapping", &self.mappings)?;
+		section(f, "strings", "String", &self.strings)
+	}
 }
 
 pub fn as_3d(scene: &Scene, node: &Node) -> Result<Point3D, String> {
@@ -243,6 +773,31 @@ pub fn as_3d(scene: &Scene, node: &Node) -> Result<Point3D, String> {
 	}
 }
 
+/// Like `as_3d`, but a single number is also accepted and broadcast to all three components.
+pub fn as_3d_broadcast(scene: &Scene, node: &Node) -> Result<Point3D, String> {
+	match node {
+		Node::Number(val) => Ok(new_point(*val)),
+		_ => as_3d(scene, node),
+	}
+}
+
+/// Like `as_3d_broadcast`, but every component must be far enough from zero that the scale can be
+/// inverted.
+fn as_scale(scene: &Scene, node: &Node) -> Result<Point3D, String> {
+	let scale = as_3d_broadcast(scene, node)?;
+	const MIN_SCALE: f64 = 1e-12;
+	for (i, axis) in ["x", "y", "z"].iter().enumerate() {
+		if scale[i].abs() < MIN_SCALE {
+			return Err(format!(
+				"Field `scale` cannot be zero (found {} on the {axis} axis), since then the \
+				 transform could not be inverted!",
+				scale[i]
+			));
+		}
+	}
+	Ok(scale)
+}
+
 fn resolve<'a>(namespace: &[usize], scene: &'a Scene, name: &str) -> Option<&'a Node> {
 	for idx in namespace.iter().rev() {
 		match scene.mappings[*idx].fields.get(name) {
@@ -253,25 +808,116 @@ fn resolve<'a>(namespace: &[usize], scene: &'a Scene, name: &str) -> Option<&'a
 	None
 }
 
-fn parse(input: &Yaml, namespace: &mut Vec<usize>, scene: &mut Scene) -> Result<Node, String> {
-	let ret = match input {
-		Yaml::Real(fp) => match fp.parse::<f64>() {
-			Ok(val) => Node::Number(val),
-			Err(_) => return Err(format!("Could not parse float number {fp}!")),
+/// Fields whose string values are taken literally rather than as references to other values.
+/// The fields which make a mapping some object other than a box or procedural
+const OBJECT_KEYS: &[&str] = &[
+	"array",
+	"data",
+	"instance",
+	"line",
+	"origin",
+	"point",
+	"sphere",
+	"strip",
+	"triangles",
+];
+const STRING_FIELDS: &[&str] = &["comment", "name", "texture", "topology"];
+/// Fields which take strings literally only if they begin with '#', since they are hex colors
+const COLOR_FIELDS: &[&str] = &["color", "color_override"];
+
+/// Prefix an error message with the location in the source it pertains to
+fn at(loc: &Location, msg: String) -> String {
+	format!("At {loc}: {msg}")
+}
+
+/// The state of parsing one document
+struct ParseContext {
+	/// The node already parsed for each YAML anchor, so that any alias of the anchor shares it
+	anchors: HashMap<usize, Node>,
+	/// The elements parsed so far of each sequence being parsed, innermost last. A back-reference
+	/// `$N` is element N of the innermost.
+	partial: Vec<Vec<Node>>,
+}
+
+/// The index named by a back-reference such as `$0`, if the text is one
+fn back_reference(text: &str) -> Option<usize> {
+	text.strip_prefix('$')
+		.filter(|digits| !digits.is_empty() && digits.bytes().all(|c| c.is_ascii_digit()))
+		.and_then(|digits| digits.parse().ok())
+}
+
+/// The earlier element of the innermost sequence being parsed which back-reference `name` names
+fn back_referenced(partial: &[Vec<Node>], name: &str) -> Result<Node, String> {
+	let Some(earlier) = partial.last() else {
+		return Err(format!(
+			"Back-reference \"{name}\" is not within a sequence!"
+		));
+	};
+	match back_reference(name).and_then(|idx| earlier.get(idx)) {
+		Some(node) => Ok(*node),
+		None => Err(format!(
+			"Back-reference \"{name}\" must name an earlier element of its sequence, but only {} \
+			 precede it!",
+			earlier.len()
+		)),
+	}
+}
+
+/// Convert the input value into a node of the scene.
+fn parse(
+	input: &Marked,
+	namespace: &mut Vec<usize>,
+	scene: &mut Scene,
+	ctx: &mut ParseContext,
+) -> Result<Node, String> {
+	if input.anchor > 0
+		&& let Some(node) = ctx.anchors.get(&input.anchor)
+	{
+		return Ok(*node);
+	}
+	let ret = match &input.value {
+		Value::Real(fp) => match fp.parse::<f64>() {
+			Ok(val) if val.is_finite() => Node::Number(val),
+			parsed => {
+				// A literal too large for a float (such as 1e400) overflows to infinity. YAML spells
+				// the special floats as `.inf`, `-.inf`, and `.nan` (in any case).
+				let special = parsed.is_ok()
+					|| [".inf", ".nan"]
+						.iter()
+						.any(|special| fp.to_lowercase().ends_with(special));
+				let msg = if special {
+					format!("Number {fp} is not finite, which is not allowed!")
+				} else {
+					format!("Could not parse float number {fp}!")
+				};
+				return Err(at(&input.loc, msg));
+			},
 		},
-		Yaml::Integer(val) => Node::Number(*val as f64),
-		Yaml::String(name) => match resolve(namespace, scene, name) {
-			Some(found) => *found,
-			None => return Err(format!("Could not resolve reference \"{name}\"!")),
+		Value::Integer(val) => Node::Number(*val as f64),
+		Value::String(name) if back_reference(name).is_some() => {
+			back_referenced(&ctx.partial, name).map_err(|e| at(&input.loc, e))?
 		},
-		Yaml::Boolean(val) => Node::Bool(*val),
-		Yaml::Array(arr) => {
-			let mut nodes = vec![];
-
+		Value::String(name) => match resolve(namespace, scene, name).copied() {
+			Some(found) => {
+				let name = scene.intern(name);
+				scene.referenced.insert(name);
+				found
+			},
+			None => {
+				return Err(at(
+					&input.loc,
+					format!("Could not resolve reference \"{name}\"!"),
+				));
+			},
+		},
+		Value::Boolean(val) => Node::Bool(*val),
+		Value::Array(arr) => {
+			ctx.partial.push(vec![]);
 			for element in arr {
-				let node = parse(element, namespace, scene)?;
-				nodes.push(node);
+				let node = parse(element, namespace, scene, ctx)?;
+				ctx.partial.last_mut().unwrap().push(node);
 			}
+			let nodes = ctx.partial.pop().unwrap();
 
 			let seq_at = scene.sequences.len();
 			scene.sequences.push(Sequence::new());
@@ -280,19 +926,98 @@ fn parse(input: &Yaml, namespace: &mut Vec<usize>, scene: &mut Scene) -> Result<
 			}
 			Node::Sequence(seq_at)
 		},
-		Yaml::Hash(map) => {
+		Value::Hash(map) => {
 			let name_at = scene.mappings.len();
 			scene.mappings.push(Mapping::new());
 			namespace.push(name_at);
+			// Save where each field's value appeared so that errors can point to it
+			let mut locs = HashMap::new();
 			for (name, val) in map.iter() {
-				let name = match name {
-					Yaml::String(n) => n,
-					_ => return Err("Name in YAML field found to be non-string!".to_string()),
+				let name = match &name.value {
+					Value::String(n) => n,
+					_ => {
+						return Err(at(
+							&name.loc,
+							"Name in YAML field found to be non-string!".to_string(),
+						));
+					},
+				};
+				locs.insert(name.clone(), val.loc);
+				let node = match &val.value {
+					Value::String(text)
+						if STRING_FIELDS.contains(&name.as_str())
+							|| (COLOR_FIELDS.contains(&name.as_str()) && text.starts_with('#')) =>
+					{
+						let str_at = scene.strings.len();
+						scene.strings.push(text.clone());
+						Node::String(str_at)
+					},
+					_ => parse(val, namespace, scene, ctx)?,
 				};
-				let node = parse(val, namespace, scene)?;
-				scene.mappings[name_at].fields.insert(name.clone(), node);
+				let name = scene.intern(name);
+				scene.mappings[name_at].fields.insert(name, node);
 			}
 			namespace.pop();
+			let located = |key: &str, msg: String| at(locs.get(key).unwrap_or(&input.loc), msg);
+
+			if let Some(visible) = scene.mappings[name_at].fields.get("visible")
+				&& !matches!(visible, Node::Bool(_))
+			{
+				return Err(located(
+					"visible",
+					format!("Field `visible` must be a boolean! Got {visible} instead."),
+				));
+			}
+
+			// A procedural may be authored by its center and size, which we convert to the usual
+			// bounds. Any other kind of object is skipped, since there the names may be user
+			// variables.
+			let fields = &scene.mappings[name_at].fields;
+			let procedural = !OBJECT_KEYS.iter().any(|key| fields.contains_key(*key));
+			let extent_key = ["size", "half_extent"]
+				.into_iter()
+				.find(|key| procedural && fields.contains_key(*key));
+			if let (Some(center), Some(extent_key)) = (fields.get("center"), extent_key) {
+				if fields.contains_key("size") && fields.contains_key("half_extent") {
+					return Err(located(
+						"half_extent",
+						"Fields `size` and `half_extent` cannot be used together!".to_string(),
+					));
+				}
+				if let Some(bound) = ["min", "max"]
+					.into_iter()
+					.find(|key| fields.contains_key(*key))
+				{
+					return Err(located(
+						bound,
+						format!("Field `{bound}` cannot be used with `center` and `{extent_key}`!"),
+					));
+				}
+				let center = as_3d(scene, center).map_err(|e| located("center", e))?;
+				let mut half = as_3d_broadcast(scene, &fields[extent_key])
+					.map_err(|e| located(extent_key, e))?;
+				if half.iter().any(|val| *val < 0.0) {
+					return Err(located(
+						extent_key,
+						format!("Field `{extent_key}` cannot be negative!"),
+					));
+				}
+				if extent_key == "size" {
+					half /= 2.0;
+				}
+				for (bound, corner) in [("min", center - half), ("max", center + half)] {
+					let seq_at = scene.sequences.len();
+					let mut seq = Sequence::new();
+					seq.vals = corner.iter().map(|val| Node::Number(*val)).collect();
+					scene.sequences.push(seq);
+					scene.mappings[name_at]
+						.fields
+						.insert(bound.into(), Node::Sequence(seq_at));
+				}
+				for key in ["center", "size", "half_extent"] {
+					scene.mappings[name_at].fields.remove(key);
+				}
+			}
 
 			// Create the result from the top namespace. Recognize various types:
 			if let Some(node) = scene.mappings[name_at].fields.get("data") {
@@ -303,21 +1028,39 @@ fn parse(input: &Yaml, namespace: &mut Vec<usize>, scene: &mut Scene) -> Result<
 						for i in 0..seq.vals.len() {
 							match seq.vals[i] {
 								Node::Number(_) => {
-									return Err(format!(
-										"All elements in `data` must be objects, but a number was \
-										 found at index {i}!"
+									return Err(located(
+										"data",
+										format!(
+											"All elements in `data` must be objects, but a number was found at \
+											 index {i}!"
+										),
 									));
 								},
 								Node::Bool(_) => {
-									return Err(format!(
-										"All elements in `data` must be objects, but a boolean \
-										 was found at index {i}!"
+									return Err(located(
+										"data",
+										format!(
+											"All elements in `data` must be objects, but a boolean was found at \
+											 index {i}!"
+										),
+									));
+								},
+								Node::String(_) => {
+									return Err(located(
+										"data",
+										format!(
+											"All elements in `data` must be objects, but a string was found at \
+											 index {i}!"
+										),
 									));
 								},
 								Node::Sequence(_) => {
-									return Err(format!(
-										"All elements in `data` must be objects, but a sequence \
-										 was found at index {i}!"
+									return Err(located(
+										"data",
+										format!(
+											"All elements in `data` must be objects, but a sequence was found at \
+											 index {i}!"
+										),
 									));
 								},
 								_ => {},
@@ -325,40 +1068,150 @@ fn parse(input: &Yaml, namespace: &mut Vec<usize>, scene: &mut Scene) -> Result<
 						}
 					},
 					_ => {
-						return Err("Field `data` must be a sequence!".to_string());
+						return Err(located(
+							"data",
+							"Field `data` must be a sequence!".to_string(),
+						));
 					},
 				}
 				Node::Mapping(name_at)
-			} else if scene.mappings[name_at].fields.contains_key("strip") {
-				// This is not, in fact, a custom, it is a strip.
+			} else if scene.mappings[name_at].fields.contains_key("strip")
+				|| scene.mappings[name_at].fields.contains_key("triangles")
+			{
+				// This is not, in fact, a custom, it is a strip (or a list of triangles).
+				let is_list = scene.mappings[name_at].fields.contains_key("triangles");
+				if is_list && scene.mappings[name_at].fields.contains_key("strip") {
+					return Err(at(
+						&input.loc,
+						"Fields `strip` and `triangles` cannot be used together!".to_string(),
+					));
+				}
+				let vert_key = if is_list { "triangles" } else { "strip" };
 				let mut strip = Strip::new();
 
-				for (key, value) in scene.mappings[name_at].fields.iter() {
-					if key == "strip" {
+				for (key, value) in sorted_fields(&scene.mappings[name_at].fields) {
+					if key == vert_key {
 						match value {
 							Node::Sequence(idx) => {
-								let vertices = &scene.sequences[*idx];
-								let len = vertices.vals.len();
-								if len < 3 {
-									return Err(format!(
-										"The field `strip` must have a sequence with at least 3 \
-										 vertices, but only {len} were found!"
-									));
-								}
-								for vertex in vertices.vals.iter() {
-									strip.vals.push(as_3d(scene, vertex)?);
+								let vertices = &scene.sequences[*idx].vals;
+								// A strip may be given as a sequence of runs, each a sequence of
+								// vertices, which restart the strip between them
+								let is_run = |node: &Node| match node {
+									Node::Sequence(at) => {
+										matches!(
+											scene.sequences[*at].vals.first(),
+											Some(Node::Sequence(_))
+										)
+									},
+									_ => false,
+								};
+								if !is_list && vertices.first().is_some_and(is_run) {
+									for run in vertices.iter() {
+										let Node::Sequence(run_at) = run else {
+											return Err(located(
+												key,
+												format!(
+													"Every run in `{vert_key}` must be a sequence of points!"
+												),
+											));
+										};
+										if !strip.vals.is_empty() {
+											strip.restarts.push(strip.vals.len());
+										}
+										for vertex in scene.sequences[*run_at].vals.iter() {
+											strip.vals.push(
+												as_3d(scene, vertex)
+													.map_err(|e| located(key, e))?,
+											);
+										}
+									}
+								} else {
+									for vertex in vertices.iter() {
+										strip.vals.push(
+											as_3d(scene, vertex).map_err(|e| located(key, e))?,
+										);
+									}
 								}
 							},
 							_ => {
-								return Err("Field `data` must hold a sequence of at least 3 \
-								            points!"
-									.to_string());
+								return Err(located(
+									key,
+									format!(
+										"Field `{vert_key}` must hold a sequence of at least 3 \
+										 points!"
+									),
+								));
+							},
+						}
+					} else if key == "topology" {
+						if is_list {
+							return Err(located(
+								key,
+								"Field `topology` cannot be used with `triangles`!".to_string(),
+							));
+						}
+						let topology = match value {
+							Node::String(idx) => Topology::from_str(&scene.strings[*idx]),
+							_ => None,
+						};
+						match topology {
+							Some(topology) => strip.topology = topology,
+							None => {
+								return Err(located(
+									key,
+									"Field `topology` must be one of \"tri_strip\", \"tri_fan\", \
+									 \"quad\", or \"polygon\"!"
+										.to_string(),
+								));
 							},
 						}
 					} else {
 						strip.fields.insert(key.clone(), *value);
 					}
 				}
+				if is_list {
+					strip.topology = Topology::TriList;
+				}
+				let len = strip.vals.len();
+				if len < 3 {
+					return Err(located(
+						vert_key,
+						format!(
+							"The field `{vert_key}` must have a sequence with at least 3 vertices, \
+							 but only {len} were found!"
+						),
+					));
+				}
+				if is_list && !len.is_multiple_of(3) {
+					return Err(located(
+						vert_key,
+						format!(
+							"The field `triangles` must have a multiple of 3 vertices, but {len} \
+							 were found!"
+						),
+					));
+				}
+				for run in strip.runs() {
+					let len = run.len();
+					if len < 3 {
+						return Err(located(
+							vert_key,
+							format!(
+								"Each run in `{vert_key}` must have at least 3 vertices, but one has \
+								 only {len}!"
+							),
+						));
+					}
+					if strip.topology == Topology::Quad && !len.is_multiple_of(4) {
+						return Err(located(
+							vert_key,
+							format!(
+								"A strip with `quad` topology must have a multiple of 4 vertices \
+								 (in each run), but {len} were found!"
+							),
+						));
+					}
+				}
 				let strip_at = scene.strips.len();
 				scene.strips.push(strip);
 				Node::Strip(strip_at)
@@ -366,9 +1219,9 @@ fn parse(input: &Yaml, namespace: &mut Vec<usize>, scene: &mut Scene) -> Result<
 				// This is not, in fact, a custom, it is a point.
 				let mut point = Point::new();
 
-				for (key, value) in scene.mappings[name_at].fields.iter() {
+				for (key, value) in sorted_fields(&scene.mappings[name_at].fields) {
 					if key == "point" {
-						let vals = as_3d(scene, value);
+						let vals = as_3d(scene, value).map_err(|e| located(key, e));
 						point.loc = Point3D::from(vals?);
 					} else {
 						point.fields.insert(key.clone(), *value);
@@ -377,6 +1230,80 @@ fn parse(input: &Yaml, namespace: &mut Vec<usize>, scene: &mut Scene) -> Result<
 				let point_at = scene.points.len();
 				scene.points.push(point);
 				Node::Point(point_at)
+			} else if scene.mappings[name_at].fields.contains_key("line") {
+				// This is not, in fact, a custom, it is a line.
+				let mut line = Line {
+					vals: vec![],
+					fields: HashMap::new(),
+				};
+
+				for (key, value) in sorted_fields(&scene.mappings[name_at].fields) {
+					if key == "line" {
+						let Node::Sequence(idx) = value else {
+							return Err(located(
+								key,
+								"Field `line` must hold a sequence of at least 2 points!"
+									.to_string(),
+							));
+						};
+						for vertex in scene.sequences[*idx].vals.iter() {
+							line.vals
+								.push(as_3d(scene, vertex).map_err(|e| located(key, e))?);
+						}
+					} else {
+						line.fields.insert(key.clone(), *value);
+					}
+				}
+				let len = line.vals.len();
+				if len < 2 {
+					return Err(located(
+						"line",
+						format!(
+							"The field `line` must have a sequence with at least 2 points, but only \
+							 {len} were found!"
+						),
+					));
+				}
+				let line_at = scene.lines.len();
+				scene.lines.push(line);
+				Node::Line(line_at)
+			} else if scene.mappings[name_at].fields.contains_key("sphere") {
+				// This is not, in fact, a custom, it is a sphere.
+				let mut center = new_point(0.0);
+				let mut radius = None;
+				let mut fields = HashMap::new();
+
+				for (key, value) in sorted_fields(&scene.mappings[name_at].fields) {
+					if key == "sphere" {
+						center = as_3d(scene, value).map_err(|e| located(key, e))?;
+					} else if key == "radius" {
+						match value {
+							Node::Number(val) if *val > 0.0 => radius = Some(*val),
+							_ => {
+								return Err(located(
+									key,
+									"Field `radius` in sphere must be a positive float!"
+										.to_string(),
+								));
+							},
+						}
+					} else {
+						fields.insert(key.clone(), *value);
+					}
+				}
+				let Some(radius) = radius else {
+					return Err(at(
+						&input.loc,
+						"Sphere is missing mandatory field `radius`!".to_string(),
+					));
+				};
+				let sphere_at = scene.spheres.len();
+				scene.spheres.push(Sphere {
+					center,
+					radius,
+					fields,
+				});
+				Node::Sphere(sphere_at)
 			} else if scene.mappings[name_at].fields.contains_key("instance") {
 				// This is not, in fact, a custom, it is an instance. Convert it to such
 				let mut affected = Node::Bool(false); // guaranteed to be replaced since conditional forces it
@@ -386,47 +1313,146 @@ fn parse(input: &Yaml, namespace: &mut Vec<usize>, scene: &mut Scene) -> Result<
 				let mut translate = new_point(0.0);
 				let mut fields = HashMap::new();
 
-				for (key, value) in scene.mappings[name_at].fields.iter() {
+				for (key, value) in sorted_fields(&scene.mappings[name_at].fields) {
 					if key == "instance" {
 						match value {
 							Node::Number(_) => {
-								return Err("Field `instance` must hold the value of some other \
-								            object, not a number!"
-									.to_string());
+								return Err(located(
+									key,
+									"Field `instance` must hold the value of some other object, \
+									 not a number!"
+										.to_string(),
+								));
 							},
 							Node::Bool(_) => {
-								return Err("Field `instance` must hold the value of some other \
-								            object, not a bool!"
-									.to_string());
+								return Err(located(
+									key,
+									"Field `instance` must hold the value of some other object, \
+									 not a bool!"
+										.to_string(),
+								));
+							},
+							Node::String(_) => {
+								return Err(located(
+									key,
+									"Field `instance` must hold the value of some other object, \
+									 not a string!"
+										.to_string(),
+								));
 							},
-							Node::Sequence(_) => {
-								return Err("Field `instance` must hold the value of some other \
-								            object, not a sequence!"
-									.to_string());
+							Node::Sequence(seq) => {
+								// A sequence of objects is wrapped in a mapping after (see below)
+								let objects = scene.sequences[*seq].vals.iter().all(|val| {
+									!matches!(
+										val,
+										Node::Number(_)
+											| Node::Bool(_) | Node::String(_) | Node::Sequence(_)
+									)
+								});
+								if !objects {
+									return Err(located(
+										key,
+										"Field `instance` must hold the value of some other \
+										 object, or a sequence of objects!"
+											.to_string(),
+									));
+								}
 							},
 							_ => {},
 						}
 						affected = *value;
 					} else if key == "scale" {
-						scale = as_3d(scene, value)?;
+						scale = as_scale(scene, value).map_err(|e| located(key, e))?;
 					} else if key == "rotate" {
-						rotate = as_3d(scene, value)?;
+						rotate = as_3d_broadcast(scene, value).map_err(|e| located(key, e))?;
 					} else if key == "translate" {
-						translate = as_3d(scene, value)?;
+						translate = as_3d_broadcast(scene, value).map_err(|e| located(key, e))?;
 					} else {
 						fields.insert(key.clone(), *value);
 					}
 				}
+				// A sequence of objects is sugar for a mapping holding them as data
+				if let Node::Sequence(_) = affected {
+					let mut map = Mapping::new();
+					map.fields.insert("data".into(), affected);
+					scene.mappings.push(map);
+					affected = Node::Mapping(scene.mappings.len() - 1);
+				}
 				let inst = Instance {
 					affected,
 					scale,
 					rotate,
 					translate,
+					baked: None,
 					fields,
+					bounds: None,
 				};
 				let scene_at = scene.instances.len();
 				scene.instances.push(inst);
 				Node::Instance(scene_at)
+			} else if scene.mappings[name_at].fields.contains_key("array") {
+				// This is not, in fact, a custom, it is an array of instances.
+				let mut affected = Node::Bool(false); // guaranteed to be replaced since conditional forces it
+				let mut count = [1, 1, 1];
+				let mut scale = new_point(1.0);
+				let mut rotate = new_point(0.0);
+				let mut translate = new_point(0.0);
+				let mut fields = HashMap::new();
+
+				for (key, value) in sorted_fields(&scene.mappings[name_at].fields) {
+					if key == "array" {
+						let kind = match value {
+							Node::Number(_) => Some("a number"),
+							Node::Bool(_) => Some("a bool"),
+							Node::String(_) => Some("a string"),
+							Node::Sequence(_) => Some("a sequence"),
+							_ => None,
+						};
+						if let Some(kind) = kind {
+							return Err(located(
+								key,
+								format!(
+									"Field `array` must hold the value of some other object, not \
+									 {kind}!"
+								),
+							));
+						}
+						affected = *value;
+					} else if key == "count" {
+						let dims = match value {
+							Node::Number(val) => Ok(Point3D::new(*val, 1.0, 1.0)),
+							_ => as_3d(scene, value),
+						};
+						let dims = dims.map_err(|e| located(key, e))?;
+						for i in 0..3 {
+							if dims[i] < 0.0 || dims[i].fract() != 0.0 {
+								return Err(located(
+									key,
+									"Field `count` must hold non-negative integers!".to_string(),
+								));
+							}
+							count[i] = dims[i] as usize;
+						}
+					} else if key == "scale" {
+						scale = as_scale(scene, value).map_err(|e| located(key, e))?;
+					} else if key == "rotate" {
+						rotate = as_3d_broadcast(scene, value).map_err(|e| located(key, e))?;
+					} else if key == "translate" {
+						translate = as_3d_broadcast(scene, value).map_err(|e| located(key, e))?;
+					} else {
+						fields.insert(key.clone(), *value);
+					}
+				}
+				let array_at = scene.arrays.len();
+				scene.arrays.push(Array {
+					affected,
+					count,
+					scale,
+					rotate,
+					translate,
+					fields,
+				});
+				Node::Array(array_at)
 			} else if scene.mappings[name_at].fields.contains_key("origin")
 				&& scene.mappings[name_at].fields.contains_key("direction")
 				&& scene.mappings[name_at].fields.contains_key("max")
@@ -434,22 +1460,32 @@ fn parse(input: &Yaml, namespace: &mut Vec<usize>, scene: &mut Scene) -> Result<
 				// This is actually a ray
 				let mut origin = new_point(1.0);
 				let mut direction = new_point(1.0);
-				let mut extent = 0.0;
-				let mut min = 0.0;
+				let mut extent = 0.0; // guaranteed to be replaced since conditional forces it
+				let mut min = 0.0; // optional, so 0 is the default
 				let mut fields = HashMap::new();
 
-				for (key, value) in scene.mappings[name_at].fields.iter() {
+				for (key, value) in sorted_fields(&scene.mappings[name_at].fields) {
 					if key == "origin" {
-						origin = as_3d(scene, value)?;
+						origin = as_3d(scene, value).map_err(|e| located(key, e))?;
 					} else if key == "direction" {
-						direction = as_3d(scene, value)?;
+						direction = as_3d(scene, value).map_err(|e| located(key, e))?;
+						if direction == new_point(0.0) {
+							return Err(located(
+								key,
+								"Field `direction` cannot be zero, since a ray must point somewhere!"
+									.to_string(),
+							));
+						}
 					} else if key == "max" {
 						match value {
 							Node::Number(val) => {
 								extent = *val;
 							},
 							_ => {
-								return Err("Field `max` in ray must be a float!".to_string());
+								return Err(located(
+									key,
+									"Field `max` in ray must be a float!".to_string(),
+								));
 							},
 						}
 					} else if key == "min" {
@@ -458,13 +1494,28 @@ fn parse(input: &Yaml, namespace: &mut Vec<usize>, scene: &mut Scene) -> Result<
 								min = *val;
 							},
 							_ => {
-								return Err("Field `min` in ray must be a float!".to_string());
+								return Err(located(
+									key,
+									"Field `min` in ray must be a float!".to_string(),
+								));
 							},
 						}
 					} else {
 						fields.insert(key.clone(), *value);
 					}
 				}
+				if min >= extent {
+					let ray_name = match fields.get("name") {
+						Some(Node::String(str_at)) => format!("Ray \"{}\"", scene.strings[*str_at]),
+						_ => "Ray".to_string(),
+					};
+					return Err(located(
+						"min",
+						format!(
+							"{ray_name} has `min` ({min}) which is not less than its `max` ({extent})!"
+						),
+					));
+				}
 				let ray = Ray {
 					origin,
 					direction,
@@ -479,30 +1530,408 @@ fn parse(input: &Yaml, namespace: &mut Vec<usize>, scene: &mut Scene) -> Result<
 				Node::Mapping(name_at)
 			}
 		},
-		_ => return Err("Unsupported YAML value found while parsing scene data!".to_string()),
+		Value::Null => {
+			return Err(at(
+				&input.loc,
+				"Unsupported YAML value found while parsing scene data!".to_string(),
+			));
+		},
 	};
+	if input.anchor > 0 {
+		ctx.anchors.insert(input.anchor, ret);
+	}
 	Ok(ret)
 }
 
+use crate::CompileError;
+use crate::load::{Location, Marked, Value};
 use nalgebra::matrix;
-use yaml_rust2::Yaml;
-pub fn to_ir(input: &Yaml) -> Result<Scene, String> {
-	let mut scene = Scene {
-		world: Node::Bool(false),
-		sequences: vec![],
-		strips: vec![],
-		points: vec![],
-		rays: vec![],
-		instances: vec![],
-		mappings: vec![],
-	};
+#[cfg_attr(not(test), allow(dead_code))]
+pub fn to_ir(input: &Marked) -> Result<Scene, String> {
+	let mut scene = Scene::new();
 
-	let mut namespace: Vec<usize> = vec![];
-	scene.world = parse(input, &mut namespace, &mut scene)?;
+	let mut globals: Vec<usize> = vec![];
+	scene.world = append_ir(input, &mut scene, &mut globals)?;
 
 	Ok(scene)
 }
 
+/// Parse another document into an existing scene, returning the document's root node. The document
+/// may reference any top-level names of the documents previously parsed, whose root mappings are
+/// given by `globals`. It is an error for the document to redefine any of those names (except for
+/// `data`, which each document may use for its own world).
+pub fn append_ir(
+	input: &Marked,
+	scene: &mut Scene,
+	globals: &mut Vec<usize>,
+) -> Result<Node, String> {
+	if let Value::Hash(map) = &input.value {
+		for (key, _) in map {
+			if let Value::String(name) = &key.value
+				&& name != "data"
+				&& name != "const"
+				&& resolve(globals, scene, name).is_some()
+			{
+				return Err(at(
+					&key.loc,
+					format!("Name \"{name}\" is defined at the top level of multiple input files!"),
+				));
+			}
+		}
+	}
+
+	let mut ctx = ParseContext {
+		anchors: HashMap::new(),
+		partial: vec![],
+	};
+	let mut rest = None;
+	if let Value::Hash(map) = &input.value
+		&& let Some(const_at) = map
+			.iter()
+			.position(|(key, _)| matches!(&key.value, Value::String(k) if k == "const"))
+	{
+		let consts_at = parse_consts(&map[const_at].1, globals, scene, &mut ctx)?;
+		for (key, _) in map {
+			if let Value::String(name) = &key.value
+				&& scene.mappings[consts_at].fields.contains_key(name.as_str())
+			{
+				return Err(at(
+					&key.loc,
+					format!("Name \"{name}\" is defined both in `const` and at the top level!"),
+				));
+			}
+		}
+		globals.push(consts_at);
+		// The constants are not a field of the root mapping
+		let mut without = input.clone();
+		if let Value::Hash(entries) = &mut without.value {
+			entries.remove(const_at);
+		}
+		rest = Some(without);
+	}
+	let input = rest.as_ref().unwrap_or(input);
+
+	// The root mapping (if any) is always the first one created by the parse
+	let root_at = scene.mappings.len();
+	let mut namespace = globals.clone();
+	let root = parse(input, &mut namespace, scene, &mut ctx)?;
+	if let Value::Hash(_) = input.value {
+		globals.push(root_at);
+	}
+	Ok(root)
+}
+
+/// Parse the top-level `const` mapping, returning the index of the mapping holding its entries.
+/// Each entry may reference the entries before it and any names of previous documents (given by
+/// `globals`), which it may not redefine.
+fn parse_consts(
+	input: &Marked,
+	globals: &[usize],
+	scene: &mut Scene,
+	ctx: &mut ParseContext,
+) -> Result<usize, String> {
+	let Value::Hash(entries) = &input.value else {
+		return Err(at(
+			&input.loc,
+			"Field `const` must be a mapping of names to values!".to_string(),
+		));
+	};
+	let consts_at = scene.mappings.len();
+	scene.mappings.push(Mapping::new());
+	let mut namespace = globals.to_vec();
+	namespace.push(consts_at);
+	for (key, val) in entries {
+		let Value::String(name) = &key.value else {
+			return Err(at(
+				&key.loc,
+				"Name in YAML field found to be non-string!".to_string(),
+			));
+		};
+		if resolve(globals, scene, name).is_some() {
+			return Err(at(
+				&key.loc,
+				format!("Name \"{name}\" is defined at the top level of multiple input files!"),
+			));
+		}
+		let node = parse(val, &mut namespace, scene, ctx)?;
+		let name = scene.intern(name);
+		scene.mappings[consts_at].fields.insert(name, node);
+	}
+	Ok(consts_at)
+}
+
+/// Files included while parsing scene files
+pub struct Includes {
+	/// Files currently being parsed, where each includes the one after it
+	chain: Vec<PathBuf>,
+	/// Files which have already been fully parsed
+	loaded: HashSet<PathBuf>,
+	/// Whether files may be included at all, which they may not be in a scene not read from a file
+	allowed: bool,
+}
+impl Includes {
+	pub fn new() -> Includes {
+		Includes {
+			chain: vec![],
+			loaded: HashSet::new(),
+			allowed: true,
+		}
+	}
+
+	/// Refuse any `include` field, so that parsing never touches the filesystem
+	pub fn refused() -> Includes {
+		Includes {
+			allowed: false,
+			..Includes::new()
+		}
+	}
+}
+
+/// Parse the scene file at `path` into an existing scene, as `append_ir` does. Any files listed in
+/// its top-level `include` field (with relative paths resolved against the directory of `path`)
+/// are parsed first, so that the file may reference what they define. A file included more than
+/// once is only parsed the first time.
+pub fn append_file(
+	path: &str,
+	scene: &mut Scene,
+	globals: &mut Vec<usize>,
+	includes: &mut Includes,
+) -> Result<Node, CompileError> {
+	let doc = crate::load::load_file(path)?;
+	append_doc(path, doc, scene, globals, includes)
+}
+
+/// Parse the document (already loaded from the file at `path`) as `append_file` does. Failing to
+/// read an included file is an input error, as it would be for `path` itself.
+pub fn append_doc(
+	path: &str,
+	mut doc: Marked,
+	scene: &mut Scene,
+	globals: &mut Vec<usize>,
+	includes: &mut Includes,
+) -> Result<Node, CompileError> {
+	// The file must exist since it was just read (unless it was never a file)
+	let canonical = if includes.allowed {
+		std::fs::canonicalize(path).unwrap_or_else(|_| PathBuf::from(path))
+	} else {
+		PathBuf::from(path)
+	};
+	if includes.chain.contains(&canonical) {
+		return Err(CompileError::Scene(format!(
+			"File \"{path}\" includes itself recursively!"
+		)));
+	}
+
+	let mut included = vec![];
+	if let Value::Hash(entries) = &mut doc.value
+		&& let Some(include_at) = entries
+			.iter()
+			.position(|(key, _)| matches!(&key.value, Value::String(k) if k == "include"))
+	{
+		let (_, val) = entries.remove(include_at);
+		if !includes.allowed {
+			return Err(CompileError::Scene(at(
+				&val.loc,
+				"Field `include` cannot be used in a scene not read from a file!".to_string(),
+			)));
+		}
+		let Value::Array(paths) = val.value else {
+			return Err(CompileError::Scene(at(
+				&val.loc,
+				"Field `include` must be a sequence of file paths!".to_string(),
+			)));
+		};
+		for element in paths {
+			let Value::String(file) = element.value else {
+				return Err(CompileError::Scene(at(
+					&element.loc,
+					"Field `include` must be a sequence of file paths!".to_string(),
+				)));
+			};
+			let dir = Path::new(path).parent().unwrap_or(Path::new(""));
+			included.push(dir.join(file).to_string_lossy().into_owned());
+		}
+	}
+
+	includes.chain.push(canonical.clone());
+	for file in included {
+		let file_canonical = std::fs::canonicalize(&file).unwrap_or_else(|_| PathBuf::from(&file));
+		if includes.loaded.contains(&file_canonical) {
+			continue;
+		}
+		append_file(&file, scene, globals, includes).map_err(|e| {
+			e.map_message(|msg| format!("In file \"{file}\" included by \"{path}\": {msg}"))
+		})?;
+	}
+	includes.chain.pop();
+
+	let root = append_ir(&doc, scene, globals).map_err(CompileError::Scene)?;
+	includes.loaded.insert(canonical);
+	Ok(root)
+}
+
+/// Convert the rotations of all instances and arrays, given in radians, to degrees as the IR holds
+/// them.
+pub fn rotate_from_radians(scene: &mut Scene) {
+	for instance in scene.instances.iter_mut() {
+		instance.rotate = instance.rotate.map(f64::to_degrees);
+	}
+	for array in scene.arrays.iter_mut() {
+		array.rotate = array.rotate.map(f64::to_degrees);
+	}
+}
+
+/// Find the object with the given top-level name (defined by any of the mappings in `globals`) to
+/// use as the world.
+pub fn named_world(scene: &Scene, globals: &[usize], name: &str) -> Result<Node, String> {
+	match resolve(globals, scene, name) {
+		None => Err(format!(
+			"Cannot use \"{name}\" as the world since no such name is defined at the top level!"
+		)),
+		Some(Node::Number(_) | Node::Bool(_) | Node::String(_) | Node::Sequence(_)) => Err(
+			format!("Cannot use \"{name}\" as the world since it is not an object!"),
+		),
+		Some(node) => Ok(*node),
+	}
+}
+
+/// Whether the world holds no objects: it is a value which is not an object, or a mapping without
+/// any data.
+pub fn is_empty(scene: &Scene) -> bool {
+	match scene.world {
+		Node::Number(_) | Node::Bool(_) | Node::String(_) | Node::Sequence(_) => true,
+		Node::Mapping(idx) => {
+			let map = &scene.mappings[idx];
+			!map.is_box
+				&& match map.fields.get("data") {
+					Some(Node::Sequence(seq)) => scene.sequences[*seq].vals.is_empty(),
+					_ => true,
+				}
+		},
+		_ => false,
+	}
+}
+
+/// Fields which any object may have
+const COMMON_FIELDS: &[&str] = &["color", "comment", "name", "visible"];
+const STRIP_FIELDS: &[&str] = &[
+	"colors",
+	"geometry_index",
+	"opaque",
+	"primitive_index",
+	"smooth",
+	"texture",
+	"uv",
+];
+const RAY_FIELDS: &[&str] = &["headless"];
+/// Fields of a sphere besides those of the procedural it is written as in BVH output
+const SPHERE_FIELDS: &[&str] = &["smooth"];
+const INSTANCE_FIELDS: &[&str] = &[
+	"color_override",
+	"custom_index",
+	"id",
+	"mask",
+	"sbt_record_offset",
+];
+const PROCEDURAL_FIELDS: &[&str] = &["geometry_index", "max", "min", "opaque", "primitive_index"];
+
+/// A message for each field of an object reachable from the world which no output uses, such as a
+/// field whose name is misspelled. Fields with a name that has been referenced are not reported,
+/// nor are the fields of mappings with `data`, since either may be variables.
+pub fn unknown_fields(scene: &Scene) -> Vec<String> {
+	fn check(
+		scene: &Scene,
+		node: &Node,
+		visited: &mut HashSet<(&'static str, usize)>,
+		unknown: &mut Vec<String>,
+	) {
+		let sphere_fields = [PROCEDURAL_FIELDS, SPHERE_FIELDS].concat();
+		let (kind, idx, fields, known) = match node {
+			Node::Strip(idx) => ("strip", *idx, &scene.strips[*idx].fields, STRIP_FIELDS),
+			Node::Point(idx) => ("point", *idx, &scene.points[*idx].fields, &[][..]),
+			Node::Ray(idx) => ("ray", *idx, &scene.rays[*idx].fields, RAY_FIELDS),
+			Node::Line(idx) => ("line", *idx, &scene.lines[*idx].fields, &[][..]),
+			Node::Sphere(idx) => (
+				"sphere",
+				*idx,
+				&scene.spheres[*idx].fields,
+				&sphere_fields[..],
+			),
+			Node::Instance(idx) => (
+				"instance",
+				*idx,
+				&scene.instances[*idx].fields,
+				INSTANCE_FIELDS,
+			),
+			Node::Mapping(idx) => (
+				"procedural",
+				*idx,
+				&scene.mappings[*idx].fields,
+				PROCEDURAL_FIELDS,
+			),
+			_ => return,
+		};
+		if !visited.insert((kind, idx)) {
+			return;
+		}
+		match node {
+			Node::Instance(idx) => check(scene, &scene.instances[*idx].affected, visited, unknown),
+			Node::Mapping(_) => {
+				if let Some(Node::Sequence(seq)) = fields.get("data") {
+					for child in scene.sequences[*seq].vals.iter() {
+						check(scene, child, visited, unknown);
+					}
+					return;
+				}
+			},
+			_ => {},
+		}
+		// Name the object by its `name` field, if it has one
+		let object = match fields.get("name") {
+			Some(Node::String(str_at)) => format!("{kind} \"{}\"", scene.strings[*str_at]),
+			_ => format!("{kind} {idx}"),
+		};
+		for (name, _) in sorted_fields(fields) {
+			if !COMMON_FIELDS.contains(&&**name)
+				&& !known.contains(&&**name)
+				&& !scene.referenced.contains(name)
+			{
+				unknown.push(format!(
+					"Field `{name}` of {object} is not used by any output!"
+				));
+			}
+		}
+	}
+
+	let mut unknown = vec![];
+	check(scene, &scene.world, &mut HashSet::new(), &mut unknown);
+	unknown
+}
+
+/// Whether the node is hidden by a `visible: false` field, such that outputs skip it (and anything
+/// it holds) while it stays in the scene.
+pub fn is_hidden(scene: &Scene, node: &Node) -> bool {
+	let fields = match node {
+		Node::Strip(idx) => &scene.strips[*idx].fields,
+		Node::Point(idx) => &scene.points[*idx].fields,
+		Node::Ray(idx) => &scene.rays[*idx].fields,
+		Node::Line(idx) => &scene.lines[*idx].fields,
+		Node::Sphere(idx) => &scene.spheres[*idx].fields,
+		Node::Instance(idx) => &scene.instances[*idx].fields,
+		Node::Mapping(idx) => &scene.mappings[*idx].fields,
+		_ => return false,
+	};
+	matches!(fields.get("visible"), Some(Node::Bool(false)))
+}
+
+/// Scale the direction of every ray to unit length, such that each ray's `min` and `max` are
+/// distances along it.
+pub fn normalize_rays(scene: &mut Scene) {
+	for ray in scene.rays.iter_mut() {
+		ray.direction = ray.direction.normalize();
+	}
+}
+
 pub fn verify_instancing(scene: &Scene, max_level: u8) -> Result<(), String> {
 	assert!(max_level > 0); // should be checked before calling
 
@@ -514,6 +1943,13 @@ pub fn verify_instancing(scene: &Scene, max_level: u8) -> Result<(), String> {
 				}
 				verify_node(scene, &scene.instances[*idx].affected, max_level, level + 1)?;
 			},
+			Node::Array(idx) => {
+				// Each copy in an array will become an instance
+				if level + 1 >= max_level {
+					return Err(format!("Array {} found at level {}!", idx, level));
+				}
+				verify_node(scene, &scene.arrays[*idx].affected, max_level, level + 1)?;
+			},
 			Node::Mapping(idx) => {
 				if let Some(Node::Sequence(seq_at)) = scene.mappings[*idx].fields.get("data") {
 					for element in scene.sequences[*seq_at].vals.iter() {
@@ -540,7 +1976,9 @@ mod tests {
 			scale,
 			rotate,
 			translate,
+			baked: None,
 			fields: HashMap::new(),
+			bounds: None,
 		};
 
 		let mat = inst.obj_to_world();
@@ -552,6 +1990,489 @@ mod tests {
 		}
 	}
 
+	fn parse_str(text: &str) -> Result<Scene, String> {
+		let docs = crate::load::load_from_str(text).unwrap();
+		to_ir(&docs[0])
+	}
+
+	#[test]
+	fn misspelled_field() {
+		let scene = parse_str(
+			"data:\n- strip: [[0, 0, 0], [1, 0, 0], [0, 1, 0]]\n  oapque: true\n  name: tri\n- \
+			 instance:\n    point: [0, 0, 0]\n    corner: [1, 1, 1]\n    color: corner\n  mask: 1\n  \
+			 opaque: true\nred: [255, 0, 0]",
+		)
+		.unwrap();
+		// Only objects are checked, and not the variables they reference
+		assert_eq!(
+			unknown_fields(&scene),
+			[
+				"Field `oapque` of strip \"tri\" is not used by any output!",
+				"Field `opaque` of instance 0 is not used by any output!",
+			]
+		);
+
+		// The fields of the procedural which a sphere is written as are known
+		let scene = parse_str(
+			"sphere: [0, 0, 0]\nradius: 1\nopaque: true\ngeometry_index: 2\nsmooth: 1\nshiny: true",
+		)
+		.unwrap();
+		assert_eq!(
+			unknown_fields(&scene),
+			["Field `shiny` of sphere 0 is not used by any output!"]
+		);
+	}
+
+	#[test]
+	fn interned_names() {
+		let text = "data:\n- point: [0, 0, 0]\n  weight: 1\n- point: [1, 0, 0]\n  weight: 2";
+		let mut scene = parse_str(text).unwrap();
+		let weight = scene.intern("weight");
+		// Both points share the scene's one copy of the name
+		let names: Vec<&str> = scene
+			.points
+			.iter()
+			.map(|point| point.fields.keys().next().unwrap().as_str())
+			.collect();
+		assert_eq!(names, ["weight", "weight"]);
+		assert!(std::ptr::eq(names[0], names[1]));
+		assert!(std::ptr::eq(weight.as_str(), names[0]));
+		// Another scene keeps its own copy, freed with it
+		let mut other = parse_str(text).unwrap();
+		assert!(!std::ptr::eq(other.intern("weight").as_str(), names[0]));
+	}
+
+	#[test]
+	fn json_matches_yaml() {
+		let yaml = parse_str(
+			"data:\n- strip: [[0, 0, 0], [1.5, 0, 0], [0, 1, 0]]\n  name: tri\n- instance:\n    \
+			 point: [0, 0, 0]\n  scale: 2\n  opaque: false",
+		)
+		.unwrap();
+		let json = crate::load::load_json_from_str(
+			"{\"data\": [\n  {\"strip\": [[0, 0, 0], [1.5, 0, 0], [0, 1, 0]], \"name\": \"tri\"},\n  \
+			 {\"instance\": {\"point\": [0, 0, 0]}, \"scale\": 2, \"opaque\": false}\n]}",
+		)
+		.unwrap();
+		let json = to_ir(&json).unwrap();
+		assert_eq!(json.to_string(), yaml.to_string());
+
+		let err = crate::load::load_json_from_str("{\"data\": [1,\n  ]}")
+			.err()
+			.unwrap();
+		assert!(err.contains("line 2"), "{err}");
+
+		let err = crate::load::load_json_from_str("{\"data\": [[0, 0, 0],\n  [1, 0")
+			.err()
+			.unwrap();
+		assert!(err.contains("in the array starting at line 2"), "{err}");
+	}
+
+	#[test]
+	fn json_escapes() {
+		let doc = crate::load::load_json_from_str(
+			"{\"data\": {\"strip\": [[0, 0, 0], [1, 0, 0], [0, 1, 0]], \"name\": \
+			 \"caf\\u00e9 \\ud83d\\ude00\\b\\f\\/\"}, \"big\": 18446744073709551616}",
+		)
+		.unwrap();
+		let Value::Hash(entries) = &doc.value else {
+			panic!()
+		};
+		let Value::Hash(data) = &entries[0].1.value else {
+			panic!()
+		};
+		assert!(
+			matches!(&data[1].1.value, Value::String(name) if name == "caf\u{e9} \u{1f600}\u{8}\u{c}/")
+		);
+		assert!(matches!(&entries[1].1.value, Value::Real(val) if val == "18446744073709551616"));
+
+		for (text, msg) in [
+			("\"\\ud83d\"", "Unpaired surrogate"),
+			("\"\\ude00\\ud83d\"", "Unpaired surrogate"),
+			("\"\\ud83d\\u0041\"", "Unpaired surrogate"),
+			("\"\\u00g0\"", "Invalid unicode escape"),
+			("\"\\x\"", "Unsupported escape"),
+		] {
+			let err = crate::load::load_json_from_str(text).err().unwrap();
+			assert!(err.contains(msg), "{text}: {err}");
+		}
+	}
+
+	#[test]
+	fn back_references() {
+		let scene = parse_str(
+			"someStrip:\n  strip: [[0, 0, 0], [1, 0, 0], [0, 1, 0]]\ndata:\n- someStrip\n- $0\n- \
+			 instance: $1\n  translate: [0, 0, 1]",
+		)
+		.unwrap();
+		let Node::Mapping(world) = scene.world else {
+			panic!("The world should be a mapping!");
+		};
+		let Some(Node::Sequence(data)) = scene.mappings[world].fields.get("data") else {
+			panic!("The world should have data!");
+		};
+		let vals = &scene.sequences[*data].vals;
+		assert!(matches!(vals[0], Node::Strip(_)));
+		assert!(vals[0] == vals[1]);
+		assert!(scene.instances[0].affected == vals[0]);
+
+		let err = parse_str("data:\n- $1\n- strip: [[0, 0, 0], [1, 0, 0], [0, 1, 0]]")
+			.err()
+			.unwrap();
+		assert!(err.contains("but only 0 precede it"), "{err}");
+		let err = parse_str("point: $0").err().unwrap();
+		assert!(err.contains("is not within a sequence"), "{err}");
+	}
+
+	#[test]
+	fn trailing_content() {
+		let text = "strip: [[0, 0, 0], [1, 0, 0], [0, 1, 0]]\ngarbage";
+		assert!(crate::load::load_from_str(text).is_err());
+		// A file of only comments holds one empty scene
+		let docs = crate::load::parse_documents("in.yaml", "# only\n# comments\n").unwrap();
+		assert_eq!(docs.len(), 1);
+		assert!(is_empty(&to_ir(&docs[0]).unwrap()));
+	}
+
+	#[test]
+	fn comment_in_flow_sequence() {
+		let commented = parse_str("point: [1, # two\n  2, 3]").unwrap();
+		let plain = parse_str("point: [1, 2, 3]").unwrap();
+		assert_eq!(commented.to_string(), plain.to_string());
+	}
+
+	#[test]
+	fn scale_broadcast() {
+		let scene = parse_str("instance:\n  point: [0, 0, 0]\nscale: 2").unwrap();
+		assert_eq!(scene.instances[0].scale, Point3D::new(2.0, 2.0, 2.0));
+	}
+
+	#[test]
+	fn scale_sequence() {
+		let scene = parse_str("instance:\n  point: [0, 0, 0]\nscale: [1, 2, 3]").unwrap();
+		assert_eq!(scene.instances[0].scale, Point3D::new(1.0, 2.0, 3.0));
+	}
+
+	#[test]
+	fn unresolved_reference_location() {
+		let res = parse_str("data:\n- strip:\n  - [0, 0, 0]\n  - [1, 0, 0]\n  - missing");
+		assert_eq!(
+			res.err().unwrap(),
+			"At line 5, column 5: Could not resolve reference \"missing\"!"
+		);
+	}
+
+	#[test]
+	fn triangles_not_multiple_of_three() {
+		let res = parse_str("triangles:\n- [0, 0, 0]\n- [1, 0, 0]\n- [0, 1, 0]\n- [1, 1, 0]");
+		assert!(res.is_err());
+	}
+
+	#[test]
+	fn sphere_radius_positive() {
+		let res = parse_str("sphere: [0, 0, 0]\nradius: 0");
+		assert_eq!(
+			res.err().unwrap(),
+			"At line 2, column 9: Field `radius` in sphere must be a positive float!"
+		);
+		let scene = parse_str("sphere: [1, 2, 3]\nradius: 0.5").unwrap();
+		assert_eq!(scene.spheres[0].radius, 0.5);
+	}
+
+	#[test]
+	fn ray_inverted_bounds() {
+		let res = parse_str("origin: [0, 0, 0]\ndirection: [1, 0, 0]\nmin: 5\nmax: 1\nname: back");
+		assert_eq!(
+			res.err().unwrap(),
+			"At line 3, column 6: Ray \"back\" has `min` (5) which is not less than its `max` (1)!"
+		);
+	}
+
+	#[test]
+	fn alias_shares_strip() {
+		let scene = parse_str(
+			"data:\n- &tri\n  strip: [[0, 0, 0], [1, 0, 0], [0, 1, 0]]\n- instance: *tri\n  \
+			 translate: [0, 0, 1]\n- *tri",
+		)
+		.unwrap();
+		assert_eq!(scene.strips.len(), 1);
+		let Node::Mapping(world) = scene.world else {
+			panic!("World should be a mapping!");
+		};
+		let Some(Node::Sequence(data)) = scene.mappings[world].fields.get("data") else {
+			panic!("World should have data!");
+		};
+		let vals = &scene.sequences[*data].vals;
+		assert!(vals[0] == Node::Strip(0) && vals[2] == Node::Strip(0));
+		assert!(scene.instances[0].affected == Node::Strip(0));
+	}
+
+	#[test]
+	fn ray_normalization() {
+		let mut scene = parse_str("origin: [0, 0, 0]\ndirection: [0, 0, 2]\nmax: 3").unwrap();
+		normalize_rays(&mut scene);
+		assert_eq!(scene.rays[0].direction, Point3D::new(0.0, 0.0, 1.0));
+		assert!(parse_str("origin: [0, 0, 0]\ndirection: [0, 0, 0]\nmax: 3").is_err());
+	}
+
+	#[test]
+	fn first_error_stable() {
+		// With several bad fields, the error for the first by name is always the one reported
+		for _ in 0..8 {
+			let err = parse_str("strip: 5\ntopology: 6").err().unwrap();
+			assert!(err.contains("Field `strip` must hold"), "{err}");
+		}
+	}
+
+	#[test]
+	fn const_block() {
+		let scene = parse_str(
+			"data:\n- data:\n  - instance:\n      point: [0, 0, 0]\n    mask: mask\nconst:\n  mask: \
+			 255\n  half: 0.5",
+		)
+		.unwrap();
+		assert!(scene.instances[0].fields["mask"] == Node::Number(255.0));
+		assert!(parse_str("const:\n  mask: 255\nmask: 3\ndata: []").is_err());
+		assert!(parse_str("const: 5\ndata: []").is_err());
+	}
+
+	#[test]
+	fn introspection() {
+		let strip = "strip: [[0, 0, 0], [1, 0, 0], [0, 1, 0], [1, 1, 0], [2, 0, 0]]";
+		let mut scene = parse_str(&format!("data:\n- {strip}\n- instance:\n    {strip}")).unwrap();
+		assert_eq!(scene.triangle_count(), 6);
+		assert_eq!(scene.instance_count(), 1);
+		let (min, max) = scene.world_bounds(&crate::transform::Bounding::default());
+		assert_eq!((min, max), (new_point(0.0), Point3D::new(2.0, 1.0, 0.0)));
+		assert_eq!(scene.box_count(), 1);
+
+		let mut scene = parse_str(strip).unwrap();
+		assert_eq!(scene.triangle_count(), 3);
+		// Objects no longer reachable from the world are not counted
+		scene.world = Node::Bool(false);
+		assert_eq!(scene.triangle_count(), 0);
+	}
+
+	#[test]
+	fn center_and_size() {
+		let scene = parse_str("center: [0, 0, 0]\nsize: [2, 2, 2]").unwrap();
+		let Node::Mapping(idx) = scene.world else {
+			panic!("Expected a procedural mapping!");
+		};
+		let fields = &scene.mappings[idx].fields;
+		assert_eq!(as_3d(&scene, &fields["min"]), Ok(new_point(-1.0)));
+		assert_eq!(as_3d(&scene, &fields["max"]), Ok(new_point(1.0)));
+		assert!(!fields.contains_key("size"));
+
+		let scene = parse_str("center: [1, 0, 0]\nhalf_extent: 2").unwrap();
+		let Node::Mapping(idx) = scene.world else {
+			panic!("Expected a procedural mapping!");
+		};
+		let max = as_3d(&scene, &scene.mappings[idx].fields["max"]);
+		assert_eq!(max, Ok(Point3D::new(3.0, 2.0, 2.0)));
+
+		assert!(parse_str("center: [0, 0, 0]\nsize: 2\nmax: [1, 1, 1]").is_err());
+		assert!(parse_str("center: [0, 0, 0]\nsize: 2\nhalf_extent: 1").is_err());
+
+		// Other objects may use the names for their own variables
+		let scene =
+			parse_str("strip: [[0, 0, 0], [1, 0, 0], [0, 1, 0]]\ncenter: [0, 0, 0]\nsize: 2")
+				.unwrap();
+		let fields = &scene.strips[0].fields;
+		assert!(fields.contains_key("center") && fields.contains_key("size"));
+		assert!(!fields.contains_key("min"));
+		let scene = parse_str("instance:\n  point: [0, 0, 0]\ncenter: [0, 0, 0]\nsize: 2").unwrap();
+		assert!(!scene.instances[0].fields.contains_key("min"));
+	}
+
+	#[test]
+	fn duplicate_keys() {
+		let err = crate::load::load_from_str(
+			"strip: [[0, 0, 0], [1, 0, 0], [0, 1, 0]]\ncolor: [255, 0, 0]\ncolor: [0, 0, 255]",
+		)
+		.err()
+		.unwrap();
+		assert_eq!(err, "Duplicated key in mapping at line 3, column 1!");
+	}
+
+	#[test]
+	fn world_by_name() {
+		let docs = crate::load::load_from_str(
+			"tower:\n  strip: [[0, 0, 0], [1, 0, 0], [0, 1, 0]]\nsize: 3\ndata:\n- point: [0, 0, 0]",
+		)
+		.unwrap();
+		let mut scene = Scene::new();
+		let mut globals = vec![];
+		scene.world = append_ir(&docs[0], &mut scene, &mut globals).unwrap();
+		assert!(named_world(&scene, &globals, "tower").unwrap() == Node::Strip(0));
+		assert!(named_world(&scene, &globals, "size").is_err());
+		assert!(named_world(&scene, &globals, "missing").is_err());
+	}
+
+	#[test]
+	fn instance_sequence() {
+		let scene = parse_str(
+			"instance:\n- strip: [[0, 0, 0], [1, 0, 0], [0, 1, 0]]\n- point: [0, 0, 0]\ntranslate: \
+			 [1, 0, 0]",
+		)
+		.unwrap();
+		let Node::Mapping(map) = scene.instances[0].affected else {
+			panic!("Instance should affect a mapping!");
+		};
+		let Some(Node::Sequence(seq)) = scene.mappings[map].fields.get("data") else {
+			panic!("Mapping should hold the objects as data!");
+		};
+		let data = &scene.sequences[*seq].vals;
+		assert!(data.len() == 2 && data[0] == Node::Strip(0) && data[1] == Node::Point(0));
+		assert!(parse_str("instance: [0, 1, 2]").is_err());
+	}
+
+	#[test]
+	fn rotate_radians() {
+		let degrees = parse_str("instance:\n  point: [0, 1, 0]\nrotate: [90, 0, 0]").unwrap();
+		let mut radians =
+			parse_str("instance:\n  point: [0, 1, 0]\nrotate: [1.5707963, 0, 0]").unwrap();
+		rotate_from_radians(&mut radians);
+		let diff = degrees.instances[0].obj_to_world() - radians.instances[0].obj_to_world();
+		assert!(diff.amax() < COMPARE_EPS);
+	}
+
+	#[test]
+	fn zero_scale() {
+		for text in [
+			"instance:\n  point: [0, 0, 0]\nscale: [0, 1, 1]",
+			"array:\n  point: [0, 0, 0]\ncount: 2\nscale: 0",
+		] {
+			let err = parse_str(text).err().unwrap();
+			assert!(err.contains("cannot be zero"), "{err}");
+		}
+		assert!(parse_str("instance:\n  point: [0, 0, 0]\nscale: [-1, 0.5, 1]").is_ok());
+	}
+
+	#[test]
+	fn gzip_input() {
+		let parse = |path: &str| {
+			let mut scene = Scene::new();
+			scene.world = append_file(path, &mut scene, &mut vec![], &mut Includes::new()).unwrap();
+			scene.to_string()
+		};
+		assert_eq!(
+			parse("examples/single/gzip/gzip.yaml.gz"),
+			parse("examples/combination/pyramid/pyramid.yaml")
+		);
+	}
+
+	#[test]
+	fn exponent_numbers() {
+		let scene = parse_str("point: [1.5e3, -2E-2, 0]").unwrap();
+		assert_eq!(scene.points[0].loc, Point3D::new(1500.0, -0.02, 0.0));
+		assert_eq!(
+			parse_str("point: [3.1.4, 0, 0]").err().unwrap(),
+			"At line 1, column 9: Could not resolve reference \"3.1.4\"!"
+		);
+		assert_eq!(
+			parse_str("point: [-.inf, 0, 0]").err().unwrap(),
+			"At line 1, column 9: Number -.inf is not finite, which is not allowed!"
+		);
+		assert_eq!(
+			parse_str("strip: [[0, 0, 0], [1, 0, 1e400], [0, 1, 0]]")
+				.err()
+				.unwrap(),
+			"At line 1, column 27: Number 1e400 is not finite, which is not allowed!"
+		);
+	}
+
+	#[test]
+	fn fmt_float_precision() {
+		let val = std::f64::consts::FRAC_1_SQRT_2;
+		assert_eq!(fmt_float(val, None), "0.7071067811865476");
+		assert_eq!(fmt_float(val, Some(4)), "0.7071");
+		assert_eq!(fmt_float(1234.5, Some(2)), "1200");
+		assert_eq!(fmt_float(-0.5, Some(3)), "-0.5");
+	}
+
+	#[test]
+	fn display_scene() {
+		let scene = parse_str("strip: [[0, 0, 0], [1, 0, 0], [0, 1, 0]]\nname: tri").unwrap();
+		let text = scene.to_string();
+		assert!(text.starts_with("world: Strip0\n"));
+		assert!(
+			text.contains(
+				"\tStrip0: tri_strip [[0, 0, 0], [1, 0, 0], [0, 1, 0]] {name: String0}\n"
+			)
+		);
+		assert!(text.contains("\tString0: tri\n"));
+	}
+
+	#[test]
+	fn include_resolves_reference() {
+		let path = concat!(
+			env!("CARGO_MANIFEST_DIR"),
+			"/examples/combination/include/include.yaml"
+		);
+		let mut scene = Scene::new();
+		let root = append_file(path, &mut scene, &mut vec![], &mut Includes::new()).unwrap();
+		let Node::Mapping(world) = root else {
+			panic!("World should be a mapping!");
+		};
+		let Some(Node::Sequence(data)) = scene.mappings[world].fields.get("data") else {
+			panic!("World should have data!");
+		};
+		// The first element of data is `redBox` itself
+		let Node::Mapping(red_box) = scene.sequences[*data].vals[0] else {
+			panic!("`redBox` should be a mapping!");
+		};
+		assert!(scene.mappings[red_box].fields.contains_key("opaque"));
+	}
+
+	#[test]
+	fn include_recursive() {
+		let dir = std::env::temp_dir().join(format!(
+			"scene-builder-{}-include-recursive",
+			std::process::id()
+		));
+		std::fs::create_dir_all(&dir).unwrap();
+		std::fs::write(dir.join("a.yaml"), "include: [b.yaml]\ndata: []").unwrap();
+		std::fs::write(dir.join("b.yaml"), "include: [a.yaml]\nfoo: 1").unwrap();
+		let path = dir.join("a.yaml").to_string_lossy().into_owned();
+		let res = append_file(&path, &mut Scene::new(), &mut vec![], &mut Includes::new());
+		let err = res.err().unwrap();
+		assert!(matches!(err, CompileError::Scene(_)), "{err:?}");
+		assert!(err.message().ends_with("includes itself recursively!"));
+
+		// An included file which cannot be read is an input error
+		std::fs::write(dir.join("b.yaml"), "include: [missing.yaml]").unwrap();
+		let res = append_file(&path, &mut Scene::new(), &mut vec![], &mut Includes::new());
+		assert!(matches!(res, Err(CompileError::Input(_))));
+		std::fs::remove_dir_all(&dir).unwrap();
+	}
+
+	#[test]
+	fn append_references_earlier() {
+		let first = &crate::load::load_from_str("tri:\n  point: [1, 2, 3]").unwrap()[0];
+		let second = &crate::load::load_from_str("data:\n- tri").unwrap()[0];
+		let mut scene = Scene::new();
+		let mut globals = vec![];
+		append_ir(first, &mut scene, &mut globals).unwrap();
+		let world = append_ir(second, &mut scene, &mut globals).unwrap();
+		let Node::Mapping(idx) = world else {
+			panic!("World should be a mapping!");
+		};
+		let Some(Node::Sequence(seq_at)) = scene.mappings[idx].fields.get("data") else {
+			panic!("World should have data!");
+		};
+		assert!(scene.sequences[*seq_at].vals[0] == Node::Point(0));
+	}
+
+	#[test]
+	fn append_name_collision() {
+		let first = &crate::load::load_from_str("tri:\n  point: [1, 2, 3]").unwrap()[0];
+		let mut scene = Scene::new();
+		let mut globals = vec![];
+		append_ir(first, &mut scene, &mut globals).unwrap();
+		assert!(append_ir(first, &mut scene, &mut globals).is_err());
+	}
+
 	#[test]
 	fn obj_to_world0() {
 		obj_to_world(
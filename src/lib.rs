@@ -0,0 +1,629 @@
+//! Compile scenes in the scene language into BVH, OBJ, and other outputs. The command line
+//! program is a thin wrapper over this library, which may also be used directly by `compile`.
+
+pub mod args;
+mod bvh;
+mod csv;
+mod dot;
+mod gzip;
+mod ir;
+mod load;
+mod obj;
+pub mod report;
+mod stats;
+mod transform;
+mod tree;
+
+pub use load::Marked as Document;
+
+/// A failure to compile, classed by its cause so that callers (and scripts, by the process exit
+/// code) may tell one from another. Each holds the message shown to the user.
+#[derive(Debug)]
+pub enum CompileError {
+	/// The command line options conflict or are incomplete
+	Usage(String),
+	/// An input file could not be read
+	Input(String),
+	/// An input could not be decoded or parsed, or its scene is invalid or cannot be compiled
+	Scene(String),
+	/// An output could not be written, or differs from the file checked against
+	Output(String),
+}
+
+impl CompileError {
+	/// The exit code of the command line program. Usage errors share code 2 with those found by
+	/// clap.
+	pub fn exit_code(&self) -> i32 {
+		match self {
+			Self::Usage(_) => 2,
+			Self::Input(_) => 3,
+			Self::Scene(_) => 4,
+			Self::Output(_) => 5,
+		}
+	}
+
+	pub fn message(&self) -> &str {
+		match self {
+			Self::Usage(msg) | Self::Input(msg) | Self::Scene(msg) | Self::Output(msg) => msg,
+		}
+	}
+
+	/// The same kind of error, with its message changed by `f`
+	pub(crate) fn map_message(self, f: impl FnOnce(String) -> String) -> CompileError {
+		match self {
+			Self::Usage(msg) => Self::Usage(f(msg)),
+			Self::Input(msg) => Self::Input(f(msg)),
+			Self::Scene(msg) => Self::Scene(f(msg)),
+			Self::Output(msg) => Self::Output(f(msg)),
+		}
+	}
+}
+
+/// Read and parse each input file into its documents.
+pub fn load_inputs(args: &args::Args) -> Result<Vec<Vec<Document>>, CompileError> {
+	let mut inputs = vec![];
+	for path in args.input.iter() {
+		inputs.push(load::load_documents(path)?);
+	}
+	Ok(inputs)
+}
+
+/// The single document of each input file, failing if any holds several.
+pub fn single_documents(
+	args: &args::Args,
+	inputs: Vec<Vec<Document>>,
+) -> Result<Vec<Document>, CompileError> {
+	let mut docs = vec![];
+	for (path, input) in args.input.iter().zip(inputs) {
+		docs.push(load::single_document(path, input).map_err(CompileError::Scene)?);
+	}
+	Ok(docs)
+}
+
+/// Parse the documents of all input files (one per file, in order) into one scene, taking the world
+/// from the input at index `world_from`, and check any requested constraints. Files are included
+/// as `includes` allows.
+fn build_scene(
+	args: &args::Args,
+	world_from: usize,
+	docs: Vec<Document>,
+	mut includes: ir::Includes,
+	reporter: &mut report::Reporter,
+) -> Result<ir::Scene, CompileError> {
+	// Convert from input data to IR data by checking grammar. Each file is parsed into the same
+	// scene so that later files may reference what earlier files define.
+	let mut scene = ir::Scene::new();
+	let mut globals = vec![];
+	for (i, (path, doc)) in args.input.iter().zip(docs).enumerate() {
+		let root = ir::append_doc(path, doc, &mut scene, &mut globals, &mut includes)?;
+		if i == world_from {
+			scene.world = root;
+		}
+	}
+
+	if let Some(path) = &args.profile {
+		scene.profile = bvh::load_profile(path)?;
+	}
+
+	if let Some(name) = &args.world {
+		scene.world = ir::named_world(&scene, &globals, name).map_err(CompileError::Scene)?;
+	}
+
+	// The IR holds rotations in degrees
+	if args.angle_unit == args::AngleUnit::Radians {
+		ir::rotate_from_radians(&mut scene);
+	}
+
+	// Any transform kept by the extracted object must be read with rotations in degrees
+	if let Some(name) = &args.extract {
+		transform::extract(&mut scene, name, args.keep_world_transform)
+			.map_err(CompileError::Scene)?;
+	}
+
+	if args.normalize_rays {
+		ir::normalize_rays(&mut scene);
+	}
+
+	if ir::is_empty(&scene) {
+		reporter.warn("The scene is empty, since its world holds no objects!");
+	}
+
+	if args.warn_unknown_fields {
+		for warning in ir::unknown_fields(&scene) {
+			reporter.warn(&warning);
+		}
+	}
+
+	// Verify instancing levels if requested
+	if args.instancing > 0 {
+		ir::verify_instancing(&scene, args.instancing).map_err(CompileError::Scene)?;
+	}
+	Ok(scene)
+}
+
+/// In strict mode, any warning reported is an error, given by the first warning.
+pub fn check_strict(args: &args::Args, reporter: &report::Reporter) -> Result<(), CompileError> {
+	match reporter.warnings().first() {
+		Some(warning) if args.strict => Err(CompileError::Scene(format!(
+			"Warnings are errors in strict mode, and the first was: {warning}"
+		))),
+		_ => Ok(()),
+	}
+}
+
+/// Read, parse, and verify the input files as in verification mode, describing the outcome by the
+/// lines of a JSON report. The report is given with the failure, if there was one.
+pub fn json_report(args: &args::Args, world_from: usize) -> (Vec<String>, Option<CompileError>) {
+	// Every outcome is described by the report, so its warnings are kept apart from any others
+	let mut reporter = report::Reporter::new();
+	let result = load_inputs(args)
+		.and_then(|inputs| single_documents(args, inputs))
+		.and_then(|docs| {
+			let mut scene =
+				build_scene(args, world_from, docs, ir::Includes::new(), &mut reporter)?;
+			if !args.raw {
+				transform::transform(&mut scene, args, false, &mut reporter)
+					.map_err(CompileError::Scene)?;
+			}
+			Ok(stats::stats(&mut scene, &transform::Bounding::new(args)))
+		});
+	match result {
+		Ok(stats) => (report::json_report(&Ok(stats), &reporter.warnings()), None),
+		Err(failure) => {
+			let result = Err(failure.message().to_string());
+			(
+				report::json_report(&result, &reporter.warnings()),
+				Some(failure),
+			)
+		},
+	}
+}
+
+/// Build the scene from the documents of the input files (one per file, in order), taking the world
+/// from the input at index `world_from`, then transform it and generate the lines of each output as
+/// `compile_scene` does.
+pub fn compile_documents(
+	args: &args::Args,
+	world_from: usize,
+	docs: Vec<Document>,
+	outputs: &[(String, args::OutputFormat)],
+	verify: bool,
+	reporter: &mut report::Reporter,
+) -> Result<Vec<(String, Vec<String>)>, CompileError> {
+	let includes = ir::Includes::new();
+	compile_including(args, world_from, docs, includes, outputs, verify, reporter)
+}
+
+/// Compile the documents as `compile_documents` does, including files as `includes` allows.
+fn compile_including(
+	args: &args::Args,
+	world_from: usize,
+	docs: Vec<Document>,
+	includes: ir::Includes,
+	outputs: &[(String, args::OutputFormat)],
+	verify: bool,
+	reporter: &mut report::Reporter,
+) -> Result<Vec<(String, Vec<String>)>, CompileError> {
+	if args.raw
+		&& outputs
+			.iter()
+			.any(|(_, format)| *format == args::OutputFormat::Bvh)
+	{
+		return Err(CompileError::Usage(
+			"Cannot use option `raw` with a BVH target!".to_string(),
+		));
+	}
+	let scene = build_scene(args, world_from, docs, includes, reporter)?;
+	compile_scene(args, scene, outputs, verify, reporter).map_err(CompileError::Scene)
+}
+
+/// The listing requested (of the tree or materials) which is printed to stdout in place of every
+/// output, if any
+fn listing(
+	args: &args::Args,
+	scene: &ir::Scene,
+	reporter: &mut report::Reporter,
+) -> Option<(String, Vec<String>)> {
+	if args.tree {
+		Some((String::new(), tree::to_tree(scene, args)))
+	} else if args.list_materials {
+		Some((String::new(), obj::list_materials(scene, args, reporter)))
+	} else {
+		None
+	}
+}
+
+/// Transform the scene and generate the lines of each output, returned with the output's path. In
+/// verification mode, there are no outputs.
+fn compile_scene(
+	args: &args::Args,
+	mut scene: ir::Scene,
+	outputs: &[(String, args::OutputFormat)],
+	verify: bool,
+	reporter: &mut report::Reporter,
+) -> Result<Vec<(String, Vec<String>)>, String> {
+	use args::OutputFormat;
+	// If we are simply verifying the scene, we are done now.
+	if verify {
+		if (args.stats || args.tree || args.list_materials) && !args.raw {
+			transform::transform(&mut scene, args, false, reporter)?;
+		}
+		if args.stats {
+			stats::report_stats(&mut scene, &transform::Bounding::new(args), reporter);
+		}
+		return Ok(listing(args, &scene, reporter).into_iter().collect());
+	}
+	// Otherwise, we want to apply transformations given by the command line arguments. Then we can
+	// translate into the target format.
+	let bvh = outputs
+		.iter()
+		.any(|(_, format)| *format == OutputFormat::Bvh);
+	// Some transformations (such as splitting strips) are only for the BVH target, so when it is
+	// written beside other targets, it is given its own copy of the scene to transform. Otherwise,
+	// all targets share the one scene (and its bounds).
+	let mixed = bvh
+		&& outputs
+			.iter()
+			.any(|(_, format)| *format != OutputFormat::Bvh)
+		&& transform::differs_for_bvh(&scene, args);
+	let mut bvh_scene = None;
+	if args.raw {
+		// BVH targets are refused with raw before now. No output can draw an array directly, so they
+		// are expanded even so.
+		transform::expand_arrays(&mut scene);
+	} else {
+		let copy = mixed.then(|| scene.clone());
+		// Handle all the box-related transformations
+		transform::transform(&mut scene, args, bvh && !mixed, reporter)?;
+		if let Some(mut copy) = copy {
+			let mut copy_reporter = report::Reporter::new();
+			copy_reporter.progress = reporter.progress;
+			let result = transform::transform(&mut copy, args, true, &mut copy_reporter);
+			reporter.merge(copy_reporter);
+			result?;
+			bvh_scene = Some(copy);
+		}
+	}
+
+	if args.stats {
+		stats::report_stats(&mut scene, &transform::Bounding::new(args), reporter);
+	}
+
+	let bvh_scene = bvh_scene.as_ref().unwrap_or(&scene);
+	if bvh {
+		bvh::check_tlas(bvh_scene)?;
+		bvh::check_instances(bvh_scene)?;
+	}
+
+	if let Some(listing) = listing(args, &scene, reporter) {
+		return Ok(vec![listing]);
+	}
+
+	let mut results = vec![];
+	for (path, format) in outputs {
+		if args.split_output {
+			for (name, lines) in obj::split_obj(&scene, args, reporter)? {
+				let file = std::path::Path::new(path).join(format!("{name}.obj"));
+				results.push((file.to_string_lossy().into_owned(), lines));
+			}
+			continue;
+		}
+		let lines = match format {
+			OutputFormat::Bvh => {
+				let lines = bvh::to_bvh(bvh_scene, args, reporter);
+				if args.bvh_validate_indices {
+					bvh::validate_indices(&lines.join("\n"))?;
+				}
+				lines
+			},
+			OutputFormat::Obj => obj::to_obj(&scene, args, reporter),
+			OutputFormat::Ir => scene.to_string().lines().map(String::from).collect(),
+			OutputFormat::Csv => csv::to_csv(&scene, args, reporter),
+			OutputFormat::Dot => dot::to_dot(&scene),
+			OutputFormat::Verify => panic!("Verify case should have exited earlier!"),
+		};
+		results.push((path.clone(), lines));
+	}
+	Ok(results)
+}
+
+/// The options of `compile`, which are those of the command line program
+pub struct CompileOptions {
+	args: args::Args,
+}
+
+impl CompileOptions {
+	/// Parse the options from command line arguments (without the program name and input paths),
+	/// such as `["--format", "bvh"]`. Options which name output or profile files cannot be used.
+	pub fn parse<I, T>(options: I) -> Result<CompileOptions, CompileError>
+	where
+		I: IntoIterator<Item = T>,
+		T: Into<std::ffi::OsString> + Clone,
+	{
+		use clap::Parser;
+		let argv = ["scene-builder".into(), SCENE_PATH.into()]
+			.into_iter()
+			.chain(options.into_iter().map(Into::into));
+		let args =
+			args::Args::try_parse_from(argv).map_err(|e| CompileError::Usage(e.to_string()))?;
+		if !args.out.is_empty()
+			|| args.check
+			|| args.split_output
+			|| args.report_json
+			|| args.profile.is_some()
+		{
+			return Err(CompileError::Usage(
+				"Options 'out', 'check', 'split-output', 'report-json', and 'profile' cannot be used \
+				 when compiling in memory!"
+					.to_string(),
+			));
+		}
+		Ok(CompileOptions { args })
+	}
+
+	/// Set the output format to compile to
+	pub fn format(mut self, format: args::OutputFormat) -> CompileOptions {
+		self.args.format = format;
+		self
+	}
+
+	/// Set whether the root is boxed even if a single node would suffice, as --root does
+	pub fn root(mut self, root: bool) -> CompileOptions {
+		self.args.root = root;
+		self
+	}
+
+	/// Set whether any warning is an error, as --strict does
+	pub fn strict(mut self, strict: bool) -> CompileOptions {
+		self.args.strict = strict;
+		self
+	}
+}
+
+/// The options of an empty command line: verifying the scene only
+impl Default for CompileOptions {
+	fn default() -> CompileOptions {
+		let args = args::Args {
+			input: vec![SCENE_PATH.to_string()],
+			..args::Args::default()
+		};
+		CompileOptions { args }
+	}
+}
+
+/// The result of `compile`
+pub struct CompileOutput {
+	/// The lines of output in the requested format (empty in verification mode, unless a listing
+	/// such as --tree is requested)
+	pub lines: Vec<String>,
+	/// The warnings reported while compiling
+	pub warnings: Vec<String>,
+}
+
+/// The path which errors name for the scene given to `compile`
+const SCENE_PATH: &str = "<scene>";
+
+/// Compile the scene held by the YAML text, returning the output in memory. Nothing is read from
+/// the filesystem, so the scene cannot include other files, and nothing is printed.
+pub fn compile(scene_yaml: &str, options: CompileOptions) -> Result<CompileOutput, CompileError> {
+	let args = &options.args;
+	let docs = load::parse_documents(SCENE_PATH, scene_yaml).map_err(CompileError::Scene)?;
+	let doc = load::single_document(SCENE_PATH, docs).map_err(CompileError::Scene)?;
+	let mut reporter = report::Reporter::new();
+	let outputs = [(String::new(), args.format)];
+	let verify = args.format == args::OutputFormat::Verify;
+	let includes = ir::Includes::refused();
+	let mut results = compile_including(
+		args,
+		0,
+		vec![doc],
+		includes,
+		&outputs,
+		verify,
+		&mut reporter,
+	)?;
+	check_strict(args, &reporter)?;
+	Ok(CompileOutput {
+		lines: results.pop().map(|(_, lines)| lines).unwrap_or_default(),
+		warnings: reporter.warnings(),
+	})
+}
+
+/// Reconstruct the scene of BVH output (as compiled with `--format bvh`) and write it as BVH again
+/// with the given options. Given the options which compiled it, the output matches the input.
+pub fn recompile_bvh(
+	bvh_json: &str,
+	options: CompileOptions,
+) -> Result<CompileOutput, CompileError> {
+	let scene = bvh::from_bvh(bvh_json).map_err(CompileError::Scene)?;
+	let mut reporter = report::Reporter::new();
+	let lines = bvh::to_bvh(&scene, &options.args, &mut reporter);
+	check_strict(&options.args, &reporter)?;
+	Ok(CompileOutput {
+		lines,
+		warnings: reporter.warnings(),
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn empty_scene() {
+		use clap::Parser;
+		let dir =
+			std::env::temp_dir().join(format!("scene-builder-{}-empty-scene", std::process::id()));
+		std::fs::create_dir_all(&dir).unwrap();
+		let path = dir.join("empty.yaml");
+		let path = path.to_str().unwrap();
+		std::fs::write(path, "").unwrap();
+		let args = args::Args::parse_from(["scene-builder", path, "-f", "bvh"]);
+		let mut reporter = report::Reporter::new();
+		let docs = single_documents(&args, load_inputs(&args).unwrap()).unwrap();
+		let mut scene = build_scene(&args, 0, docs, ir::Includes::new(), &mut reporter).unwrap();
+		std::fs::remove_dir_all(&dir).unwrap();
+		assert_eq!(
+			reporter.warnings(),
+			vec!["The scene is empty, since its world holds no objects!"]
+		);
+
+		transform::transform(&mut scene, &args, true, &mut reporter).unwrap();
+		let bvh = bvh::to_bvh(&scene, &args, &mut reporter);
+		assert!(!bvh.iter().any(|line| line.contains("tlas")));
+		let obj = obj::to_obj(&scene, &args, &mut reporter);
+		assert!(!obj.iter().any(|line| line.starts_with("o ")));
+	}
+
+	#[test]
+	fn strict_fails_on_warning() {
+		use clap::Parser;
+		let docs = load::load_from_str("strip: [[0, 0, 0], [1, 0, 0], [0, 1, 0]]\ncolor: [255, 0]")
+			.unwrap();
+		let scene = ir::to_ir(&docs[0]).unwrap();
+		for (strict, fails) in [(false, false), (true, true)] {
+			let mut argv = vec!["scene-builder", "in.yaml", "-f", "obj"];
+			if strict {
+				argv.push("--strict");
+			}
+			let args = args::Args::parse_from(argv);
+			let mut reporter = report::Reporter::new();
+			obj::to_obj(&scene, &args, &mut reporter);
+			let res = check_strict(&args, &reporter);
+			assert_eq!(res.is_err(), fails);
+		}
+	}
+
+	#[test]
+	fn mixed_targets() {
+		use clap::Parser;
+		let text = "data:\n- sphere: [0, 0, 0]\n  radius: 1\n- strip: [[0, 0, 0], [1, 0, 0], [0, 1, 0], [1, \
+		            1, 0]]";
+		let args = args::Args::parse_from(["scene-builder", "in.yaml"]);
+		let scene = || ir::to_ir(&load::load_from_str(text).unwrap()[0]).unwrap();
+		let compiled = |outputs: &[(String, args::OutputFormat)]| {
+			compile_scene(&args, scene(), outputs, false, &mut report::Reporter::new()).unwrap()
+		};
+		let obj = ("out.obj".to_string(), args::OutputFormat::Obj);
+		let bvh = ("out.json".to_string(), args::OutputFormat::Bvh);
+		let alone = compiled(std::slice::from_ref(&obj));
+		let both = compiled(&[bvh.clone(), obj]);
+		// The OBJ output is the same as when written alone, with neither the sphere nor the strip
+		// changed for the BVH target
+		assert_eq!(both[1], alone[0]);
+		assert_eq!(both[0], compiled(&[bvh])[0]);
+	}
+
+	#[test]
+	fn bounds_shared() {
+		use clap::Parser;
+		let text = "data:\n- strip: [[0, 0, 0], [1, 0, 0], [0, 1, 0]]\n- instance:\n    strip: [[0, 0, \
+		            0], [1, 0, 0], [0, 1, 0]]\n  translate: [0, 0, 1]";
+		let args = args::Args::parse_from(["scene-builder", "in.yaml", "--stats"]);
+		let bounds_set = |outputs: &[(String, args::OutputFormat)]| {
+			let scene = ir::to_ir(&load::load_from_str(text).unwrap()[0]).unwrap();
+			transform::BOUNDS_SET.with(|count| count.set(0));
+			compile_scene(&args, scene, outputs, false, &mut report::Reporter::new()).unwrap();
+			transform::BOUNDS_SET.with(|count| count.get())
+		};
+		let obj = ("out.obj".to_string(), args::OutputFormat::Obj);
+		let bvh = ("out.json".to_string(), args::OutputFormat::Bvh);
+		// Bounds are computed once for the scene, however many outputs (and stats) read them
+		let once = bounds_set(std::slice::from_ref(&obj));
+		assert!(once > 0);
+		assert_eq!(bounds_set(&[bvh, obj]), once);
+	}
+
+	#[test]
+	fn extract_in_radians() {
+		use clap::Parser;
+		let args = args::Args::parse_from([
+			"scene-builder",
+			"in.yaml",
+			"--angle-unit",
+			"radians",
+			"--extract",
+			"inner",
+			"--keep-world-transform",
+		]);
+		let docs = load::load_from_str(
+			"data:\n- instance:\n    instance:\n      strip: [[1, 0, 0], [2, 0, 0], [1, 1, 0]]\n    \
+			 name: inner\n  rotate: [0, 0, 1.5707963267948966]",
+		)
+		.unwrap();
+		let scene = build_scene(
+			&args,
+			0,
+			docs,
+			ir::Includes::new(),
+			&mut report::Reporter::new(),
+		)
+		.unwrap();
+		let ir::Node::Instance(idx) = scene.world else {
+			panic!("The extracted strip should keep its transform in an instance!");
+		};
+		// A quarter turn about z takes +x to -y
+		let turned = scene.instances[idx].obj_to_world()
+			* ir::homogenize_pt(&nalgebra::Vector3::new(1.0, 0.0, 0.0));
+		assert!(
+			(turned - nalgebra::Vector3::new(0.0, -1.0, 0.0)).norm() < 1e-9,
+			"{turned}"
+		);
+	}
+
+	#[test]
+	fn error_kinds() {
+		use clap::Parser;
+		let doc = || load::load_from_str("point: [0, 0, 0]").unwrap();
+		let bvh = [("out.json".to_string(), args::OutputFormat::Bvh)];
+		let compiled = |argv: &[&str]| {
+			let args = args::Args::parse_from(["scene-builder", "in.yaml"].iter().chain(argv));
+			compile_documents(&args, 0, doc(), &bvh, false, &mut report::Reporter::new())
+		};
+		let err = compiled(&["--raw"]).err().unwrap();
+		assert!(matches!(err, CompileError::Usage(_)), "{err:?}");
+		let err = compiled(&["--profile", "missing-profile.yaml"])
+			.err()
+			.unwrap();
+		assert!(matches!(err, CompileError::Input(_)), "{err:?}");
+	}
+
+	#[test]
+	fn compile_in_memory() {
+		let options = CompileOptions::parse(["--format", "obj"]).unwrap();
+		let out = compile("strip: [[0, 0, 0], [1, 0, 0], [0, 1, 0]]", options).unwrap();
+		assert!(out.lines.contains(&"f -3 -2 -1".to_string()));
+		assert!(out.warnings.is_empty());
+
+		let err = compile("strip: [[0, 0, 0],", CompileOptions::default())
+			.err()
+			.unwrap();
+		assert!(matches!(err, CompileError::Scene(_)), "{err:?}");
+		for options in [["--out", "out.obj"], ["--profile", "profile.yaml"]] {
+			let err = CompileOptions::parse(options).err().unwrap();
+			assert!(matches!(err, CompileError::Usage(_)), "{err:?}");
+		}
+		// Nothing is read from the filesystem, even a file which would exist
+		let err = compile(
+			"include: [Cargo.toml]
+point: [0, 0, 0]",
+			CompileOptions::default(),
+		)
+		.err()
+		.unwrap();
+		assert!(
+			err.message().contains("Field `include` cannot be used"),
+			"{err:?}"
+		);
+
+		// The default options are those of an empty command line
+		let parsed = CompileOptions::parse(Vec::<String>::new()).unwrap();
+		let default = CompileOptions::default();
+		assert_eq!(format!("{:?}", default.args), format!("{:?}", parsed.args));
+		let options = CompileOptions::default()
+			.format(args::OutputFormat::Bvh)
+			.root(true);
+		assert_eq!(options.args.format, args::OutputFormat::Bvh);
+		assert!(options.args.root);
+	}
+}
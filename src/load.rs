@@ -0,0 +1,525 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::CompileError;
+use yaml_rust2::Yaml;
+use yaml_rust2::parser::{Event, MarkedEventReceiver, Parser};
+use yaml_rust2::scanner::{Marker, TScalarStyle};
+
+/// A position in the source text. Both line and column are 1-indexed.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Location {
+	pub line: usize,
+	pub col: usize,
+}
+
+impl Location {
+	fn from_marker(mark: &Marker) -> Location {
+		Location {
+			line: mark.line(),
+			col: mark.col() + 1,
+		}
+	}
+}
+
+impl fmt::Display for Location {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "line {}, column {}", self.line, self.col)
+	}
+}
+
+#[derive(Clone)]
+pub enum Value {
+	Real(String),
+	Integer(i64),
+	String(String),
+	Boolean(bool),
+	Array(Vec<Marked>),
+	Hash(Vec<(Marked, Marked)>),
+	Null,
+}
+
+/// A YAML value and the location in the source where it appeared.
+#[derive(Clone)]
+pub struct Marked {
+	pub value: Value,
+	pub loc: Location,
+	/// The id of the anchor which this value defines or is an alias of (0 if neither). All values
+	/// with the same nonzero id are the same value.
+	pub anchor: usize,
+}
+
+/// Whether the two values are equal scalars (as is needed to detect duplicate mapping keys)
+fn same_scalar(a: &Value, b: &Value) -> bool {
+	match (a, b) {
+		(Value::Real(a), Value::Real(b)) => a == b,
+		(Value::Integer(a), Value::Integer(b)) => a == b,
+		(Value::String(a), Value::String(b)) => a == b,
+		(Value::Boolean(a), Value::Boolean(b)) => a == b,
+		(Value::Null, Value::Null) => true,
+		_ => false,
+	}
+}
+
+/// Builds documents from the YAML parser's events, saving the location of each value.
+struct Loader {
+	docs: Vec<Marked>,
+	/// Aggregates currently being built, with their anchor ids and (for hashes) any key awaiting
+	/// its value
+	stack: Vec<(Marked, usize, Option<Marked>)>,
+	anchors: HashMap<usize, Marked>,
+	error: Option<String>,
+}
+
+impl Loader {
+	fn insert(&mut self, mut node: Marked, anchor: usize) -> Result<(), String> {
+		// valid anchor ids start from 1
+		if anchor > 0 {
+			node.anchor = anchor;
+			self.anchors.insert(anchor, node.clone());
+		}
+		let Some((parent, _, key)) = self.stack.last_mut() else {
+			self.docs.push(node);
+			return Ok(());
+		};
+		match &mut parent.value {
+			Value::Array(vals) => vals.push(node),
+			Value::Hash(entries) => match key.take() {
+				None => *key = Some(node),
+				Some(key) => {
+					if entries
+						.iter()
+						.any(|(k, _)| same_scalar(&k.value, &key.value))
+					{
+						return Err(format!("Duplicated key in mapping at {}!", key.loc));
+					}
+					entries.push((key, node));
+				},
+			},
+			_ => unreachable!("Only aggregates are pushed to the stack!"),
+		}
+		Ok(())
+	}
+
+	fn on_event_impl(&mut self, ev: Event, loc: Location) -> Result<(), String> {
+		match ev {
+			Event::SequenceStart(anchor, _) => {
+				let node = Marked {
+					value: Value::Array(vec![]),
+					loc,
+					anchor: 0,
+				};
+				self.stack.push((node, anchor, None));
+			},
+			Event::MappingStart(anchor, _) => {
+				let node = Marked {
+					value: Value::Hash(vec![]),
+					loc,
+					anchor: 0,
+				};
+				self.stack.push((node, anchor, None));
+			},
+			Event::SequenceEnd | Event::MappingEnd => {
+				let (node, anchor, _) = self.stack.pop().unwrap();
+				self.insert(node, anchor)?;
+			},
+			Event::Scalar(text, style, anchor, tag) => {
+				let is_str = match tag {
+					Some(tag) => tag.handle == "tag:yaml.org,2002:" && tag.suffix == "str",
+					None => false,
+				};
+				let value = if style != TScalarStyle::Plain || is_str {
+					Value::String(text)
+				} else {
+					match Yaml::from_str(&text) {
+						Yaml::Real(v) => Value::Real(v),
+						Yaml::Integer(v) => Value::Integer(v),
+						Yaml::Boolean(v) => Value::Boolean(v),
+						Yaml::Null => Value::Null,
+						_ => Value::String(text),
+					}
+				};
+				let node = Marked {
+					value,
+					loc,
+					anchor: 0,
+				};
+				self.insert(node, anchor)?;
+			},
+			Event::Alias(id) => {
+				let node = match self.anchors.get(&id) {
+					Some(node) => Marked {
+						value: node.value.clone(),
+						loc,
+						anchor: node.anchor,
+					},
+					None => return Err(format!("Unknown alias at {loc}!")),
+				};
+				self.insert(node, 0)?;
+			},
+			_ => {}, // Other events (document and stream bounds) need no handling
+		}
+		Ok(())
+	}
+}
+
+impl MarkedEventReceiver for Loader {
+	fn on_event(&mut self, ev: Event, mark: Marker) {
+		if self.error.is_some() {
+			return;
+		}
+		if let Err(e) = self.on_event_impl(ev, Location::from_marker(&mark)) {
+			self.error = Some(e);
+		}
+	}
+}
+
+/// Load all YAML documents in the given text.
+pub fn load_from_str(text: &str) -> Result<Vec<Marked>, String> {
+	let mut loader = Loader {
+		docs: vec![],
+		stack: vec![],
+		anchors: HashMap::new(),
+		error: None,
+	};
+	let mut parser = Parser::new_from_str(text);
+	if let Err(e) = parser.load(&mut loader, true) {
+		return Err(e.to_string());
+	}
+	match loader.error {
+		Some(e) => Err(e),
+		None => Ok(loader.docs),
+	}
+}
+
+/// Builds a document from JSON text, saving the location of each value. Unlike YAML, JSON has no
+/// anchors, and numeric types are decided by syntax alone: a number is an integer unless it has a
+/// fraction or exponent.
+struct JsonLoader<'a> {
+	text: &'a [u8],
+	at: usize,
+	loc: Location,
+}
+
+impl<'a> JsonLoader<'a> {
+	fn bump(&mut self) {
+		if self.text[self.at] == b'\n' {
+			self.loc.line += 1;
+			self.loc.col = 1;
+		} else if self.text[self.at] & 0xC0 != 0x80 {
+			// Continuation bytes of a UTF-8 character do not start a new column
+			self.loc.col += 1;
+		}
+		self.at += 1;
+	}
+
+	fn peek(&mut self) -> Option<u8> {
+		while self.at < self.text.len() && self.text[self.at].is_ascii_whitespace() {
+			self.bump();
+		}
+		self.text.get(self.at).copied()
+	}
+
+	fn expect(&mut self, c: u8) -> Result<(), String> {
+		if self.peek() == Some(c) {
+			self.bump();
+			Ok(())
+		} else {
+			Err(format!("Expected '{}' at {}!", c as char, self.loc))
+		}
+	}
+
+	/// Fail if the input ends before the aggregate of the given kind, which started at `loc`, closes.
+	fn check_open(&mut self, kind: &str, loc: Location) -> Result<(), String> {
+		match self.peek() {
+			None => Err(format!(
+				"Unexpected end of input in the {kind} starting at {loc}!"
+			)),
+			Some(_) => Ok(()),
+		}
+	}
+
+	fn marked(&self, value: Value, loc: Location) -> Marked {
+		Marked {
+			value,
+			loc,
+			anchor: 0,
+		}
+	}
+
+	fn parse_value(&mut self) -> Result<Marked, String> {
+		let first = self.peek();
+		let loc = self.loc;
+		match first {
+			None => Err(format!("Unexpected end of input at {loc}!")),
+			Some(b'{') => {
+				self.bump();
+				let mut entries: Vec<(Marked, Marked)> = vec![];
+				if self.peek() == Some(b'}') {
+					self.bump();
+					return Ok(self.marked(Value::Hash(entries), loc));
+				}
+				loop {
+					self.check_open("object", loc)?;
+					if self.peek() != Some(b'"') {
+						return Err(format!("Expected a string key at {}!", self.loc));
+					}
+					let key = self.parse_value()?;
+					if entries
+						.iter()
+						.any(|(k, _)| same_scalar(&k.value, &key.value))
+					{
+						return Err(format!("Duplicated key in mapping at {}!", key.loc));
+					}
+					self.check_open("object", loc)?;
+					self.expect(b':')?;
+					self.check_open("object", loc)?;
+					let value = self.parse_value()?;
+					entries.push((key, value));
+					self.check_open("object", loc)?;
+					if self.peek() == Some(b',') {
+						self.bump();
+					} else {
+						self.expect(b'}')?;
+						return Ok(self.marked(Value::Hash(entries), loc));
+					}
+				}
+			},
+			Some(b'[') => {
+				self.bump();
+				let mut vals = vec![];
+				if self.peek() == Some(b']') {
+					self.bump();
+					return Ok(self.marked(Value::Array(vals), loc));
+				}
+				loop {
+					self.check_open("array", loc)?;
+					vals.push(self.parse_value()?);
+					self.check_open("array", loc)?;
+					if self.peek() == Some(b',') {
+						self.bump();
+					} else {
+						self.expect(b']')?;
+						return Ok(self.marked(Value::Array(vals), loc));
+					}
+				}
+			},
+			Some(b'"') => {
+				let text = self.parse_string()?;
+				Ok(self.marked(Value::String(text), loc))
+			},
+			Some(_) => {
+				// Either a number or a keyword. Take everything up to the next delimiter.
+				let start = self.at;
+				while self.at < self.text.len()
+					&& !matches!(self.text[self.at], b',' | b':' | b']' | b'}')
+					&& !self.text[self.at].is_ascii_whitespace()
+				{
+					self.bump();
+				}
+				let token = String::from_utf8_lossy(&self.text[start..self.at]);
+				let value = match token.as_ref() {
+					"true" => Value::Boolean(true),
+					"false" => Value::Boolean(false),
+					"null" => Value::Null,
+					_ if token.contains(['.', 'e', 'E']) && token.parse::<f64>().is_ok() => {
+						Value::Real(token.to_string())
+					},
+					_ => match token.parse::<i64>() {
+						// A negative zero is kept as a real so that its sign survives
+						Ok(0) if token.starts_with('-') => Value::Real(token.to_string()),
+						Ok(val) => Value::Integer(val),
+						// An integer too large for i64 is kept as a real, as YAML does
+						Err(_) if is_integer(token.strip_prefix('-').unwrap_or(&token)) => {
+							Value::Real(token.to_string())
+						},
+						Err(_) => {
+							return Err(format!("Could not parse value \"{token}\" at {loc}!"));
+						},
+					},
+				};
+				Ok(self.marked(value, loc))
+			},
+		}
+	}
+
+	fn parse_string(&mut self) -> Result<String, String> {
+		self.expect(b'"')?;
+		let mut bytes = vec![];
+		loop {
+			match self.text.get(self.at) {
+				None => return Err(format!("Unterminated string at {}!", self.loc)),
+				Some(b'"') => {
+					self.bump();
+					break;
+				},
+				Some(b'\\') => {
+					let loc = self.loc;
+					let escaped = match self.text.get(self.at + 1) {
+						Some(b'n') => '\n',
+						Some(b't') => '\t',
+						Some(b'r') => '\r',
+						Some(b'b') => '\u{8}',
+						Some(b'f') => '\u{c}',
+						Some(c @ (b'"' | b'\\' | b'/')) => *c as char,
+						Some(b'u') => {
+							self.bump();
+							self.bump();
+							let c = self.parse_unicode(loc)?;
+							bytes.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+							continue;
+						},
+						_ => return Err(format!("Unsupported escape sequence at {loc}!")),
+					};
+					bytes.push(escaped as u8);
+					self.bump();
+					self.bump();
+				},
+				Some(c) => {
+					bytes.push(*c);
+					self.bump();
+				},
+			}
+		}
+		String::from_utf8(bytes).map_err(|_| format!("Invalid UTF-8 string at {}!", self.loc))
+	}
+
+	/// The four hex digits of a `\u` escape, as a UTF-16 code unit
+	fn parse_hex4(&mut self, loc: Location) -> Result<u32, String> {
+		let digits = self.text.get(self.at..self.at + 4).unwrap_or_default();
+		if digits.len() != 4 || !digits.iter().all(u8::is_ascii_hexdigit) {
+			return Err(format!("Invalid unicode escape sequence at {loc}!"));
+		}
+		let unit = u32::from_str_radix(std::str::from_utf8(digits).unwrap(), 16).unwrap();
+		for _ in 0..4 {
+			self.bump();
+		}
+		Ok(unit)
+	}
+
+	/// The character of a `\u` escape (whose `\u` at `loc` was just consumed). A character outside
+	/// the basic multilingual plane is escaped as a surrogate pair, where the high surrogate must be
+	/// followed directly by an escape of the low surrogate.
+	fn parse_unicode(&mut self, loc: Location) -> Result<char, String> {
+		let high = self.parse_hex4(loc)?;
+		let code = match high {
+			0xD800..=0xDBFF => {
+				if self.text.get(self.at..self.at + 2) != Some(b"\\u") {
+					return Err(format!("Unpaired surrogate in unicode escape at {loc}!"));
+				}
+				self.bump();
+				self.bump();
+				let low = self.parse_hex4(loc)?;
+				if !(0xDC00..=0xDFFF).contains(&low) {
+					return Err(format!("Unpaired surrogate in unicode escape at {loc}!"));
+				}
+				0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00)
+			},
+			0xDC00..=0xDFFF => {
+				return Err(format!("Unpaired surrogate in unicode escape at {loc}!"));
+			},
+			_ => high,
+		};
+		// Surrogates are excluded above, so every remaining code is a character
+		Ok(char::from_u32(code).unwrap())
+	}
+}
+
+fn is_integer(digits: &str) -> bool {
+	!digits.is_empty() && digits.bytes().all(|b| b.is_ascii_digit())
+}
+
+/// Load the single JSON document in the given text.
+pub fn load_json_from_str(text: &str) -> Result<Marked, String> {
+	let mut loader = JsonLoader {
+		text: text.as_bytes(),
+		at: 0,
+		loc: Location { line: 1, col: 1 },
+	};
+	let doc = loader.parse_value()?;
+	if loader.peek().is_some() {
+		return Err(format!(
+			"Unexpected content after the document at {}!",
+			loader.loc
+		));
+	}
+	Ok(doc)
+}
+
+/// Read and parse all YAML documents held by the file at `path`. A gzip-compressed file (recognized
+/// by its magic header) is decompressed first. An empty file holds one empty scene. A file with the
+/// `.json` extension is parsed as a single JSON document instead. Failing to read the file is an
+/// input error, whereas failing to decompress, decode or parse its content is a scene error.
+pub fn load_documents(path: &str) -> Result<Vec<Marked>, CompileError> {
+	let file = read_input(path)?;
+	parse_documents(path, &file).map_err(CompileError::Scene)
+}
+
+/// Read the text of the file at `path`, decompressing it first if it is gzip-compressed. Errors are
+/// classified as `load_documents` describes.
+pub fn read_input(path: &str) -> Result<String, CompileError> {
+	let mut data = match std::fs::read(path) {
+		Ok(got_data) => got_data,
+		Err(_) => {
+			return Err(CompileError::Input(format!(
+				"Could not read input file: \"{path}\"!"
+			)));
+		},
+	};
+	if crate::gzip::is_gzip(&data) {
+		data = match crate::gzip::decompress(&data) {
+			Ok(got_data) => got_data,
+			Err(e) => {
+				return Err(CompileError::Scene(format!(
+					"Could not decompress input file \"{path}\": {e}"
+				)));
+			},
+		};
+	}
+	match String::from_utf8(data) {
+		Ok(got_text) => Ok(got_text),
+		Err(_) => Err(CompileError::Scene(format!(
+			"Input file \"{path}\" is not valid UTF-8!"
+		))),
+	}
+}
+
+/// Parse all documents in the text read from the file at `path`, as `load_documents` does.
+pub fn parse_documents(path: &str, file: &str) -> Result<Vec<Marked>, String> {
+	if path.ends_with(".json") {
+		return match load_json_from_str(file) {
+			Ok(doc) => Ok(vec![doc]),
+			Err(e) => Err(format!("Could not parse JSON from file \"{path}\": {e}")),
+		};
+	}
+	let docs = match load_from_str(file) {
+		Ok(docs) => docs,
+		Err(e) => return Err(format!("Could not parse YAML from file \"{path}\": {e}")),
+	};
+
+	if docs.is_empty() {
+		return Ok(vec![Marked {
+			value: Value::Hash(vec![]),
+			loc: Location { line: 1, col: 1 },
+			anchor: 0,
+		}]);
+	}
+	Ok(docs)
+}
+
+/// Read and parse the single YAML document held by the file at `path`, as `load_documents` does.
+pub fn load_file(path: &str) -> Result<Marked, CompileError> {
+	single_document(path, load_documents(path)?).map_err(CompileError::Scene)
+}
+
+/// The only document of those loaded from the file at `path`, failing if there are several.
+pub fn single_document(path: &str, mut docs: Vec<Marked>) -> Result<Marked, String> {
+	let num_docs = docs.len();
+	if num_docs != 1 {
+		return Err(format!(
+			"Incompatible number of YAML documents found in \"{path}\"! 1 expected, but \
+			 {num_docs} seen."
+		));
+	}
+	Ok(docs.remove(0))
+}
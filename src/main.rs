@@ -1,115 +1,331 @@
-mod args;
-mod bvh;
-mod ir;
-mod obj;
-mod report;
-mod transform;
-
-fn main() -> Result<(), String> {
+use scene_builder::{
+	CompileError, args, check_strict, compile_documents, load_inputs, report, single_documents,
+};
+
+fn main() {
 	use clap::Parser;
 	let args = args::Args::parse();
 
+	// Messages are collected while running and only printed at the end
+	let mut reporter = report::Reporter::new();
+	reporter.quiet = args.quiet;
+	reporter.progress = args.progress;
+	let res = run(&args, &mut reporter);
+	reporter.flush();
+	if let Err(failure) = res {
+		// Shown as returning the message from main would show it
+		eprintln!("Error: {:?}", failure.message());
+		std::process::exit(failure.exit_code());
+	}
+}
+
+/// The format to write the output at `path` in: `format` unless it is verification, in which case
+/// the format is deduced from the path's file extension.
+fn out_format(format: args::OutputFormat, path: &str) -> Result<args::OutputFormat, String> {
 	use args::OutputFormat;
-	let out_format = if args.format != OutputFormat::Verify || args.out.is_empty() {
-		args.format
-	} else if args.out.ends_with(".json")
-		|| args.out.ends_with(".yaml")
-		|| args.out.ends_with(".yml")
-	{
-		OutputFormat::Bvh
-	} else if args.out.ends_with(".obj") {
-		OutputFormat::Obj
+	if format != OutputFormat::Verify {
+		Ok(format)
+	} else if path.ends_with(".json") || path.ends_with(".yaml") || path.ends_with(".yml") {
+		Ok(OutputFormat::Bvh)
+	} else if path.ends_with(".obj") {
+		Ok(OutputFormat::Obj)
+	} else if path.ends_with(".csv") {
+		Ok(OutputFormat::Csv)
+	} else if path.ends_with(".dot") || path.ends_with(".gv") {
+		Ok(OutputFormat::Dot)
 	} else {
-		return Err(String::from("Cannot deduce output type!"));
+		Err(format!("Cannot deduce output type of \"{path}\"!"))
+	}
+}
+
+/// Write the lines to the file at `path`, or to stdout if `path` is empty.
+fn write_lines(path: &str, lines: &[String]) -> Result<(), String> {
+	if path.is_empty() {
+		for line in lines.iter() {
+			println!("{}", line);
+		}
+		return Ok(());
+	}
+	use std::fs::File;
+	let mut writer = match File::create(path) {
+		Ok(f) => f,
+		Err(_) => return Err(format!("Could not write output to file \"{path}\"!")),
 	};
+	use std::io::Write;
+	for line in lines.iter() {
+		match writeln!(writer, "{}", line) {
+			Ok(_) => {},
+			Err(_) => {
+				return Err(format!("Failure in writing output to file \"{path}\"!"));
+			},
+		}
+	}
+	Ok(())
+}
 
-	if !(args.format == OutputFormat::Verify || args.format == OutputFormat::Obj) && args.raw {
-		return Err(String::from(
-			"Cannot use command line option 'raw' when outputting BVH data!",
+/// Compare the lines against those of the existing file at `path`, failing at the first difference.
+fn check_lines(path: &str, lines: &[String]) -> Result<(), String> {
+	let existing = match std::fs::read_to_string(path) {
+		Ok(text) => text,
+		Err(_) => return Err(format!("Could not read file \"{path}\" to check against!")),
+	};
+	let mut existing = existing.lines();
+	for (i, line) in lines.iter().enumerate() {
+		match existing.next() {
+			Some(old) if old == line => {},
+			Some(old) => {
+				return Err(format!(
+					"Output differs from \"{path}\" at line {}: \"{old}\" was generated as \
+					 \"{line}\"!",
+					i + 1
+				));
+			},
+			None => {
+				return Err(format!(
+					"Output differs from \"{path}\" at line {}, which the file does not have!",
+					i + 1
+				));
+			},
+		}
+	}
+	if existing.next().is_some() {
+		return Err(format!(
+			"Output differs from \"{path}\" at line {}, which was not generated!",
+			lines.len() + 1
 		));
 	}
+	Ok(())
+}
 
-	// parse file and check syntax
-	let path = args.input.clone();
-	let file = match std::fs::read_to_string(&path) {
-		Ok(got_text) => got_text,
-		Err(_) => return Err(format!("Could not read input file: \"{path}\"!")),
+/// The path for the output of document `doc` in place of `path`, numbered before its extension.
+/// Stdout (an empty path) is shared.
+fn numbered(path: &str, doc: usize) -> String {
+	if path.is_empty() {
+		return String::new();
+	}
+	let path = std::path::Path::new(path);
+	let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+	let name = match path.extension() {
+		Some(ext) => format!("{stem}_{doc}.{}", ext.to_string_lossy()),
+		None => format!("{stem}_{doc}"),
 	};
-	use yaml_rust2::YamlLoader;
-	let docs = match YamlLoader::load_from_str(file.as_str()) {
-		Ok(docs) => docs,
-		Err(_) => return Err("Could not parse YAML from given file!".to_string()),
+	path.with_file_name(name).to_string_lossy().into_owned()
+}
+
+fn run(args: &args::Args, reporter: &mut report::Reporter) -> Result<(), CompileError> {
+	use args::OutputFormat;
+	// Each output is written in the format given or, if none is, the format its path implies. With
+	// no output path, the result is printed to stdout.
+	let outputs = if args.split_output {
+		if args.out.len() != 1 || !matches!(args.format, OutputFormat::Obj | OutputFormat::Verify) {
+			return Err(CompileError::Usage(String::from(
+				"Command line option 'split-output' requires a single OBJ output directory!",
+			)));
+		}
+		vec![(args.out[0].as_str(), OutputFormat::Obj)]
+	} else if args.out.is_empty() {
+		vec![("", args.format)]
+	} else {
+		let mut outputs = vec![];
+		for path in args.out.iter() {
+			outputs.push((
+				path.as_str(),
+				out_format(args.format, path).map_err(CompileError::Usage)?,
+			));
+		}
+		outputs
 	};
 
-	let num_docs = docs.len();
-	if num_docs != 1 {
-		return Err(format!(
-			"Incompatible number of YAML documents found in input! 1 expected, but {num_docs} \
-			 seen."
-		));
+	if args.check && args.out.is_empty() {
+		return Err(CompileError::Usage(String::from(
+			"Command line option 'check' requires the output file(s) to compare against!",
+		)));
 	}
 
-	// Convert from input data to IR data by checking grammar
-	let mut scene = ir::to_ir(&docs[0])?;
+	if args.format == OutputFormat::Bvh && args.raw {
+		return Err(CompileError::Usage(String::from(
+			"Cannot use command line option 'raw' when outputting BVH data!",
+		)));
+	}
 
-	// Verify instancing levels if requested
-	if args.instancing > 0 {
-		ir::verify_instancing(&scene, args.instancing)?;
+	let world_from = if args.world_from.is_empty() {
+		args.input.len() - 1
+	} else {
+		match args.input.iter().position(|path| *path == args.world_from) {
+			Some(at) => at,
+			None => {
+				return Err(CompileError::Usage(format!(
+					"Cannot take world from \"{}\" since it is not an input file!",
+					args.world_from
+				)));
+			},
+		}
+	};
+
+	if let Some((path, _)) = outputs
+		.iter()
+		.find(|(path, format)| *format == OutputFormat::Verify && !path.is_empty())
+	{
+		return Err(CompileError::Usage(format!(
+			"Cannot print to \"{path}\" because verification mode is enabled!"
+		)));
 	}
+	// Verification mode only holds the single output to stdout, as checked above
+	let verify = outputs[0].1 == OutputFormat::Verify;
 
-	// If we are simply verifying the scene, we are done now.
-	if let OutputFormat::Verify = out_format {
-		if !args.out.is_empty() {
-			return Err(format!(
-				"Cannot print to \"{}\" because verification mode is enabled!",
-				args.out
-			));
+	if args.report_json {
+		if !verify {
+			return Err(CompileError::Usage(String::from(
+				"Cannot use command line option 'report-json' outside of verification mode!",
+			)));
+		}
+		let (lines, failure) = scene_builder::json_report(args, world_from);
+		for line in lines {
+			println!("{}", line);
+		}
+		// The report holds the message, so only the exit code is left to give
+		if let Some(failure) = failure {
+			std::process::exit(failure.exit_code());
 		}
 		return Ok(());
 	}
-	// Otherwise, we want to apply transformations given by the command line arguments. Then we can
-	// translate into the target format.
-	if args.raw {
-		if let OutputFormat::Bvh = out_format {
-			return Err("Cannot use option `raw` with a BVH target!".to_string());
+
+	// A single input may hold several documents, each of which is compiled on its own, with its own
+	// numbered outputs
+	let mut inputs = load_inputs(args)?;
+	let mut results = vec![];
+	if inputs.len() == 1 && inputs[0].len() > 1 {
+		for (i, doc) in inputs.remove(0).into_iter().enumerate() {
+			let outputs: Vec<(String, OutputFormat)> = outputs
+				.iter()
+				.map(|(path, format)| (numbered(path, i), *format))
+				.collect();
+			let compiled =
+				compile_documents(args, world_from, vec![doc], &outputs, verify, reporter)?;
+			for (path, mut lines) in compiled {
+				// Documents printed to stdout are separated
+				if path.is_empty() && i > 0 {
+					lines.insert(0, "---".to_string());
+				}
+				results.push((path, lines));
+			}
 		}
 	} else {
-		// Handle all the box-related transformations
-		transform::transform(
-			&mut scene,
-			&args,
-			out_format == OutputFormat::Bvh || args.split,
-		);
-	}
-
-	let lines = match out_format {
-		OutputFormat::Bvh => bvh::to_bvh(&scene),
-		OutputFormat::Obj => obj::to_obj(&scene),
-		OutputFormat::Verify => panic!("Verify case should have exited earlier!"),
-	};
-	if args.out.is_empty() {
-		for line in lines.iter() {
-			println!("{}", line);
+		let docs = single_documents(args, inputs)?;
+		let outputs: Vec<(String, OutputFormat)> = outputs
+			.iter()
+			.map(|(path, format)| (path.to_string(), *format))
+			.collect();
+		results = compile_documents(args, world_from, docs, &outputs, verify, reporter)?;
+	}
+
+	// All outputs are generated before any is written, so none is written if a warning is fatal
+	check_strict(args, reporter)?;
+	if args.check {
+		for (path, lines) in results.iter() {
+			check_lines(path, lines).map_err(CompileError::Output)?;
 		}
-	} else {
-		use std::fs::File;
-		let mut writer = match File::create(&args.out) {
-			Ok(f) => f,
-			Err(_) => return Err(format!("Could not write output to file \"{}\"!", &args.out)),
-		};
-		use std::io::Write;
-		for line in lines.iter() {
-			match writeln!(writer, "{}", line) {
-				Ok(_) => {},
-				Err(_) => {
-					return Err(format!(
-						"Failure in writing output to file \"{}\"!",
-						&args.out
-					));
-				},
-			}
+		return Ok(());
+	}
+	for (path, lines) in results {
+		if args.split_output
+			&& let Some(dir) = std::path::Path::new(&path).parent()
+			&& std::fs::create_dir_all(dir).is_err()
+		{
+			return Err(CompileError::Output(format!(
+				"Could not create output directory \"{}\"!",
+				dir.display()
+			)));
 		}
+		write_lines(&path, &lines).map_err(CompileError::Output)?;
 	}
 
 	Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn check_against_file() {
+		let dir = std::env::temp_dir().join(format!(
+			"scene-builder-{}-check-against-file",
+			std::process::id()
+		));
+		std::fs::create_dir_all(&dir).unwrap();
+		let path = dir.join("check.obj");
+		let path = path.to_str().unwrap();
+		let lines = vec!["o strip0".to_string(), "v 0 0 0".to_string()];
+		write_lines(path, &lines).unwrap();
+		assert!(check_lines(path, &lines).is_ok());
+
+		let changed = vec!["o strip0".to_string(), "v 0 1 0".to_string()];
+		let err = check_lines(path, &changed).err().unwrap();
+		assert!(err.contains("at line 2"), "{err}");
+		assert!(check_lines(path, &lines[..1]).is_err());
+		std::fs::remove_dir_all(&dir).unwrap();
+	}
+
+	#[test]
+	fn exit_codes() {
+		use clap::Parser;
+		let dir =
+			std::env::temp_dir().join(format!("scene-builder-{}-exit-codes", std::process::id()));
+		std::fs::create_dir_all(&dir).unwrap();
+		let invalid = dir.join("invalid.yaml");
+		let invalid = invalid.to_str().unwrap();
+		write_lines(invalid, &["strip: [[0, 0, 0],".to_string()]).unwrap();
+		let missing = dir.join("missing.yaml");
+		let missing = missing.to_str().unwrap();
+		// Content which cannot be decompressed or decoded is a scene error, like a parse failure
+		let corrupt = dir.join("corrupt.yaml.gz");
+		let corrupt = corrupt.to_str().unwrap();
+		std::fs::write(corrupt, [0x1F, 0x8B, 0x08, 0x00, 0x01]).unwrap();
+		let binary = dir.join("binary.yaml");
+		let binary = binary.to_str().unwrap();
+		std::fs::write(binary, [0xFF, 0xFE, 0x00]).unwrap();
+
+		let code = |input: &str| {
+			let args = args::Args::parse_from(["scene-builder", input]);
+			run(&args, &mut report::Reporter::new())
+				.err()
+				.unwrap()
+				.exit_code()
+		};
+		assert_eq!(code(missing), 3);
+		assert_eq!(code(invalid), 4);
+		assert_eq!(code(corrupt), 4);
+		assert_eq!(code(binary), 4);
+		std::fs::remove_dir_all(dir).unwrap();
+	}
+
+	#[test]
+	fn multiple_documents() {
+		use clap::Parser;
+		let dir = std::env::temp_dir().join(format!(
+			"scene-builder-{}-multiple-documents",
+			std::process::id()
+		));
+		std::fs::create_dir_all(&dir).unwrap();
+		let input = dir.join("batch.yaml");
+		let input = input.to_str().unwrap();
+		write_lines(
+			input,
+			&[
+				"strip: [[0, 0, 0], [1, 0, 0], [0, 1, 0]]".to_string(),
+				"---".to_string(),
+				"point: [0, 0, 0]".to_string(),
+			],
+		)
+		.unwrap();
+		let out = dir.join("out.obj");
+		let args = args::Args::parse_from(["scene-builder", input, "-o", out.to_str().unwrap()]);
+		run(&args, &mut report::Reporter::new()).unwrap();
+		let first = std::fs::read_to_string(dir.join("out_0.obj")).unwrap();
+		let second = std::fs::read_to_string(dir.join("out_1.obj")).unwrap();
+		assert!(first.contains("o strip0") && !first.contains("o point0"));
+		assert!(second.contains("o point0") && !second.contains("o strip0"));
+		std::fs::remove_dir_all(dir).unwrap();
+	}
+}
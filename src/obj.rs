@@ -1,26 +1,88 @@
 use std::collections::HashSet;
 
-use crate::ir::{Node, Point3D, Scene, homogenize, homogenize_pt, new_point};
-use crate::report::warn;
+use crate::args::Args;
+use crate::ir::{
+	Fields, Node, Point3D, Scene, Sphere, Strip, Topology, as_3d, fmt_float, homogenize,
+	homogenize_pt, is_hidden, new_point,
+};
+use crate::report::Reporter;
 use nalgebra::matrix;
 
 struct Palette {
 	pub current: usize,
 	materials: HashSet<usize>,
+	/// The image paths of textured materials, in the order registered
+	textures: Vec<String>,
+	/// Whether the material for each axis drawn by --draw-axes has been registered
+	axes: [bool; 3],
+	/// The number of boxes enclosing the node currently handled
+	pub depth: usize,
+	/// Whether an enclosing instance has overridden the color of everything within it, such that
+	/// updates are ignored
+	pub overridden: bool,
+	/// The smoothing group of the faces which follow, if any is in effect
+	smoothing: Option<usize>,
+	/// The index of the material for depth 0. Those for deeper levels follow it.
+	depth_base: usize,
+	default_color: Point3D,
 }
 impl Palette {
-	pub fn new(lines: &mut Vec<String>, default: usize) -> Palette {
+	/// Create a palette whose default material has the given color, with channels from 0 to 255.
+	/// Materials are indexed by the sequence or string holding their color, the default following
+	/// all sequences and the depth colors following all strings. If `lazy`, the default material is
+	/// only registered once it must replace another, so objects drawn before any color is used have
+	/// no material.
+	pub fn new(lines: &mut Vec<String>, scene: &Scene, color: &[f64; 3], lazy: bool) -> Palette {
+		let default = scene.sequences.len();
 		let mut palette = Palette {
-			current: 0,
+			current: default,
 			materials: HashSet::new(),
+			textures: vec![],
+			axes: [false; 3],
+			depth: 0,
+			overridden: false,
+			smoothing: None,
+			depth_base: default + 1 + scene.strings.len(),
+			default_color: Point3D::from(*color) / 255.0,
 		};
-		// Default color is black
-		palette.register(lines, &new_point(0.0), default);
+		if !lazy {
+			palette.register(lines, &palette.default_color.clone(), default);
+		}
 		palette
 	}
 
+	/// Use the color for the current depth, registering it first if needed. Hues step around the
+	/// color wheel by a fixed amount per level.
+	pub fn use_depth(&mut self, lines: &mut Vec<String>) {
+		let idx = self.depth_base + self.depth;
+		if idx == self.current {
+			return;
+		}
+		if self.materials.contains(&idx) {
+			self.reuse(lines, idx);
+			return;
+		}
+		// Convert the hue (with full saturation and value) to RGB
+		let hue = (self.depth as f64 * 0.15).fract() * 6.0;
+		let rise = hue.fract();
+		let color = match hue as usize {
+			0 => Point3D::new(1.0, rise, 0.0),
+			1 => Point3D::new(1.0 - rise, 1.0, 0.0),
+			2 => Point3D::new(0.0, 1.0, rise),
+			3 => Point3D::new(0.0, 1.0 - rise, 1.0),
+			4 => Point3D::new(rise, 0.0, 1.0),
+			_ => Point3D::new(1.0, 0.0, 1.0 - rise),
+		};
+		self.register(lines, &color, idx);
+	}
+
 	/// Emit the change to a previously defined color
 	pub fn reuse(&mut self, lines: &mut Vec<String>, color: usize) {
+		// Only the default can be reused before it is registered
+		if !self.materials.contains(&color) {
+			self.register(lines, &self.default_color.clone(), color);
+			return;
+		}
 		lines.push(format!("usemtl color{}", color));
 		self.current = color;
 	}
@@ -32,6 +94,55 @@ impl Palette {
 		}
 	}
 
+	/// Put the faces which follow in the smoothing group, or in none, if not already
+	pub fn smooth(&mut self, lines: &mut Vec<String>, group: Option<usize>) {
+		if group != self.smoothing {
+			match group {
+				Some(group) => lines.push(format!("s {group}")),
+				None => lines.push("s off".to_string()),
+			}
+			self.smoothing = group;
+		}
+	}
+
+	/// Use the material which maps the image at the path held by string `str_at`, registering it
+	/// first if needed. The current color is not changed, so it must be reused once done.
+	pub fn use_texture(&mut self, lines: &mut Vec<String>, scene: &Scene, str_at: usize) {
+		let path = &scene.strings[str_at];
+		if let Some(idx) = self.textures.iter().position(|texture| texture == path) {
+			lines.push(format!("usemtl texture{}", idx));
+			return;
+		}
+		let idx = self.textures.len();
+		lines.push("".to_string());
+		lines.push(format!("newmtl texture{}", idx));
+		lines.push("Kd 1 1 1".to_string());
+		lines.push("Ks 0.5 0.5 0.5".to_string());
+		lines.push("Ns 18.0".to_string());
+		lines.push(format!("map_Kd {}", path));
+		lines.push("".to_string());
+		lines.push(format!("usemtl texture{}", idx));
+		self.textures.push(path.clone());
+	}
+
+	/// Use the material for the axis (0 for x, 1 for y, 2 for z), which is red, green, or blue
+	/// respectively, registering it first if needed. As with textures, the current color is not
+	/// changed, so it must be reused once done.
+	pub fn use_axis(&mut self, lines: &mut Vec<String>, axis: usize) {
+		if !self.axes[axis] {
+			let mut color = [0; 3];
+			color[axis] = 1;
+			lines.push("".to_string());
+			lines.push(format!("newmtl axis{}", axis));
+			lines.push(format!("Kd {} {} {}", color[0], color[1], color[2]));
+			lines.push("Ks 0.5 0.5 0.5".to_string());
+			lines.push("Ns 18.0".to_string());
+			lines.push("".to_string());
+			self.axes[axis] = true;
+		}
+		lines.push(format!("usemtl axis{}", axis));
+	}
+
 	/// Register a unique color. Does not check if the color has already been defined. For that, use
 	/// function `update` instead.
 	fn register(&mut self, lines: &mut Vec<String>, color: &Point3D, idx: usize) {
@@ -47,7 +158,16 @@ impl Palette {
 		self.current = idx;
 	}
 
-	pub fn update(&mut self, new: Option<&Node>, lines: &mut Vec<String>, scene: &Scene) -> usize {
+	pub fn update(
+		&mut self,
+		new: Option<&Node>,
+		lines: &mut Vec<String>,
+		scene: &Scene,
+		reporter: &mut Reporter,
+	) -> usize {
+		if self.overridden {
+			return self.current;
+		}
 		match new {
 			None => {},
 			Some(node) => {
@@ -63,7 +183,7 @@ impl Palette {
 								let vals = &scene.sequences[*idx].vals;
 								let len = vals.len();
 								if len != 3 {
-									warn(&format!(
+									reporter.warn(&format!(
 										"`color` is expected to have 3 components! {len} found \
 										 instead."
 									))
@@ -73,7 +193,7 @@ impl Palette {
 									if let Node::Number(f) = vals[i] {
 										fcolor[i] = f / 255.0;
 									} else {
-										warn(&format!(
+										reporter.warn(&format!(
 											"`color` channel {} is expected to be a number!",
 											i
 										))
@@ -83,8 +203,25 @@ impl Palette {
 							}
 						}
 					},
+					Node::String(str_at) => {
+						// Strings are numbered after all sequences and the default color
+						let idx = scene.sequences.len() + 1 + *str_at;
+						if idx != self.current {
+							if self.materials.contains(&idx) {
+								self.reuse(lines, idx);
+							} else {
+								let text = &scene.strings[*str_at];
+								match parse_hex(text) {
+									Some(fcolor) => self.register(lines, &fcolor, idx),
+									None => reporter.warn(&format!(
+										"`color` \"{text}\" is not a hex color of the form #rrggbb!"
+									)),
+								}
+							}
+						}
+					},
 					_ => {
-						warn(&format!(
+						reporter.warn(&format!(
 							"`color` is not a sequence as expected! Got {} instead.",
 							node
 						));
@@ -98,56 +235,444 @@ impl Palette {
 
 use crate::ir::TransformMat;
 
+/// Convert a hex color of the form "#rrggbb" to its channels, each from 0 to 1.
+fn parse_hex(text: &str) -> Option<Point3D> {
+	let digits = text.strip_prefix('#')?;
+	if digits.len() != 6 || !digits.is_ascii() {
+		return None;
+	}
+	let mut color = new_point(0.0);
+	for i in 0..3 {
+		let channel = u8::from_str_radix(&digits[i * 2..i * 2 + 2], 16).ok()?;
+		color[i] = channel as f64 / 255.0;
+	}
+	Some(color)
+}
+
+/// Format a vertex directive for the point, with numbers of the given precision
+fn vertex(point: &Point3D, precision: Option<u8>) -> String {
+	format!(
+		"v {} {} {}",
+		fmt_float(point.x, precision),
+		fmt_float(point.y, precision),
+		fmt_float(point.z, precision)
+	)
+}
+
+/// Get the color (with channels from 0 to 1) of each vertex in the strip, if it has valid colors in
+/// a `colors` field. Otherwise, the strip is drawn in its object color alone.
+fn strip_colors(strip: &Strip, scene: &Scene, reporter: &mut Reporter) -> Option<Vec<Point3D>> {
+	let node = strip.fields.get("colors")?;
+	let Node::Sequence(idx) = node else {
+		reporter.warn(&format!(
+			"`colors` is not a sequence as expected! Got {node} instead."
+		));
+		return None;
+	};
+	let vals = &scene.sequences[*idx].vals;
+	if vals.len() != strip.vals.len() {
+		reporter.warn(&format!(
+			"`colors` is expected to have one color per vertex! {} found for {} vertices.",
+			vals.len(),
+			strip.vals.len()
+		));
+		return None;
+	}
+	let mut colors = vec![];
+	for (i, val) in vals.iter().enumerate() {
+		match as_3d(scene, val) {
+			Ok(color) if color.iter().all(|c| (0.0..=255.0).contains(c)) => {
+				colors.push(color / 255.0)
+			},
+			_ => {
+				reporter.warn(&format!(
+					"`colors` color {i} is expected to be 3 channels from 0 to 255!"
+				));
+				return None;
+			},
+		}
+	}
+	Some(colors)
+}
+
+/// Get the texture coordinates of each vertex in the strip, if it has valid coordinates in a `uv`
+/// field.
+fn strip_uvs(strip: &Strip, scene: &Scene, reporter: &mut Reporter) -> Option<Vec<[f64; 2]>> {
+	let node = strip.fields.get("uv")?;
+	let Node::Sequence(idx) = node else {
+		reporter.warn(&format!(
+			"`uv` is not a sequence as expected! Got {node} instead."
+		));
+		return None;
+	};
+	let vals = &scene.sequences[*idx].vals;
+	if vals.len() != strip.vals.len() {
+		reporter.warn(&format!(
+			"`uv` is expected to have one coordinate per vertex! {} found for {} vertices.",
+			vals.len(),
+			strip.vals.len()
+		));
+		return None;
+	}
+	let mut uvs = vec![];
+	for (i, val) in vals.iter().enumerate() {
+		let coord = match val {
+			Node::Sequence(at) => match scene.sequences[*at].vals.as_slice() {
+				[Node::Number(u), Node::Number(v)] => Some([*u, *v]),
+				_ => None,
+			},
+			_ => None,
+		};
+		match coord {
+			Some(coord) => uvs.push(coord),
+			None => {
+				reporter.warn(&format!("`uv` coordinate {i} is expected to be 2 numbers!"));
+				return None;
+			},
+		}
+	}
+	Some(uvs)
+}
+
+/// Format a face directive from the vertices (and texture coordinates, if `textured`) at the
+/// given offsets back from the last emitted
+fn face(offsets: &[usize], textured: bool) -> String {
+	let refs: Vec<String> = offsets
+		.iter()
+		.map(|i| {
+			if textured {
+				format!("-{i}/-{i}")
+			} else {
+				format!("-{i}")
+			}
+		})
+		.collect();
+	format!("f {}", refs.join(" "))
+}
+
+/// Where a node is drawn: the transform from its space to world space, and the path of the group
+/// (named by each enclosing mapping, separated by '/') which it belongs to
+struct Frame {
+	transform: TransformMat,
+	group: String,
+}
+
+/// Replace any whitespace in the name, since it would break the directive it is used in
+fn sanitize(name: &str) -> String {
+	name.chars()
+		.map(|c| if c.is_whitespace() { '_' } else { c })
+		.collect()
+}
+
+/// Get the name to give an object in the output. Uses the object's `name` field if present (with any
+/// whitespace replaced, since it would break the `o` directive), else a name generated from the
+/// object kind and index.
+pub fn object_name(
+	fields: &Fields,
+	scene: &Scene,
+	kind: &str,
+	idx: usize,
+	reporter: &mut Reporter,
+) -> String {
+	match fields.get("name") {
+		Some(Node::String(str_at)) => {
+			let name = &scene.strings[*str_at];
+			if name.is_empty() {
+				reporter.warn("`name` is expected to be nonempty!");
+				format!("{kind}{idx}")
+			} else {
+				sanitize(name)
+			}
+		},
+		Some(node) => {
+			reporter.warn(&format!(
+				"`name` is not a string as expected! Got {} instead.",
+				node
+			));
+			format!("{kind}{idx}")
+		},
+		None => format!("{kind}{idx}"),
+	}
+}
+
+/// The smoothing group which the faces of an object with these fields belong to, if any. A `smooth`
+/// field gives the group by number (where 0 is none) or as true for group 1. Without the field, the
+/// object is in group 1 if --smooth-all is used.
+fn smoothing_group(fields: &Fields, args: &Args, reporter: &mut Reporter) -> Option<usize> {
+	match fields.get("smooth") {
+		Some(Node::Bool(smooth)) => smooth.then_some(1),
+		Some(Node::Number(group)) if *group >= 0.0 && group.fract() == 0.0 => {
+			(*group > 0.0).then_some(*group as usize)
+		},
+		Some(node) => {
+			reporter.warn(&format!(
+				"`smooth` is expected to be a boolean or a nonnegative integer! Got {node} instead."
+			));
+			None
+		},
+		None => args.smooth_all.then_some(1),
+	}
+}
+
+/// Begin a new object in the output, preceded by its comment (if enabled and present).
+fn start_object(
+	lines: &mut Vec<String>,
+	fields: &Fields,
+	scene: &Scene,
+	args: &Args,
+	kind: &str,
+	idx: usize,
+	reporter: &mut Reporter,
+) {
+	lines.push("".to_string());
+	if args.comments {
+		match fields.get("comment") {
+			Some(Node::String(str_at)) => {
+				for line in scene.strings[*str_at].lines() {
+					lines.push(format!("# {line}"));
+				}
+			},
+			Some(node) => reporter.warn(&format!(
+				"`comment` is not a string as expected! Got {node} instead."
+			)),
+			None => {},
+		}
+	}
+	lines.push(format!(
+		"o {}",
+		object_name(fields, scene, kind, idx, reporter)
+	));
+}
+
+/// Emit the vertices and faces of a UV sphere with `stacks` latitude bands. Faces are wound so that
+/// their normals point outward.
+fn tessellate_sphere(
+	sphere: &Sphere,
+	args: &Args,
+	lines: &mut Vec<String>,
+	transform: &TransformMat,
+) {
+	let stacks = args.sphere_subdiv as usize;
+	let slices = stacks * 2;
+	let mut verts = vec![Point3D::new(0.0, sphere.radius, 0.0)];
+	for i in 1..stacks {
+		let theta = std::f64::consts::PI * (i as f64) / (stacks as f64);
+		for j in 0..slices {
+			let phi = std::f64::consts::TAU * (j as f64) / (slices as f64);
+			verts.push(
+				Point3D::new(
+					theta.sin() * phi.cos(),
+					theta.cos(),
+					theta.sin() * phi.sin(),
+				) * sphere.radius,
+			);
+		}
+	}
+	verts.push(Point3D::new(0.0, -sphere.radius, 0.0));
+
+	for vert in verts.iter() {
+		let point = transform * homogenize_pt(&(sphere.center + vert));
+		lines.push(vertex(&point, args.precision));
+	}
+	// Faces use relative indices, so convert from the index within `verts`
+	let total = verts.len();
+	let rel = |k: usize| format!("-{}", total - k);
+	let ring = |i: usize, j: usize| 1 + i * slices + (j % slices);
+	for j in 0..slices {
+		lines.push(format!(
+			"f {} {} {}",
+			rel(0),
+			rel(ring(0, j + 1)),
+			rel(ring(0, j))
+		));
+	}
+	for i in 0..(stacks - 2) {
+		for j in 0..slices {
+			lines.push(format!(
+				"f {} {} {} {}",
+				rel(ring(i, j + 1)),
+				rel(ring(i, j)),
+				rel(ring(i + 1, j)),
+				rel(ring(i + 1, j + 1))
+			));
+		}
+	}
+	for j in 0..slices {
+		lines.push(format!(
+			"f {} {} {}",
+			rel(ring(stacks - 2, j + 1)),
+			rel(ring(stacks - 2, j)),
+			rel(total - 1)
+		));
+	}
+}
+
+/// The fields of the node, if it is an object which OBJ output draws
+fn node_fields<'a>(node: &Node, scene: &'a Scene) -> Option<&'a Fields> {
+	match node {
+		Node::Strip(idx) => Some(&scene.strips[*idx].fields),
+		Node::Point(idx) => Some(&scene.points[*idx].fields),
+		Node::Ray(idx) => Some(&scene.rays[*idx].fields),
+		Node::Line(idx) => Some(&scene.lines[*idx].fields),
+		Node::Sphere(idx) => Some(&scene.spheres[*idx].fields),
+		Node::Instance(idx) => Some(&scene.instances[*idx].fields),
+		Node::Mapping(idx) => Some(&scene.mappings[*idx].fields),
+		_ => None,
+	}
+}
+
+/// The path of the group for mapping `idx` within the group at `parent`. The mapping is named by
+/// its `name` field if present, else by its kind and index.
+fn group_path(parent: &str, scene: &Scene, idx: usize) -> String {
+	let map = &scene.mappings[idx];
+	let kind = if map.is_box { "box" } else { "mapping" };
+	let name = match map.fields.get("name") {
+		Some(Node::String(str_at)) if !scene.strings[*str_at].is_empty() => {
+			sanitize(&scene.strings[*str_at])
+		},
+		_ => format!("{kind}{idx}"),
+	};
+	if parent.is_empty() {
+		name
+	} else {
+		format!("{parent}/{name}")
+	}
+}
+
 fn handle_node(
 	node: &Node,
 	lines: &mut Vec<String>,
 	scene: &Scene,
+	args: &Args,
 	palette: &mut Palette,
-	transform: &TransformMat,
+	frame: &Frame,
+	reporter: &mut Reporter,
 ) {
+	reporter.step();
+	if is_hidden(scene, node) {
+		return;
+	}
+	let transform = &frame.transform;
 	match node {
+		Node::Strip(_) | Node::Point(_) | Node::Ray(_) | Node::Line(_) | Node::Sphere(_)
+			if args.boxes_only => {},
 		Node::Strip(idx) => {
 			let strip = &scene.strips[*idx];
-			palette.update(strip.fields.get("color"), lines, scene);
-			lines.push("".to_string());
-			lines.push(format!("o strip{}", *idx));
+			let color = palette.update(strip.fields.get("color"), lines, scene, reporter);
+			let uvs = strip_uvs(strip, scene, reporter);
+			let texture = match strip.fields.get("texture") {
+				Some(Node::String(str_at)) => Some(*str_at),
+				Some(node) => {
+					reporter.warn(&format!(
+						"`texture` is not a string as expected! Got {node} instead."
+					));
+					None
+				},
+				None => None,
+			};
+			if let Some(str_at) = texture {
+				if uvs.is_none() {
+					reporter
+						.warn("`texture` is given without valid `uv` coordinates to map it by!");
+				}
+				palette.use_texture(lines, scene, str_at);
+			}
+			let textured = uvs.is_some();
+			let colors = strip_colors(strip, scene, reporter);
+			start_object(lines, &strip.fields, scene, args, "strip", *idx, reporter);
+			palette.smooth(lines, smoothing_group(&strip.fields, args, reporter));
 			let mut inverse = false;
 			let mut count = 0;
-			for vert in strip.vals.iter() {
-				let point = transform * homogenize_pt(vert);
-				lines.push(format!("v {} {} {}", point.x, point.y, point.z));
-				if count >= 2 {
-					if inverse {
-						lines.push("f -2 -3 -1".to_string());
-					} else {
-						lines.push("f -3 -2 -1".to_string());
+			// The restarts are ascending, so the next is the only one which can begin here
+			let mut restarts = strip.restarts.iter().peekable();
+			for (i, vert) in strip.vals.iter().enumerate() {
+				if restarts.next_if(|&&at| at == i).is_some() {
+					// Finish the previous run, then begin anew so no face bridges the two
+					if strip.topology == Topology::Polygon {
+						let offsets: Vec<usize> = (1..=count).rev().collect();
+						lines.push(face(&offsets, textured));
 					}
-					inverse = !inverse;
-				} else {
-					count += 1;
+					count = 0;
+					inverse = false;
+				}
+				let point = transform * homogenize_pt(vert);
+				match &colors {
+					// Vertex colors extend the vertex directive with red, green, and blue
+					Some(colors) => lines.push(format!(
+						"{} {} {} {}",
+						vertex(&point, args.precision),
+						fmt_float(colors[i].x, args.precision),
+						fmt_float(colors[i].y, args.precision),
+						fmt_float(colors[i].z, args.precision)
+					)),
+					None => lines.push(vertex(&point, args.precision)),
+				}
+				if let Some(uvs) = &uvs {
+					lines.push(format!(
+						"vt {} {}",
+						fmt_float(uvs[i][0], args.precision),
+						fmt_float(uvs[i][1], args.precision)
+					));
+				}
+				count += 1;
+				match strip.topology {
+					Topology::TriStrip => {
+						if count >= 3 {
+							if inverse {
+								lines.push(face(&[2, 3, 1], textured));
+							} else {
+								lines.push(face(&[3, 2, 1], textured));
+							}
+							inverse = !inverse;
+						}
+					},
+					Topology::Quad => {
+						if count % 4 == 0 {
+							lines.push(face(&[4, 3, 2, 1], textured));
+						}
+					},
+					Topology::TriList => {
+						if count % 3 == 0 {
+							lines.push(face(&[3, 2, 1], textured));
+						}
+					},
+					Topology::TriFan => {
+						// The first vertex of the run is `count` vertices back
+						if count >= 3 {
+							lines.push(face(&[count, 2, 1], textured));
+						}
+					},
+					Topology::Polygon => {},
 				}
 			}
+			if strip.topology == Topology::Polygon {
+				let offsets: Vec<usize> = (1..=count).rev().collect();
+				lines.push(face(&offsets, textured));
+			}
+			if texture.is_some() {
+				palette.reuse(lines, color);
+			}
 		},
 		Node::Point(idx) => {
 			let point = &scene.points[*idx];
-			palette.update(point.fields.get("color"), lines, scene);
+			palette.update(point.fields.get("color"), lines, scene, reporter);
 			let vert = transform * homogenize_pt(&point.loc);
 			const POINT_RADIUS: f64 = 0.01;
-			lines.push("".to_string());
-			lines.push(format!("o point{}", *idx));
-			lines.push(format!("v {} {} {}", vert.x - POINT_RADIUS, vert.y, vert.z));
-			lines.push(format!("v {} {} {}", vert.x + POINT_RADIUS, vert.y, vert.z));
-			lines.push(format!("v {} {} {}", vert.x, vert.y - POINT_RADIUS, vert.z));
-			lines.push(format!("v {} {} {}", vert.x, vert.y + POINT_RADIUS, vert.z));
-			lines.push(format!("v {} {} {}", vert.x, vert.y, vert.z - POINT_RADIUS));
-			lines.push(format!("v {} {} {}", vert.x, vert.y, vert.z + POINT_RADIUS));
+			start_object(lines, &point.fields, scene, args, "point", *idx, reporter);
+			for axis in 0..3 {
+				for sign in [-1.0, 1.0] {
+					let mut end = vert;
+					end[axis] += sign * POINT_RADIUS;
+					lines.push(vertex(&end, args.precision));
+				}
+			}
 			lines.push("l -6 -5".to_string());
 			lines.push("l -4 -3".to_string());
 			lines.push("l -2 -1".to_string());
 		},
 		Node::Ray(idx) => {
 			let ray = &scene.rays[*idx];
-			palette.update(ray.fields.get("color"), lines, scene);
+			palette.update(ray.fields.get("color"), lines, scene, reporter);
 			let min = new_point(ray.min);
 			let extent = new_point(ray.extent);
 			let start = ray.origin + ray.direction.component_mul(&min);
@@ -155,10 +680,9 @@ fn handle_node(
 
 			let origin = transform * homogenize_pt(&start);
 			let dest = transform * homogenize_pt(&end);
-			lines.push("".to_string());
-			lines.push(format!("o ray{}", *idx));
-			lines.push(format!("v {} {} {}", origin.x, origin.y, origin.z));
-			lines.push(format!("v {} {} {}", dest.x, dest.y, dest.z));
+			start_object(lines, &ray.fields, scene, args, "ray", *idx, reporter);
+			lines.push(vertex(&origin, args.precision));
+			lines.push(vertex(&dest, args.precision));
 			lines.push("l -2 -1".to_string()); // line from penultimate vertex to ultimate
 
 			let has_head = match ray.fields.get("headless") {
@@ -166,8 +690,6 @@ fn handle_node(
 				_ => true,
 			};
 			if has_head {
-				// produce arrowhead using two perpendicular lines
-				const HEAD_RATIO: f64 = 0.05;
 				let diff_orig = dest - origin;
 				let ray_mag = diff_orig.magnitude();
 				let diff = diff_orig / ray_mag;
@@ -176,6 +698,35 @@ fn handle_node(
 				let dummy = if diff == pos_y { pos_x } else { pos_y };
 				let cross0 = diff.cross(&dummy).normalize();
 				let cross1 = diff.cross(&cross0).normalize();
+
+				if args.ray_arrows {
+					// produce a cone with its apex at the destination, pointing along the ray
+					palette.smooth(lines, None);
+					const SEGMENTS: usize = 8;
+					let length = ray_mag * args.ray_arrow_size;
+					let base = dest - diff * length;
+					for j in 0..SEGMENTS {
+						let angle = std::f64::consts::TAU * (j as f64) / (SEGMENTS as f64);
+						let rim =
+							base + (cross0 * angle.cos() + cross1 * angle.sin()) * (length / 2.0);
+						lines.push(vertex(&rim, args.precision));
+					}
+					// The destination (apex) is the vertex just before the rim
+					for j in 0..SEGMENTS {
+						lines.push(format!(
+							"f -{} -{} -{}",
+							SEGMENTS - j,
+							SEGMENTS - (j + 1) % SEGMENTS,
+							SEGMENTS + 1
+						));
+					}
+					let face: Vec<String> = (1..=SEGMENTS).map(|i| format!("-{i}")).collect();
+					lines.push(format!("f {}", face.join(" ")));
+					return;
+				}
+
+				// produce arrowhead using two perpendicular lines
+				const HEAD_RATIO: f64 = 0.05;
 				let cross = [cross0, cross1];
 				let mut to_dest = 1;
 
@@ -183,48 +734,134 @@ fn handle_node(
 					let heads = [diff + cross_vec, diff - cross_vec];
 					for head in heads {
 						let actual = dest - head.normalize() * (ray_mag * HEAD_RATIO);
-						lines.push(format!("v {} {} {}", actual.x, actual.y, actual.z));
+						lines.push(vertex(&actual, args.precision));
 						to_dest += 1;
 						lines.push(format!("l -1 -{}", to_dest));
 					}
 				}
 			}
 		},
+		Node::Line(idx) => {
+			let line = &scene.lines[*idx];
+			palette.update(line.fields.get("color"), lines, scene, reporter);
+			start_object(lines, &line.fields, scene, args, "line", *idx, reporter);
+			for vert in line.vals.iter() {
+				let point = transform * homogenize_pt(vert);
+				lines.push(vertex(&point, args.precision));
+			}
+			let refs: Vec<String> = (1..=line.vals.len())
+				.rev()
+				.map(|i| format!("-{i}"))
+				.collect();
+			lines.push(format!("l {}", refs.join(" ")));
+		},
+		Node::Sphere(idx) => {
+			let sphere = &scene.spheres[*idx];
+			palette.update(sphere.fields.get("color"), lines, scene, reporter);
+			start_object(lines, &sphere.fields, scene, args, "sphere", *idx, reporter);
+			palette.smooth(lines, smoothing_group(&sphere.fields, args, reporter));
+			tessellate_sphere(sphere, args, lines, transform);
+		},
 		Node::Instance(idx) => {
 			let instance = &scene.instances[*idx];
-			palette.update(instance.fields.get("color"), lines, scene);
+			palette.update(instance.fields.get("color"), lines, scene, reporter);
+			// An override color is used by everything within the instance, whatever its own color
+			let was_overridden = palette.overridden;
+			if let Some(color) = instance.fields.get("color_override") {
+				palette.update(Some(color), lines, scene, reporter);
+				palette.overridden = true;
+			}
 			// Instance doesn't push any lines, but it does update the transformation matrix
-			let homogenous = &homogenize(transform);
-			let mult = instance.obj_to_world() * homogenous;
-			handle_node(&instance.affected, lines, scene, palette, &mult);
+			let inner = Frame {
+				transform: transform * homogenize(&instance.obj_to_world()),
+				group: frame.group.clone(),
+			};
+			if args.draw_axes {
+				// Draw the unit axes of the instance's space, so their lengths show its scale
+				let color = palette.current;
+				start_object(
+					lines,
+					&instance.fields,
+					scene,
+					args,
+					"instance",
+					*idx,
+					reporter,
+				);
+				let origin = inner.transform * homogenize_pt(&new_point(0.0));
+				for axis in 0..3 {
+					let mut end = new_point(0.0);
+					end[axis] = 1.0;
+					palette.use_axis(lines, axis);
+					lines.push(vertex(&origin, args.precision));
+					lines.push(vertex(
+						&(inner.transform * homogenize_pt(&end)),
+						args.precision,
+					));
+					lines.push("l -2 -1".to_string());
+				}
+				palette.reuse(lines, color);
+			}
+			handle_node(
+				&instance.affected,
+				lines,
+				scene,
+				args,
+				palette,
+				&inner,
+				reporter,
+			);
+			palette.overridden = was_overridden;
 		},
 		Node::Mapping(idx) => {
 			let map = &scene.mappings[*idx];
-			let color = palette.update(map.fields.get("color"), lines, scene);
+			let color = palette.update(map.fields.get("color"), lines, scene, reporter);
+			let inner = Frame {
+				transform: *transform,
+				group: group_path(&frame.group, scene, *idx),
+			};
+			lines.push(format!("g {}", inner.group));
 			if map.is_box {
 				// create a box if min and max are present
-				lines.push("".to_string());
-				lines.push(format!("o box{}", *idx));
-
-				for i in 0..8 {
-					let mut point = new_point(0.0);
-					for j in 0..3 {
-						point[j] = if ((i >> j) & 1) == 1 {
-							map.max[j]
-						} else {
-							map.min[j]
-						}
-					}
-
-					let vert = transform * homogenize_pt(&point);
-					lines.push(format!("v {} {} {}", vert.x, vert.y, vert.z));
+				if args.color_by_depth {
+					palette.use_depth(lines);
 				}
+				start_object(lines, &map.fields, scene, args, "box", *idx, reporter);
+				// Only strips and spheres may be smoothed
+				palette.smooth(lines, None);
 
 				let mut fill = false;
 				if let Some(Node::Bool(val)) = map.fields.get("opaque") {
 					fill = *val;
 				}
 
+				if let Some(thickness) = args.solid_wireframe
+					&& !fill
+				{
+					box_tubes(
+						lines,
+						&map.min,
+						&map.max,
+						thickness,
+						transform,
+						args.precision,
+					);
+				} else {
+					for i in 0..8 {
+						let mut point = new_point(0.0);
+						for j in 0..3 {
+							point[j] = if ((i >> j) & 1) == 1 {
+								map.max[j]
+							} else {
+								map.min[j]
+							}
+						}
+
+						let vert = transform * homogenize_pt(&point);
+						lines.push(vertex(&vert, args.precision));
+					}
+				}
+
 				if fill {
 					lines.push("f -8 -4 -2 -6".to_string()); // minX
 					lines.push("f -8 -4 -3 -7".to_string()); // minY
@@ -232,17 +869,43 @@ fn handle_node(
 					lines.push("f -7 -3 -1 -5".to_string()); // maxX
 					lines.push("f -6 -2 -1 -5".to_string()); // maxY
 					lines.push("f -8 -6 -5 -7".to_string()); // maxZ
-				} else {
+				} else if args.solid_wireframe.is_none() {
 					lines.push("l -8 -4 -2 -6".to_string());
 					lines.push("l -3 -1 -5 -7".to_string());
 					lines.push("l -8 -7 -3 -4 -2 -1 -5 -6 -8".to_string());
 				}
 			}
-			if let Some(Node::Sequence(idx)) = map.fields.get("data") {
+			// A box at the maximum depth is drawn without its contents
+			let truncated = map.is_box && args.max_depth.is_some_and(|max| palette.depth >= max);
+			if let Some(Node::Sequence(idx)) = map.fields.get("data")
+				&& !truncated
+			{
 				let seq = &scene.sequences[*idx];
+				if map.is_box {
+					palette.depth += 1;
+				}
+				// Any mapping within a child sets its own group, so this group must then be restored
+				let mut regroup = false;
 				for node in seq.vals.iter() {
-					palette.reset(lines, color);
-					handle_node(node, lines, scene, palette, transform);
+					// Without a default material, a child with its own color need not return to the
+					// mapping's color first, which may be the default
+					let colored = node_fields(node, scene).is_some_and(|fields| {
+						matches!(
+							fields.get("color"),
+							Some(Node::Sequence(_) | Node::String(_))
+						)
+					});
+					if !(args.no_default_material && colored) {
+						palette.reset(lines, color);
+					}
+					if regroup {
+						lines.push(format!("g {}", inner.group));
+					}
+					handle_node(node, lines, scene, args, palette, &inner, reporter);
+					regroup = matches!(node, Node::Mapping(_) | Node::Instance(_));
+				}
+				if map.is_box {
+					palette.depth -= 1;
 				}
 			}
 		},
@@ -250,18 +913,843 @@ fn handle_node(
 	}
 }
 
-pub fn to_obj(scene: &Scene) -> Vec<String> {
+/// Write the twelve edges of the box from `min` to `max` as tubes with a square cross section
+/// `thickness` wide, each made of eight triangles facing outward. The tubes are built in the space
+/// of the box, then transformed.
+fn box_tubes(
+	lines: &mut Vec<String>,
+	min: &Point3D,
+	max: &Point3D,
+	thickness: f64,
+	transform: &TransformMat,
+	precision: Option<u8>,
+) {
+	let half = thickness / 2.0;
+	for axis in 0..3 {
+		// The edge runs along `axis` at a corner of the face spanned by the other two
+		let (u, v) = ((axis + 1) % 3, (axis + 2) % 3);
+		for corner in 0..4 {
+			let mut center = *min;
+			if corner & 1 == 1 {
+				center[u] = max[u];
+			}
+			if corner & 2 == 2 {
+				center[v] = max[v];
+			}
+			// The cross section counterclockwise about the axis, first at the start of the edge
+			for end in [min[axis], max[axis]] {
+				for (du, dv) in [(-half, -half), (half, -half), (half, half), (-half, half)] {
+					let mut point = center;
+					point[axis] = end;
+					point[u] += du;
+					point[v] += dv;
+					lines.push(vertex(&(transform * homogenize_pt(&point)), precision));
+				}
+			}
+			// Join each side of the cross section at the start to the same side at the end
+			for side in 0..4 {
+				let (first, second) = (8 - side, 8 - (side + 1) % 4);
+				lines.push(face(&[first, second, second - 4], false));
+				lines.push(face(&[first, second - 4, first - 4], false));
+			}
+		}
+	}
+}
+
+/// The number of nodes visited when drawing `node`, counting each time a node is reused.
+fn drawn_count(node: &Node, scene: &Scene) -> usize {
+	1 + match node {
+		Node::Instance(idx) => drawn_count(&scene.instances[*idx].affected, scene),
+		Node::Mapping(idx) => match scene.mappings[*idx].fields.get("data") {
+			Some(Node::Sequence(seq_at)) => scene.sequences[*seq_at]
+				.vals
+				.iter()
+				.map(|val| drawn_count(val, scene))
+				.sum(),
+			_ => 0,
+		},
+		_ => 0,
+	}
+}
+
+/// Generate the OBJ lines for the node drawn in the frame. `colors` are the colors of the
+/// enclosing objects, outermost first, for the node to inherit.
+fn node_obj(
+	node: &Node,
+	frame: &Frame,
+	colors: &[&Node],
+	scene: &Scene,
+	args: &Args,
+	reporter: &mut Reporter,
+) -> Vec<String> {
 	// Append header to every obj file
 	let mut res = vec![
 		"# Generated by Scene Builder @ https://github.com/mmoult/scene-builder".to_string(),
 		"# Recommended OBJ viewer: https://3dviewer.net/".to_string(),
 	];
-	let transform = matrix![
-		1.0, 0.0, 0.0, 0.0;
-		0.0, 1.0, 0.0, 0.0;
-		0.0, 0.0, 1.0, 0.0;
-	];
-	let mut palette = Palette::new(&mut res, scene.sequences.len());
-	handle_node(&scene.world, &mut res, scene, &mut palette, &transform);
+	let mut palette = Palette::new(
+		&mut res,
+		scene,
+		&args.default_color,
+		args.no_default_material,
+	);
+	for color in colors {
+		palette.update(Some(color), &mut res, scene, reporter);
+	}
+	// A mapping names its own group, but anything else must be given the group of the frame
+	if !frame.group.is_empty() && !matches!(node, Node::Mapping(_)) {
+		res.push(format!("g {}", frame.group));
+	}
+	if reporter.progress {
+		reporter.start_stage("Writing OBJ", drawn_count(node, scene));
+	}
+	handle_node(node, &mut res, scene, args, &mut palette, frame, reporter);
+	reporter.end_stage();
+	if let Some(name) = &args.single_object {
+		// Only the first object is started, so all geometry is within it
+		let mut started = false;
+		res.retain_mut(|line| {
+			if !line.starts_with("o ") {
+				return true;
+			}
+			if started {
+				return false;
+			}
+			*line = format!("o {}", sanitize(name));
+			started = true;
+			true
+		});
+	}
+	if args.flip_winding {
+		for line in res.iter_mut() {
+			if let Some(indices) = line.strip_prefix("f ") {
+				let reversed: Vec<&str> = indices.split(' ').rev().collect();
+				*line = format!("f {}", reversed.join(" "));
+			}
+		}
+	}
 	res
 }
+
+fn identity_frame() -> Frame {
+	Frame {
+		transform: matrix![
+			1.0, 0.0, 0.0, 0.0;
+			0.0, 1.0, 0.0, 0.0;
+			0.0, 0.0, 1.0, 0.0;
+		],
+		group: String::new(),
+	}
+}
+
+pub fn to_obj(scene: &Scene, args: &Args, reporter: &mut Reporter) -> Vec<String> {
+	node_obj(&scene.world, &identity_frame(), &[], scene, args, reporter)
+}
+
+/// List each distinct material which objects of the OBJ output are drawn with: its position, its
+/// color (with channels from 0 to 255), and the number of objects using it. Color materials are
+/// merged by color, whereas others (such as textures) are listed by name. Materials are listed in
+/// the order they are first used.
+pub fn list_materials(scene: &Scene, args: &Args, reporter: &mut Reporter) -> Vec<String> {
+	// The palette decides which material each object uses, so the table is read from its output
+	let mut defined: Vec<(String, String)> = vec![];
+	let mut current = None;
+	let mut table: Vec<(String, usize)> = vec![];
+	for line in to_obj(scene, args, reporter) {
+		if let Some(name) = line.strip_prefix("newmtl ") {
+			defined.push((name.to_string(), String::new()));
+		} else if let Some(channels) = line.strip_prefix("Kd ")
+			&& let Some((_, color)) = defined.last_mut()
+		{
+			let channels: Vec<String> = channels
+				.split(' ')
+				.map(|channel| {
+					(channel.parse::<f64>().unwrap_or(0.0) * 255.0)
+						.round()
+						.to_string()
+				})
+				.collect();
+			*color = channels.join(" ");
+		} else if let Some(name) = line.strip_prefix("usemtl ") {
+			current = defined.iter().position(|(other, _)| other == name);
+		} else if line.starts_with("o ")
+			&& let Some(at) = current
+		{
+			let (name, color) = &defined[at];
+			let label = if name.starts_with("color") {
+				format!("color {color}")
+			} else {
+				format!("{name} {color}")
+			};
+			match table.iter_mut().find(|(other, _)| *other == label) {
+				Some((_, count)) => *count += 1,
+				None => table.push((label, 1)),
+			}
+		}
+	}
+	table
+		.iter()
+		.enumerate()
+		.map(|(i, (label, count))| {
+			let plural = if *count == 1 { "" } else { "s" };
+			format!("{i}: {label}, used by {count} object{plural}")
+		})
+		.collect()
+}
+
+/// The name made safe for use as a file name within the output directory: it cannot name another
+/// directory, by path separators or by `..`.
+fn file_name(name: &str) -> String {
+	sanitize(name)
+		.replace(['/', '\\', ':'], "_")
+		.replace("..", "__")
+}
+
+/// Generate a separate OBJ file for each object in the data of the world's mapping, returning the
+/// name of each file (without extension) with its lines. Each file is named by its object's `name`
+/// field if present, else by the object's position in the data. Any instances enclosing the world's
+/// mapping transform every object.
+pub fn split_obj(
+	scene: &Scene,
+	args: &Args,
+	reporter: &mut Reporter,
+) -> Result<Vec<(String, Vec<String>)>, String> {
+	let mut frame = identity_frame();
+	let mut colors = vec![];
+	let mut node = &scene.world;
+	let map_at = loop {
+		if is_hidden(scene, node) {
+			return Ok(vec![]);
+		}
+		match node {
+			Node::Instance(idx) => {
+				let instance = &scene.instances[*idx];
+				colors.extend(instance.fields.get("color"));
+				frame.transform *= homogenize(&instance.obj_to_world());
+				node = &instance.affected;
+			},
+			Node::Mapping(idx) => break *idx,
+			_ => {
+				return Err(String::from(
+					"Cannot split output by object since the world is not a mapping!",
+				));
+			},
+		}
+	};
+	let map = &scene.mappings[map_at];
+	colors.extend(map.fields.get("color"));
+	frame.group = group_path("", scene, map_at);
+	let Some(Node::Sequence(seq)) = map.fields.get("data") else {
+		return Ok(vec![]);
+	};
+
+	let mut files = vec![];
+	for (i, child) in scene.sequences[*seq].vals.iter().enumerate() {
+		if is_hidden(scene, child) {
+			continue;
+		}
+		let name = match node_fields(child, scene).and_then(|fields| fields.get("name")) {
+			Some(Node::String(str_at)) if !scene.strings[*str_at].is_empty() => {
+				file_name(&scene.strings[*str_at])
+			},
+			_ => i.to_string(),
+		};
+		if files.iter().any(|(other, _)| *other == name) {
+			return Err(format!(
+				"Cannot split output by object since multiple objects are named \"{name}\"!"
+			));
+		}
+		let lines = node_obj(child, &frame, &colors, scene, args, reporter);
+		files.push((name, lines));
+	}
+	Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn material_table() {
+		use clap::Parser;
+		let args = Args::parse_from(["scene-builder", "in.yaml", "--list-materials"]);
+		let text = "data:\n- origin: [0, 0, 0]\n  direction: [1, 0, 0]\n  max: 1\n  color: &red [255, 0, \
+		            0]\n- point: [0, 0, 0]\n  color: *red\n- point: [1, 0, 0]\n  color: '#0000ff'\n- \
+		            point: [2, 0, 0]\n  color: *red\n- point: [3, 0, 0]\n  color: '#0000ff'";
+		let docs = crate::load::load_from_str(text).unwrap();
+		let scene = crate::ir::to_ir(&docs[0]).unwrap();
+		let table = list_materials(&scene, &args, &mut Reporter::new());
+		assert_eq!(table.len(), 2, "{table:?}");
+		assert_eq!(table[0], "0: color 255 0 0, used by 3 objects");
+		assert_eq!(table[1], "1: color 0 0 255, used by 2 objects");
+	}
+
+	#[test]
+	fn bad_color_warnings() {
+		use clap::Parser;
+		let args = Args::parse_from(["scene-builder", "in.yaml"]);
+		let docs = crate::load::load_from_str(
+			"data:\n- strip: [[0, 0, 0], [1, 0, 0], [0, 1, 0]]\n  color: [255, 0]\n- strip: [[0, 0, \
+			 1], [1, 0, 1], [0, 1, 1]]\n  color: [true, 0, 0]\n- point: [0, 0, 0]\n  color: 7",
+		)
+		.unwrap();
+		let scene = crate::ir::to_ir(&docs[0]).unwrap();
+		let mut reporter = Reporter::new();
+		to_obj(&scene, &args, &mut reporter);
+		assert_eq!(
+			reporter.warnings(),
+			vec![
+				"`color` is expected to have 3 components! 2 found instead.",
+				"`color` channel 0 is expected to be a number!",
+				"`color` is not a sequence as expected! Got 7 instead.",
+			]
+		);
+	}
+
+	#[test]
+	fn configured_default_color() {
+		use clap::Parser;
+		let args = Args::parse_from(["scene-builder", "in.yaml", "--default-color", "255,0,51"]);
+		let docs = crate::load::load_from_str("strip: [[0, 0, 0], [1, 0, 0], [0, 1, 0]]").unwrap();
+		let scene = crate::ir::to_ir(&docs[0]).unwrap();
+		let lines = to_obj(&scene, &args, &mut Reporter::new());
+		assert!(lines.contains(&"Kd 1 0 0.2".to_string()));
+	}
+
+	#[test]
+	fn boxes_only() {
+		use clap::Parser;
+		let args = Args::parse_from(["scene-builder", "in.yaml", "--boxes-only"]);
+		let docs = crate::load::load_from_str("data:\n- strip: [[0, 0, 0], [1, 0, 0], [0, 1, 0]]")
+			.unwrap();
+		let mut scene = crate::ir::to_ir(&docs[0]).unwrap();
+		crate::transform::transform(&mut scene, &args, false, &mut Reporter::new()).unwrap();
+		let lines = to_obj(&scene, &args, &mut Reporter::new());
+		assert_eq!(
+			lines.iter().filter(|line| line.starts_with("v ")).count(),
+			8
+		);
+		assert!(!lines.iter().any(|line| line.starts_with("f ")));
+	}
+
+	#[test]
+	fn color_by_depth() {
+		use clap::Parser;
+		let args = Args::parse_from(["scene-builder", "in.yaml", "--color-by-depth"]);
+		let docs = crate::load::load_from_str(
+			"data:\n- data:\n  - strip: [[0, 0, 0], [1, 0, 0], [0, 1, 0]]\n- data:\n  - strip: \
+			 [[0, 0, 1], [1, 0, 1], [0, 1, 1]]\n    color: [0, 0, 255]",
+		)
+		.unwrap();
+		let mut scene = crate::ir::to_ir(&docs[0]).unwrap();
+		crate::transform::transform(&mut scene, &args, false, &mut Reporter::new()).unwrap();
+		let lines = to_obj(&scene, &args, &mut Reporter::new());
+		// The material used for each object is the last selected before it
+		let mut current = "";
+		let mut used = vec![];
+		for line in lines.iter() {
+			if let Some(material) = line.strip_prefix("usemtl ") {
+				current = material;
+			} else if line.starts_with("o ") {
+				used.push((line.as_str(), current));
+			}
+		}
+		let material = |name: &str| used.iter().find(|(obj, _)| *obj == name).unwrap().1;
+		assert_ne!(material("o box0"), material("o box1"));
+		assert_eq!(material("o box1"), material("o box3"));
+		assert_ne!(material("o strip1"), material("o box3"));
+	}
+
+	#[test]
+	fn comment_above_object() {
+		use clap::Parser;
+		let args = Args::parse_from(["scene-builder", "in.yaml", "--comments"]);
+		let docs = crate::load::load_from_str(
+			"strip: [[0, 0, 0], [1, 0, 0], [0, 1, 0]]\ncomment: this is the hero asset",
+		)
+		.unwrap();
+		let scene = crate::ir::to_ir(&docs[0]).unwrap();
+		let lines = to_obj(&scene, &args, &mut Reporter::new());
+		let at_obj = lines.iter().position(|line| line == "o strip0").unwrap();
+		assert_eq!(lines[at_obj - 1], "# this is the hero asset");
+	}
+
+	#[test]
+	fn color_override() {
+		use clap::Parser;
+		let args = Args::parse_from(["scene-builder", "in.yaml"]);
+		let docs = crate::load::load_from_str(
+			"asset:\n  strip: [[0, 0, 0], [1, 0, 0], [0, 1, 0]]\n  color: [0, 0, 255]\ndata:\n- \
+			 instance: asset\n  color_override: [255, 0, 0]\n- instance: asset\n  color_override: \
+			 \"#00ff00\"\n- asset",
+		)
+		.unwrap();
+		let scene = crate::ir::to_ir(&docs[0]).unwrap();
+		let lines = to_obj(&scene, &args, &mut Reporter::new());
+		// Find the color of the material that each object uses
+		let mut colors = std::collections::HashMap::new();
+		let mut material = "";
+		let mut used = vec![];
+		for (i, line) in lines.iter().enumerate() {
+			if let Some(name) = line.strip_prefix("newmtl ") {
+				colors.insert(name, lines[i + 1].as_str());
+			}
+			if let Some(name) = line.strip_prefix("usemtl ") {
+				material = name;
+			} else if line.starts_with("o ") {
+				used.push(colors[material]);
+			}
+		}
+		assert_eq!(used, vec!["Kd 1 0 0", "Kd 0 1 0", "Kd 0 0 1"]);
+	}
+
+	#[test]
+	fn flip_winding() {
+		use clap::Parser;
+		let args = Args::parse_from(["scene-builder", "in.yaml", "--flip-winding"]);
+		let docs = crate::load::load_from_str("strip: [[0, 0, 0], [1, 0, 0], [0, 1, 0]]").unwrap();
+		let scene = crate::ir::to_ir(&docs[0]).unwrap();
+		let lines = to_obj(&scene, &args, &mut Reporter::new());
+		assert!(lines.contains(&"f -1 -2 -3".to_string()));
+		assert!(!lines.contains(&"f -3 -2 -1".to_string()));
+	}
+
+	#[test]
+	fn solid_wireframe() {
+		use clap::Parser;
+		let args = Args::parse_from(["scene-builder", "in.yaml", "--solid-wireframe", "0.1"]);
+		let docs = crate::load::load_from_str("data:\n- strip: [[0, 0, 0], [1, 0, 0], [0, 1, 1]]")
+			.unwrap();
+		let mut scene = crate::ir::to_ir(&docs[0]).unwrap();
+		crate::transform::transform(&mut scene, &args, false, &mut Reporter::new()).unwrap();
+		let lines = to_obj(&scene, &args, &mut Reporter::new());
+		let count = |prefix: &str| lines.iter().filter(|line| line.starts_with(prefix)).count();
+		assert_eq!(count("l "), 0);
+		// Eight triangles for each of the twelve edges, plus the strip's own triangle
+		assert_eq!(count("f "), 12 * 8 + 1);
+		assert_eq!(count("v "), 12 * 8 + 3);
+		// The first tube runs along x from the min corner, so its vertices straddle y = 0 and z = 0
+		assert!(lines.contains(&"v 0 -0.05 -0.05".to_string()), "{lines:?}");
+		assert!(lines.contains(&"v 1 0.05 0.05".to_string()));
+	}
+
+	#[test]
+	fn fan() {
+		use clap::Parser;
+		let args = Args::parse_from(["scene-builder", "in.yaml", "--fan"]);
+		let docs = crate::load::load_from_str(
+			"strip: [[0, 0, 0], [1, 0, 0], [1, 1, 0], [0, 1, 0], [-1, 1, 0]]",
+		)
+		.unwrap();
+		let mut scene = crate::ir::to_ir(&docs[0]).unwrap();
+		crate::transform::transform(&mut scene, &args, false, &mut Reporter::new()).unwrap();
+		let zero = Point3D::new(0.0, 0.0, 0.0);
+		let verts = &scene.strips[0].vals;
+		let tris = scene.strips[0].triangles();
+		assert_eq!(
+			tris,
+			[
+				[zero, verts[1], verts[2]],
+				[zero, verts[2], verts[3]],
+				[zero, verts[3], verts[4]]
+			]
+		);
+
+		let lines = to_obj(&scene, &args, &mut Reporter::new());
+		let faces: Vec<&String> = lines.iter().filter(|line| line.starts_with("f ")).collect();
+		// Each face is written after its last vertex, so the first vertex is further back each time
+		assert_eq!(faces, ["f -3 -2 -1", "f -4 -2 -1", "f -5 -2 -1"]);
+	}
+
+	#[test]
+	fn textured_quad() {
+		use clap::Parser;
+		let args = Args::parse_from(["scene-builder", "in.yaml"]);
+		let docs = crate::load::load_from_str(
+			"data:\n- strip: [[0, 0, 0], [1, 0, 0], [1, 1, 0], [0, 1, 0]]\n  topology: quad\n  \
+			 texture: brick.png\n  uv: [[0, 0], [1, 0], [1, 1], [0, 1]]\n- strip: [[0, 0, 1], [1, 0, \
+			 1], [0, 1, 1]]\n  texture: brick.png",
+		)
+		.unwrap();
+		let scene = crate::ir::to_ir(&docs[0]).unwrap();
+		let mut reporter = Reporter::new();
+		let lines = to_obj(&scene, &args, &mut reporter);
+		let count = |text: &str| lines.iter().filter(|line| *line == text).count();
+		assert_eq!(count("map_Kd brick.png"), 1);
+		assert_eq!(count("vt 1 1"), 1);
+		assert_eq!(count("f -4/-4 -3/-3 -2/-2 -1/-1"), 1);
+		// The untextured strip still gets plain faces
+		assert_eq!(count("f -3 -2 -1"), 1);
+		assert_eq!(
+			reporter.warnings(),
+			vec!["`texture` is given without valid `uv` coordinates to map it by!"]
+		);
+	}
+
+	#[test]
+	fn split_textured_quad() {
+		use clap::Parser;
+		let args = Args::parse_from(["scene-builder", "in.yaml", "--split"]);
+		let docs = crate::load::load_from_str(
+			"strip: [[0, 0, 0], [1, 0, 0], [1, 1, 0], [0, 1, 0]]\ntopology: quad\ntexture: \
+			 brick.png\nuv: [[0, 0], [1, 0], [1, 1], [0, 1]]",
+		)
+		.unwrap();
+		let mut scene = crate::ir::to_ir(&docs[0]).unwrap();
+		let mut reporter = Reporter::new();
+		crate::transform::transform(&mut scene, &args, false, &mut reporter).unwrap();
+		let lines = to_obj(&scene, &args, &mut reporter);
+		let count = |text: &str| lines.iter().filter(|line| *line == text).count();
+		// Each triangle keeps the coordinates of its own three vertices
+		assert_eq!(count("f -3/-3 -2/-2 -1/-1"), 2, "{lines:?}");
+		assert_eq!(count("vt 1 1"), 2);
+		assert_eq!(count("vt 1 0"), 1);
+		assert!(reporter.warnings().is_empty(), "{:?}", reporter.warnings());
+	}
+
+	#[test]
+	fn split_by_object() {
+		use clap::Parser;
+		let args = Args::parse_from(["scene-builder", "in.yaml", "--split-output"]);
+		let docs = crate::load::load_from_str(
+			"instance:\n  data:\n  - strip: [[0, 0, 0], [1, 0, 0], [0, 1, 0]]\n    name: roof\n  - \
+			 point: [0, 0, 0]\n  - data:\n    - strip: [[0, 0, 0], [1, 0, 0], [0, 1, 0]]\ntranslate: \
+			 [0, 0, 5]",
+		)
+		.unwrap();
+		let scene = crate::ir::to_ir(&docs[0]).unwrap();
+		let files = split_obj(&scene, &args, &mut Reporter::new()).unwrap();
+		let names: Vec<&str> = files.iter().map(|(name, _)| name.as_str()).collect();
+		assert_eq!(names, vec!["roof", "1", "2"]);
+		for (_, lines) in files.iter() {
+			assert!(lines.iter().any(|line| line.starts_with("newmtl ")));
+		}
+		// The root instance still applies to each object
+		assert!(files[0].1.contains(&"v 1 0 5".to_string()));
+	}
+
+	#[test]
+	fn split_names_stay_in_directory() {
+		use clap::Parser;
+		let args = Args::parse_from(["scene-builder", "in.yaml", "--split-output"]);
+		let docs = crate::load::load_from_str(
+			"data:\n- point: [0, 0, 0]\n  name: ../up\n- point: [0, 0, 0]\n  name: /abs/path\n- point: \
+			 [0, 0, 0]\n  name: 'back\\slash'\n- point: [0, 0, 0]\n  name: ..",
+		)
+		.unwrap();
+		let scene = crate::ir::to_ir(&docs[0]).unwrap();
+		let files = split_obj(&scene, &args, &mut Reporter::new()).unwrap();
+		let names: Vec<&str> = files.iter().map(|(name, _)| name.as_str()).collect();
+		assert_eq!(names, vec!["___up", "_abs_path", "back_slash", "__"]);
+	}
+
+	#[test]
+	fn no_default_material() {
+		use clap::Parser;
+		let docs = crate::load::load_from_str(
+			"data:\n- strip: [[0, 0, 0], [1, 0, 0], [0, 1, 0]]\n  color: [255, 0, 0]\n- point: [0, 0, \
+			 0]\n  color: [0, 255, 0]",
+		)
+		.unwrap();
+		let scene = crate::ir::to_ir(&docs[0]).unwrap();
+		let materials = |extra: &[&str]| {
+			let args = Args::parse_from(["scene-builder", "in.yaml"].iter().chain(extra));
+			let lines = to_obj(&scene, &args, &mut Reporter::new());
+			lines
+				.iter()
+				.filter(|line| line.starts_with("Kd "))
+				.cloned()
+				.collect::<Vec<_>>()
+		};
+		assert_eq!(materials(&[]), vec!["Kd 0 0 0", "Kd 1 0 0", "Kd 0 1 0"]);
+		assert_eq!(
+			materials(&["--no-default-material"]),
+			vec!["Kd 1 0 0", "Kd 0 1 0"]
+		);
+	}
+
+	#[test]
+	fn polyline() {
+		use clap::Parser;
+		let args = Args::parse_from(["scene-builder", "in.yaml"]);
+		let docs = crate::load::load_from_str("line: [[0, 0, 0], [1, 0, 0], [1, 1, 0], [1, 1, 1]]")
+			.unwrap();
+		let scene = crate::ir::to_ir(&docs[0]).unwrap();
+		let lines = to_obj(&scene, &args, &mut Reporter::new());
+		let verts = lines.iter().filter(|line| line.starts_with("v ")).count();
+		let polylines: Vec<&String> = lines.iter().filter(|line| line.starts_with("l ")).collect();
+		assert_eq!(verts, 4);
+		assert_eq!(polylines, vec!["l -4 -3 -2 -1"]);
+	}
+
+	#[test]
+	fn nested_instance_order() {
+		use clap::Parser;
+		let args = Args::parse_from(["scene-builder", "in.yaml"]);
+		let docs = crate::load::load_from_str(
+			"instance:\n  instance:\n    strip: [[0, 0, 0], [1, 0, 0], [0, 1, 0]]\n  translate: [1, 0, 0]\nrotate: [0, 0, 90]",
+		)
+		.unwrap();
+		let scene = crate::ir::to_ir(&docs[0]).unwrap();
+		let lines = to_obj(&scene, &args, &mut Reporter::new());
+		let verts: Vec<Point3D> = lines
+			.iter()
+			.filter_map(|line| line.strip_prefix("v "))
+			.map(|coords| {
+				let vals: Vec<f64> = coords.split(' ').map(|val| val.parse().unwrap()).collect();
+				Point3D::new(vals[0], vals[1], vals[2])
+			})
+			.collect();
+		// The inner instance is translated first, then the outer instance rotates the result.
+		let expected = [
+			Point3D::new(0.0, -1.0, 0.0),
+			Point3D::new(0.0, -2.0, 0.0),
+			Point3D::new(1.0, -1.0, 0.0),
+		];
+		assert_eq!(verts.len(), expected.len());
+		for (vert, expect) in verts.iter().zip(expected) {
+			assert!((*vert - expect).magnitude() < 1e-9, "{vert:?}");
+		}
+	}
+
+	#[test]
+	fn instance_axes() {
+		use clap::Parser;
+		let args = Args::parse_from(["scene-builder", "in.yaml", "--draw-axes"]);
+		let docs = crate::load::load_from_str(
+			"instance:\n  point: [0, 0, 0]\nscale: [2, 3, 4]\nrotate: [0, 0, 90]\ntranslate: [1, 0, 0]",
+		)
+		.unwrap();
+		let scene = crate::ir::to_ir(&docs[0]).unwrap();
+		let lines = to_obj(&scene, &args, &mut Reporter::new());
+		let verts: Vec<Point3D> = lines
+			.iter()
+			.filter_map(|line| line.strip_prefix("v "))
+			.map(|coords| {
+				let vals: Vec<f64> = coords.split(' ').map(|val| val.parse().unwrap()).collect();
+				Point3D::new(vals[0], vals[1], vals[2])
+			})
+			.collect();
+		// Each axis is drawn from the translated origin. The scale applies after rotation, so the x
+		// and y axes (turned onto each other) take each other's scale.
+		for (axis, length) in [3.0, 2.0, 4.0].iter().enumerate() {
+			assert!((verts[axis * 2] - Point3D::new(1.0, 0.0, 0.0)).magnitude() < 1e-9);
+			let drawn = (verts[axis * 2 + 1] - verts[axis * 2]).magnitude();
+			assert!((drawn - length).abs() < 1e-9, "{drawn}");
+		}
+		let axes = lines.iter().filter(|line| line.starts_with("newmtl axis"));
+		assert_eq!(axes.count(), 3);
+		assert!(lines.contains(&"Kd 0 0 1".to_string()));
+	}
+
+	#[test]
+	fn vertex_colors() {
+		use clap::Parser;
+		let args = Args::parse_from(["scene-builder", "in.yaml"]);
+		let strip = "strip: [[0, 0, 0], [1, 0, 0], [0, 1, 0]]";
+		let docs = crate::load::load_from_str(&format!(
+			"{strip}\ncolors: [[255, 0, 0], [0, 255, 0], [0, 0, 255]]"
+		))
+		.unwrap();
+		let scene = crate::ir::to_ir(&docs[0]).unwrap();
+		let lines = to_obj(&scene, &args, &mut Reporter::new());
+		let verts: Vec<&String> = lines.iter().filter(|line| line.starts_with("v ")).collect();
+		assert_eq!(
+			verts,
+			vec!["v 0 0 0 1 0 0", "v 1 0 0 0 1 0", "v 0 1 0 0 0 1"]
+		);
+
+		let docs = crate::load::load_from_str(&format!("{strip}\ncolors: [[255, 0, 0]]")).unwrap();
+		let scene = crate::ir::to_ir(&docs[0]).unwrap();
+		let mut reporter = Reporter::new();
+		let lines = to_obj(&scene, &args, &mut reporter);
+		assert!(lines.iter().any(|line| line == "v 0 0 0"));
+		assert_eq!(reporter.warnings().len(), 1);
+	}
+
+	#[test]
+	fn split_vertex_colors() {
+		use clap::Parser;
+		let args = Args::parse_from(["scene-builder", "in.yaml", "--split"]);
+		let docs = crate::load::load_from_str(
+			"data:\n- strip: [[0, 0, 0], [1, 0, 0], [0, 1, 0], [1, 1, 0]]\n  colors: [[255, 0, 0], [0, \
+			 255, 0], [0, 0, 255], [255, 255, 255]]",
+		)
+		.unwrap();
+		let mut scene = crate::ir::to_ir(&docs[0]).unwrap();
+		let mut reporter = Reporter::new();
+		crate::transform::transform(&mut scene, &args, false, &mut reporter).unwrap();
+		let lines = to_obj(&scene, &args, &mut reporter);
+		// Only the vertices of the triangles (not of the box holding them) have colors
+		let verts: Vec<&String> = lines
+			.iter()
+			.filter(|line| line.starts_with("v ") && line.split(' ').count() == 7)
+			.collect();
+		assert_eq!(
+			verts,
+			vec![
+				"v 0 0 0 1 0 0",
+				"v 1 0 0 0 1 0",
+				"v 0 1 0 0 0 1",
+				"v 0 1 0 0 0 1",
+				"v 1 0 0 0 1 0",
+				"v 1 1 0 1 1 1",
+			]
+		);
+		assert!(reporter.warnings().is_empty(), "{:?}", reporter.warnings());
+	}
+
+	#[test]
+	fn restarted_strip() {
+		use clap::Parser;
+		let args = Args::parse_from(["scene-builder", "in.yaml"]);
+		let docs = crate::load::load_from_str(
+			"strip:\n- [[0, 0, 0], [1, 0, 0], [0, 1, 0], [1, 1, 0]]\n- [[5, 0, 0], [6, 0, 0], [5, 1, 0]]",
+		)
+		.unwrap();
+		let scene = crate::ir::to_ir(&docs[0]).unwrap();
+		assert_eq!(scene.strips[0].restarts, vec![4]);
+		assert_eq!(scene.triangle_count(), 3);
+		let lines = to_obj(&scene, &args, &mut Reporter::new());
+		let faces: Vec<&String> = lines.iter().filter(|line| line.starts_with("f ")).collect();
+		// The second run starts its winding over, with no triangle from the end of the first
+		assert_eq!(faces, vec!["f -3 -2 -1", "f -2 -3 -1", "f -3 -2 -1"]);
+	}
+
+	#[test]
+	fn single_object() {
+		use clap::Parser;
+		let args = Args::parse_from(["scene-builder", "in.yaml", "--single-object", "merged"]);
+		let docs = crate::load::load_from_str(
+			"data:\n- strip: [[0, 0, 0], [1, 0, 0], [0, 1, 0]]\n  color: [255, 0, 0]\n- strip: [[0, 0, \
+			 1], [1, 0, 1], [0, 1, 1]]\n  name: other",
+		)
+		.unwrap();
+		let scene = crate::ir::to_ir(&docs[0]).unwrap();
+		let lines = to_obj(&scene, &args, &mut Reporter::new());
+		let objects: Vec<&String> = lines.iter().filter(|line| line.starts_with("o ")).collect();
+		assert_eq!(objects, vec!["o merged"]);
+		assert_eq!(
+			lines.iter().filter(|line| line.starts_with("f ")).count(),
+			2
+		);
+		assert!(lines.contains(&"Kd 1 0 0".to_string()));
+	}
+
+	#[test]
+	fn extract_instance() {
+		use clap::Parser;
+		let args = Args::parse_from(["scene-builder", "in.yaml"]);
+		let text = "data:\n- strip: [[0, 0, 0], [1, 0, 0], [0, 1, 0]]\n- instance:\n    instance:\n      \
+		            strip: [[0, 0, 1], [1, 0, 1], [0, 1, 1]]\n    translate: [0, 0, 5]\n    name: \
+		            moved\n  translate: [2, 0, 0]";
+		let docs = crate::load::load_from_str(text).unwrap();
+		for (keep, vertex) in [(false, "v 0 0 1"), (true, "v 2 0 6")] {
+			let mut scene = crate::ir::to_ir(&docs[0]).unwrap();
+			crate::transform::extract(&mut scene, "moved", keep).unwrap();
+			let lines = to_obj(&scene, &args, &mut Reporter::new());
+			assert_eq!(
+				lines.iter().filter(|line| line.starts_with("f ")).count(),
+				1,
+				"{lines:?}"
+			);
+			assert!(lines.contains(&vertex.to_string()), "{lines:?}");
+		}
+		let mut scene = crate::ir::to_ir(&docs[0]).unwrap();
+		assert!(crate::transform::extract(&mut scene, "missing", false).is_err());
+	}
+
+	#[test]
+	fn smoothing_groups() {
+		use clap::Parser;
+		let args = Args::parse_from(["scene-builder", "in.yaml"]);
+		let docs = crate::load::load_from_str(
+			"data:\n- strip: [[0, 0, 0], [1, 0, 0], [0, 1, 0]]\n  smooth: 1\n- strip: [[0, 0, 1], [1, \
+			 0, 1], [0, 1, 1]]",
+		)
+		.unwrap();
+		let scene = crate::ir::to_ir(&docs[0]).unwrap();
+		let lines = to_obj(&scene, &args, &mut Reporter::new());
+		let smooth = lines.iter().position(|line| line == "s 1").unwrap();
+		let first_face = lines
+			.iter()
+			.position(|line| line.starts_with("f "))
+			.unwrap();
+		assert!(smooth < first_face, "{lines:?}");
+		// Smoothing is turned off again for the following strip
+		let off = lines.iter().position(|line| line == "s off").unwrap();
+		assert!(off > first_face, "{lines:?}");
+
+		// Nor are the faces of a box which follows
+		let args = Args::parse_from(["scene-builder", "in.yaml", "--solid-wireframe", "0.1"]);
+		let docs = crate::load::load_from_str(
+			"data:\n- strip: [[0, 0, 0], [1, 0, 0], [0, 1, 0]]\n  smooth: 1\n- min: [0, 0, 0]\n  max: \
+			 [1, 1, 1]",
+		)
+		.unwrap();
+		let mut boxed = crate::ir::to_ir(&docs[0]).unwrap();
+		crate::transform::transform(&mut boxed, &args, false, &mut Reporter::new()).unwrap();
+		let lines = to_obj(&boxed, &args, &mut Reporter::new());
+		let off = lines.iter().position(|line| line == "s off").unwrap();
+		let tubes = lines
+			.iter()
+			.rposition(|line| line.starts_with("f "))
+			.unwrap();
+		assert!(off < tubes, "{lines:?}");
+		assert!(off > lines.iter().position(|line| line == "s 1").unwrap());
+
+		let args = Args::parse_from(["scene-builder", "in.yaml", "--smooth-all"]);
+		let lines = to_obj(&scene, &args, &mut Reporter::new());
+		assert_eq!(
+			lines.iter().filter(|line| line.starts_with("s ")).count(),
+			1,
+			"{lines:?}"
+		);
+	}
+
+	#[test]
+	fn hierarchical_groups() {
+		use clap::Parser;
+		let args = Args::parse_from(["scene-builder", "in.yaml"]);
+		let docs = crate::load::load_from_str(
+			"name: city\ndata:\n- name: tall tower\n  data:\n  - strip: [[0, 0, 0], [1, 0, 0], [0, 1, \
+			 0]]\n- strip: [[0, 0, 1], [1, 0, 1], [0, 1, 1]]",
+		)
+		.unwrap();
+		let scene = crate::ir::to_ir(&docs[0]).unwrap();
+		let lines = to_obj(&scene, &args, &mut Reporter::new());
+		let groups: Vec<&String> = lines
+			.iter()
+			.filter(|line| line.starts_with("g ") || line.starts_with("o "))
+			.collect();
+		assert_eq!(
+			groups,
+			vec![
+				"g city",
+				"g city/tall_tower",
+				"o strip0",
+				"g city",
+				"o strip1"
+			]
+		);
+	}
+
+	#[test]
+	fn hex_color() {
+		use clap::Parser;
+		let args = Args::parse_from(["scene-builder", "in.yaml"]);
+		let docs = crate::load::load_from_str(
+			"data:\n- strip: [[0, 0, 0], [1, 0, 0], [0, 1, 0]]\n  color: \"#00ff00\"\n- point: [0, 0, \
+			 0]\n  color: \"#12345\"",
+		)
+		.unwrap();
+		let scene = crate::ir::to_ir(&docs[0]).unwrap();
+		let mut reporter = Reporter::new();
+		let lines = to_obj(&scene, &args, &mut reporter);
+		assert!(lines.contains(&"Kd 0 1 0".to_string()));
+		assert_eq!(
+			reporter.warnings(),
+			vec!["`color` \"#12345\" is not a hex color of the form #rrggbb!"]
+		);
+	}
+}
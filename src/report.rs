@@ -1,5 +1,254 @@
 use colored::Colorize;
 
-pub fn warn(msg: &str) {
-	eprintln!("{}: {}", "WARN".bold().yellow(), msg);
+use crate::stats::Stats;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Level {
+	Info,
+	Warn,
+}
+
+/// Collects messages to report to the user. Messages are held until flushed so that they cannot
+/// interfere with output written to stdout (and so they may be examined, such as in a report).
+pub struct Reporter {
+	messages: Vec<(Level, String)>,
+	/// Whether messages are discarded rather than printed when flushed
+	pub quiet: bool,
+	/// Whether the progress of long stages is printed to stderr as they run
+	pub progress: bool,
+	/// The name of the stage in progress, how many steps it has, how many are done, and the last
+	/// percentage printed
+	stage: Option<(String, usize, usize, usize)>,
+}
+
+impl Default for Reporter {
+	fn default() -> Reporter {
+		Reporter::new()
+	}
+}
+
+impl Reporter {
+	pub fn new() -> Reporter {
+		Reporter {
+			messages: vec![],
+			quiet: false,
+			progress: false,
+			stage: None,
+		}
+	}
+
+	/// Begin a stage of `total` steps, whose progress is printed (if enabled) as steps are done.
+	pub fn start_stage(&mut self, name: &str, total: usize) {
+		if !self.progress {
+			return;
+		}
+		// A stage with nothing to do is already done
+		let shown = if total == 0 { 100 } else { 0 };
+		eprint!("{name}: {shown}%");
+		self.stage = Some((name.to_string(), total, 0, shown));
+	}
+
+	/// Mark one more step of the current stage done. To avoid flooding stderr, progress is only
+	/// printed when the percentage done changes.
+	pub fn step(&mut self) {
+		let Some((name, total, done, shown)) = &mut self.stage else {
+			return;
+		};
+		*done += 1;
+		let percent = (*done * 100 / (*total).max(1)).min(100);
+		if percent > *shown {
+			*shown = percent;
+			eprint!("\r{name}: {percent}%");
+		}
+	}
+
+	/// End the current stage (if any), which may have stopped early.
+	pub fn end_stage(&mut self) {
+		if self.stage.take().is_some() {
+			eprintln!();
+		}
+	}
+
+	pub fn warn(&mut self, msg: &str) {
+		self.messages.push((Level::Warn, msg.to_string()));
+	}
+
+	pub fn info(&mut self, msg: &str) {
+		self.messages.push((Level::Info, msg.to_string()));
+	}
+
+	/// All warnings collected so far, in the order they were reported
+	pub fn warnings(&self) -> Vec<String> {
+		self.messages
+			.iter()
+			.filter(|(level, _)| *level == Level::Warn)
+			.map(|(_, msg)| msg.clone())
+			.collect()
+	}
+
+	/// All informational messages collected so far, in the order they were reported
+	#[cfg_attr(not(test), allow(dead_code))]
+	pub fn infos(&self) -> Vec<String> {
+		self.messages
+			.iter()
+			.filter(|(level, _)| *level == Level::Info)
+			.map(|(_, msg)| msg.clone())
+			.collect()
+	}
+
+	/// Take each message of `other` which has not been collected already, such as when the same
+	/// scene is transformed once for each of several targets.
+	pub fn merge(&mut self, other: Reporter) {
+		for message in other.messages {
+			if !self.messages.contains(&message) {
+				self.messages.push(message);
+			}
+		}
+	}
+
+	/// Print all collected messages to stderr (unless quiet), then forget them.
+	pub fn flush(&mut self) {
+		if self.quiet {
+			self.messages.clear();
+			return;
+		}
+		for (level, msg) in self.messages.drain(..) {
+			match level {
+				Level::Info => eprintln!("{}: {}", "INFO".bold().blue(), msg),
+				Level::Warn => eprintln!("{}: {}", "WARN".bold().yellow(), msg),
+			}
+		}
+	}
+}
+
+/// Quote and escape the text for use as a JSON string.
+pub fn json_string(text: &str) -> String {
+	let mut res = String::from("\"");
+	for c in text.chars() {
+		match c {
+			'"' => res.push_str("\\\""),
+			'\\' => res.push_str("\\\\"),
+			'\n' => res.push_str("\\n"),
+			'\r' => res.push_str("\\r"),
+			'\t' => res.push_str("\\t"),
+			c if (c as u32) < 0x20 => res.push_str(&format!("\\u{:04x}", c as u32)),
+			c => res.push(c),
+		}
+	}
+	res.push('"');
+	res
+}
+
+/// Format a number for JSON output, which cannot represent NaN or infinity.
+fn json_number(val: f64) -> String {
+	if val.is_finite() {
+		format!("{val}")
+	} else {
+		"null".to_string()
+	}
+}
+
+/// Build a JSON report of the outcome of verification. `result` holds the scene's statistics if
+/// verification passed, else the error which caused it to fail.
+pub fn json_report(result: &Result<Stats, String>, warnings: &[String]) -> Vec<String> {
+	let mut res = vec!["{".to_string()];
+	res.push(format!("\t\"valid\" : {},", result.is_ok()));
+	match result {
+		Ok(_) => res.push("\t\"error\" : null,".to_string()),
+		Err(e) => res.push(format!("\t\"error\" : {},", json_string(e))),
+	}
+
+	res.push("\t\"warnings\" : [".to_string());
+	for (i, warning) in warnings.iter().enumerate() {
+		let end = if i + 1 == warnings.len() { "" } else { "," };
+		res.push(format!("\t\t{}{end}", json_string(warning)));
+	}
+	res.push("\t],".to_string());
+
+	match result {
+		Ok(stats) => {
+			res.push("\t\"stats\" : {".to_string());
+			let counts = [
+				("sequences", stats.sequences),
+				("strips", stats.strips),
+				("points", stats.points),
+				("rays", stats.rays),
+				("spheres", stats.spheres),
+				("instances", stats.instances),
+				("boxes", stats.boxes),
+				("procedurals", stats.procedurals),
+				("triangles", stats.triangles),
+			];
+			for (name, count) in counts {
+				res.push(format!("\t\t\"{name}\" : {count},"));
+			}
+			for (name, pt, end) in [
+				("min_bounds", stats.min, ","),
+				("max_bounds", stats.max, ""),
+			] {
+				res.push(format!(
+					"\t\t\"{name}\" : [ {}, {}, {} ]{end}",
+					json_number(pt.x),
+					json_number(pt.y),
+					json_number(pt.z)
+				));
+			}
+			res.push("\t}".to_string());
+		},
+		Err(_) => res.push("\t\"stats\" : null".to_string()),
+	}
+	res.push("}".to_string());
+	res
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn quiet_still_collects() {
+		let mut reporter = Reporter::new();
+		reporter.quiet = true;
+		reporter.warn("`color` is not a sequence as expected!");
+		// Warnings are still available (such as for --strict) until flushed
+		assert_eq!(reporter.warnings().len(), 1);
+		reporter.flush();
+		assert!(reporter.warnings().is_empty());
+	}
+
+	#[test]
+	fn stage_counts_steps() {
+		let mut reporter = Reporter::new();
+		reporter.progress = true;
+		reporter.start_stage("Splitting", 3);
+		for _ in 0..3 {
+			reporter.step();
+		}
+		assert_eq!(reporter.stage, Some(("Splitting".to_string(), 3, 3, 100)));
+		reporter.end_stage();
+		assert_eq!(reporter.stage, None);
+		// Nothing is tracked unless progress was requested
+		reporter.progress = false;
+		reporter.start_stage("Writing BVH", 3);
+		reporter.step();
+		assert_eq!(reporter.stage, None);
+	}
+
+	#[test]
+	fn report_escapes_error() {
+		let result = Err("Could not read \"a\tb\"!".to_string());
+		let lines = json_report(&result, &["first".to_string()]);
+		assert_eq!(lines[1], "\t\"valid\" : false,");
+		assert_eq!(lines[2], "\t\"error\" : \"Could not read \\\"a\\tb\\\"!\",");
+		assert_eq!(lines[4], "\t\t\"first\"");
+	}
+
+	#[test]
+	fn warnings_in_order() {
+		let mut reporter = Reporter::new();
+		reporter.warn("one");
+		reporter.info("between");
+		reporter.warn("two");
+		assert_eq!(reporter.warnings(), vec!["one", "two"]);
+	}
 }
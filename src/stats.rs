@@ -0,0 +1,167 @@
+use crate::ir::{Node, Point3D, Scene, new_point};
+use crate::report::Reporter;
+use crate::transform::Bounding;
+
+/// Counts of the objects reachable from the scene's world. Objects referenced multiple times are
+/// only counted once.
+pub struct Stats {
+	pub sequences: usize,
+	pub strips: usize,
+	pub points: usize,
+	pub rays: usize,
+	pub spheres: usize,
+	pub instances: usize,
+	pub boxes: usize,
+	pub procedurals: usize,
+	pub triangles: usize,
+	pub min: Point3D,
+	pub max: Point3D,
+}
+
+struct Visited {
+	strips: Vec<bool>,
+	points: Vec<bool>,
+	rays: Vec<bool>,
+	spheres: Vec<bool>,
+	instances: Vec<bool>,
+	mappings: Vec<bool>,
+}
+
+fn count(scene: &Scene, node: &Node, visited: &mut Visited, stats: &mut Stats) {
+	match node {
+		Node::Strip(idx) => {
+			if !visited.strips[*idx] {
+				visited.strips[*idx] = true;
+				stats.strips += 1;
+				stats.triangles += scene.strips[*idx].triangles().len();
+			}
+		},
+		Node::Point(idx) => {
+			if !visited.points[*idx] {
+				visited.points[*idx] = true;
+				stats.points += 1;
+			}
+		},
+		Node::Ray(idx) => {
+			if !visited.rays[*idx] {
+				visited.rays[*idx] = true;
+				stats.rays += 1;
+			}
+		},
+		Node::Sphere(idx) => {
+			if !visited.spheres[*idx] {
+				visited.spheres[*idx] = true;
+				stats.spheres += 1;
+			}
+		},
+		Node::Instance(idx) => {
+			if !visited.instances[*idx] {
+				visited.instances[*idx] = true;
+				stats.instances += 1;
+				count(scene, &scene.instances[*idx].affected, visited, stats);
+			}
+		},
+		Node::Mapping(idx) => {
+			if !visited.mappings[*idx] {
+				visited.mappings[*idx] = true;
+				let map = &scene.mappings[*idx];
+				if map.is_box {
+					if map.fields.contains_key("min") {
+						stats.procedurals += 1;
+					} else {
+						stats.boxes += 1;
+					}
+				}
+				if let Some(Node::Sequence(seq_at)) = map.fields.get("data") {
+					for element in scene.sequences[*seq_at].vals.iter() {
+						count(scene, element, visited, stats);
+					}
+				}
+			}
+		},
+		_ => {},
+	}
+}
+
+/// Count the objects reachable from the scene's world, without computing its bounds (which are
+/// left NaN). Boxes are only counted if bounds were set before, since that is what marks them.
+pub fn reachable(scene: &Scene) -> Stats {
+	let mut stats = Stats {
+		sequences: scene.sequences.len(),
+		strips: 0,
+		points: 0,
+		rays: 0,
+		spheres: 0,
+		instances: 0,
+		boxes: 0,
+		procedurals: 0,
+		triangles: 0,
+		min: new_point(f64::NAN),
+		max: new_point(f64::NAN),
+	};
+	let mut visited = Visited {
+		strips: vec![false; scene.strips.len()],
+		points: vec![false; scene.points.len()],
+		rays: vec![false; scene.rays.len()],
+		spheres: vec![false; scene.spheres.len()],
+		instances: vec![false; scene.instances.len()],
+		mappings: vec![false; scene.mappings.len()],
+	};
+	count(scene, &scene.world, &mut visited, &mut stats);
+	stats
+}
+
+/// Compute statistics for the scene. This sets the bounds of the world (and thus marks boxes) as a
+/// side effect.
+pub fn stats(scene: &mut Scene, bounding: &Bounding) -> Stats {
+	let (min, max) = scene.world_bounds(bounding);
+	Stats {
+		min,
+		max,
+		..reachable(scene)
+	}
+}
+
+/// Report statistics about the scene, which are printed to stderr so as not to interfere with any
+/// output on stdout.
+pub fn report_stats(scene: &mut Scene, bounding: &Bounding, reporter: &mut Reporter) {
+	let stats = stats(scene, bounding);
+	reporter.info(&format!("sequences: {}", stats.sequences));
+	reporter.info(&format!("strips: {}", stats.strips));
+	reporter.info(&format!("points: {}", stats.points));
+	reporter.info(&format!("rays: {}", stats.rays));
+	reporter.info(&format!("spheres: {}", stats.spheres));
+	reporter.info(&format!("instances: {}", stats.instances));
+	reporter.info(&format!("boxes: {}", stats.boxes));
+	reporter.info(&format!("procedurals: {}", stats.procedurals));
+	reporter.info(&format!("triangles: {}", stats.triangles));
+	if stats.min.x.is_nan() {
+		reporter.info("bounds: none");
+	} else {
+		reporter.info(&format!(
+			"bounds: [{}, {}, {}] to [{}, {}, {}]",
+			stats.min.x, stats.min.y, stats.min.z, stats.max.x, stats.max.y, stats.max.z
+		));
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn count_shared_quads() {
+		let docs = crate::load::load_from_str(
+			"quad:\n  strip: [[0, 0, 0], [1, 0, 0], [1, 1, 0], [0, 1, 0]]\n  topology: quad\ndata:\n- \
+			 quad\n- instance: quad\n  translate: [0, 0, 1]",
+		)
+		.unwrap();
+		let mut scene = crate::ir::to_ir(&docs[0]).unwrap();
+		let stats = stats(&mut scene, &Bounding::default());
+		assert_eq!(stats.strips, 1);
+		assert_eq!(stats.instances, 1);
+		assert_eq!(stats.boxes, 1);
+		assert_eq!(stats.triangles, 2);
+		assert_eq!(stats.max, Point3D::new(1.0, 1.0, 1.0));
+	}
+}
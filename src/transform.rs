@@ -1,8 +1,55 @@
-use crate::ir::{Mapping, Node, Point3D, Scene, Sequence, Strip, as_3d, homogenize_pt, new_point};
+use std::collections::HashMap;
+
+use crate::args::{AutoBvhMode, SplitIndex, UpAxis};
+use crate::ir::{
+	Fields, Instance, Line, Mapping, Node, Point, Point3D, Ray, Scene, Sequence, Sphere, SquareMat,
+	Strip, Topology, TransformMat, as_3d, homogenize, homogenize_pt, new_point,
+};
+use crate::report::Reporter;
+use nalgebra::matrix;
+
+/// Options for how bounds are computed
+#[derive(Default)]
+pub struct Bounding {
+	/// Whether points, rays, and lines contribute to the bounds of the boxes holding them
+	pub total_box: bool,
+	/// The padding added on each side of a box along any axis where it has no extent
+	pub epsilon: f64,
+	/// Whether the padding is added along every axis, regardless of extent
+	pub epsilon_all: bool,
+}
+impl Bounding {
+	pub fn new(args: &crate::args::Args) -> Bounding {
+		Bounding {
+			total_box: args.total_box,
+			epsilon: args.epsilon,
+			epsilon_all: args.epsilon_all,
+		}
+	}
+}
+
+#[cfg(test)]
+thread_local! {
+	/// The number of calls to `set_bounds` made on this thread, which tests count
+	pub static BOUNDS_SET: std::cell::Cell<usize> = const { std::cell::Cell::new(0) };
+}
 
 impl Node {
-	/// Recursively compute and set bounds for this node and its children.
-	pub fn set_bounds(&self, scene: &mut Scene, total_box: bool) -> (Point3D, Point3D) {
+	/// The bounds of this node as last set, computing (and caching) them only if they are unknown.
+	pub fn bounds(&self, scene: &mut Scene, bounding: &Bounding) -> (Point3D, Point3D) {
+		let cached = match self {
+			Node::Instance(idx) => scene.instances[*idx].bounds,
+			Node::Mapping(idx) => scene.mappings[*idx].bounds,
+			_ => None,
+		};
+		cached.unwrap_or_else(|| self.set_bounds(scene, bounding))
+	}
+
+	/// Recursively compute and set bounds for this node and its children. The bounds of each mapping
+	/// and instance are cached for `bounds` to return.
+	pub fn set_bounds(&self, scene: &mut Scene, bounding: &Bounding) -> (Point3D, Point3D) {
+		#[cfg(test)]
+		BOUNDS_SET.with(|count| count.set(count.get() + 1));
 		match self {
 			Node::Strip(idx) => {
 				let strip = &scene.strips[*idx];
@@ -16,8 +63,13 @@ impl Node {
 				}
 				(min, max)
 			},
+			Node::Sphere(idx) => {
+				let sphere = &scene.spheres[*idx];
+				let radius = new_point(sphere.radius);
+				(sphere.center - radius, sphere.center + radius)
+			},
 			Node::Point(idx) => {
-				if total_box {
+				if bounding.total_box {
 					let point = &scene.points[*idx];
 					(point.loc, point.loc)
 				} else {
@@ -25,7 +77,7 @@ impl Node {
 				}
 			},
 			Node::Ray(idx) => {
-				if total_box {
+				if bounding.total_box {
 					let ray = &scene.rays[*idx];
 					let rmin = new_point(ray.min);
 					let extent = new_point(ray.extent);
@@ -44,11 +96,27 @@ impl Node {
 					(new_point(f64::NAN), new_point(f64::NAN))
 				}
 			},
+			Node::Line(idx) => {
+				if bounding.total_box {
+					let line = &scene.lines[*idx];
+					let mut min = new_point(f64::NAN);
+					let mut max = new_point(f64::NAN);
+					for vert in line.vals.iter() {
+						for i in 0..3 {
+							min[i] = f64::min(min[i], vert[i]);
+							max[i] = f64::max(max[i], vert[i]);
+						}
+					}
+					(min, max)
+				} else {
+					(new_point(f64::NAN), new_point(f64::NAN))
+				}
+			},
 			Node::Instance(idx) => {
 				let instance = &scene.instances[*idx];
 				let mult = instance.obj_to_world();
 				let affected = scene.instances[*idx].affected;
-				let (amin, amax) = affected.set_bounds(scene, total_box);
+				let (amin, amax) = affected.set_bounds(scene, bounding);
 
 				let mut min = new_point(f64::NAN);
 				let mut max = new_point(f64::NAN);
@@ -71,6 +139,7 @@ impl Node {
 					}
 				}
 
+				scene.instances[*idx].bounds = Some((min, max));
 				(min, max)
 			},
 			Node::Mapping(idx) => {
@@ -101,7 +170,7 @@ impl Node {
 				if let Some(Node::Sequence(idx)) = map.fields.get("data") {
 					let seq = &scene.sequences[*idx];
 					for element in seq.vals.clone() {
-						let (emin, emax) = element.set_bounds(scene, total_box);
+						let (emin, emax) = element.set_bounds(scene, bounding);
 						for i in 0..3 {
 							mins[i] = f64::min(mins[i], emin[i]);
 							maxs[i] = f64::max(maxs[i], emax[i]);
@@ -113,11 +182,22 @@ impl Node {
 				// Checking x for NaN is the same as checking any for NaN. If any max or min is set,
 				// then all must be set to some initial value. In other words, we cannot selectively
 				// set some channels but not all.
+				let map = &mut scene.mappings[*idx];
 				if !mins.x.is_nan() {
-					let map = &mut scene.mappings[*idx];
+					// Pad flat boxes so that rays grazing them are not missed
+					for i in 0..3 {
+						if bounding.epsilon_all || mins[i] == maxs[i] {
+							mins[i] -= bounding.epsilon;
+							maxs[i] += bounding.epsilon;
+						}
+					}
 					map.as_box(&mins, &maxs);
+				} else {
+					// May have been a box before some transformation removed its children
+					map.is_box = false;
 				}
 
+				map.bounds = Some((mins, maxs));
 				(mins, maxs)
 			},
 			_ => (new_point(f64::NAN), new_point(f64::NAN)),
@@ -136,6 +216,14 @@ fn replace(scene: &mut Scene, before: &Node, after: &Node, curr: &Node) {
 				replace(scene, before, after, &affected);
 			}
 		},
+		Node::Array(idx) => {
+			let affected = scene.arrays[*idx].affected;
+			if affected == *before {
+				scene.arrays[*idx].affected = *after;
+			} else {
+				replace(scene, before, after, &affected);
+			}
+		},
 		Node::Mapping(idx) => {
 			let map = &scene.mappings[*idx];
 			let mut recursives = vec![];
@@ -158,11 +246,1073 @@ fn replace(scene: &mut Scene, before: &Node, after: &Node, curr: &Node) {
 	}
 }
 
+/// Collect the indices of all strips reachable from `node`, each listed once.
+fn reachable_strips(scene: &Scene, node: &Node, strips: &mut Vec<usize>) {
+	match node {
+		Node::Strip(idx) => {
+			if !strips.contains(idx) {
+				strips.push(*idx);
+			}
+		},
+		Node::Instance(idx) => reachable_strips(scene, &scene.instances[*idx].affected, strips),
+		Node::Mapping(idx) => {
+			if let Some(Node::Sequence(idx)) = scene.mappings[*idx].fields.get("data") {
+				for element in scene.sequences[*idx].vals.iter() {
+					reachable_strips(scene, element, strips);
+				}
+			}
+		},
+		_ => {},
+	}
+}
+
+/// Whether the triangle has (nearly) zero area, as when its vertices are coincident or collinear
+fn is_degenerate(tri: &[Point3D; 3]) -> bool {
+	const DEGENERATE_EPS: f64 = 1e-10;
+	let ab = tri[1] - tri[0];
+	let ac = tri[2] - tri[0];
+	ab.cross(&ac).magnitude() <= DEGENERATE_EPS * ab.magnitude() * ac.magnitude()
+}
+
+/// Find all degenerate triangles reachable from the world, returned as (strip index, triangle
+/// ordinal within the strip) pairs.
+fn find_degenerate(scene: &Scene) -> Vec<(usize, usize)> {
+	let mut strips = vec![];
+	reachable_strips(scene, &scene.world, &mut strips);
+	let mut found = vec![];
+	for strip_idx in strips {
+		for (i, tri) in scene.strips[strip_idx].triangles().iter().enumerate() {
+			if is_degenerate(tri) {
+				found.push((strip_idx, i));
+			}
+		}
+	}
+	found
+}
+
+/// Fields which give a value for each vertex of a strip
+const PER_VERTEX_FIELDS: &[&str] = &["colors", "uv"];
+
+/// The fields for the triangle at position `ordinal` among those split from strip `strip_idx`, whose
+/// vertices are those at `verts` in the strip. Fields which give a value for each vertex only keep
+/// the values of the triangle's vertices.
+fn split_fields(
+	scene: &mut Scene,
+	strip_idx: usize,
+	verts: [usize; 3],
+	ordinal: usize,
+	split_index: SplitIndex,
+) -> Fields {
+	let strip = &scene.strips[strip_idx];
+	let fields = &strip.fields;
+	let mut res = fields.clone();
+	let mut sliced = vec![];
+	for name in PER_VERTEX_FIELDS {
+		// A sequence of the wrong length is left whole so that it is reported when used
+		if let Some(Node::Sequence(seq)) = fields.get(*name)
+			&& scene.sequences[*seq].vals.len() == strip.vals.len()
+		{
+			let mut seq_vals = Sequence::new();
+			seq_vals
+				.vals
+				.extend(verts.map(|vert| scene.sequences[*seq].vals[vert]));
+			sliced.push((*name, seq_vals));
+		}
+	}
+	for (name, seq) in sliced {
+		res.insert(name.into(), Node::Sequence(scene.sequences.len()));
+		scene.sequences.push(seq);
+	}
+	match split_index {
+		SplitIndex::Increment => {
+			if let Some(Node::Number(idx)) = res.get("primitive_index") {
+				res.insert("primitive_index".into(), Node::Number(idx + ordinal as f64));
+			}
+		},
+		SplitIndex::Drop => {
+			res.remove("primitive_index");
+		},
+		SplitIndex::Copy => {},
+	}
+	res
+}
+
+/// Replace each strip holding a degenerate triangle with a mapping of its other triangles.
+fn drop_degenerate(scene: &mut Scene, degenerate: &[(usize, usize)], split_index: SplitIndex) {
+	let mut strips: Vec<usize> = degenerate.iter().map(|(strip_idx, _)| *strip_idx).collect();
+	strips.dedup();
+	let world = scene.world;
+	for strip_idx in strips {
+		let seq_at = scene.sequences.len();
+		scene.sequences.push(Sequence::new());
+		let map_at = scene.mappings.len();
+		scene.mappings.push(Mapping::new());
+		scene.mappings[map_at]
+			.fields
+			.insert("data".into(), Node::Sequence(seq_at));
+
+		let before = Node::Strip(strip_idx);
+		let after = Node::Mapping(map_at);
+		if world == before {
+			scene.world = after;
+		} else {
+			replace(scene, &before, &after, &world);
+		}
+
+		let mut children = vec![];
+		for (i, verts) in scene.strips[strip_idx]
+			.triangle_indices()
+			.into_iter()
+			.enumerate()
+		{
+			if degenerate.contains(&(strip_idx, i)) {
+				continue;
+			}
+			let mut child = Strip::new();
+			child
+				.vals
+				.extend(verts.map(|vert| scene.strips[strip_idx].vals[vert]));
+			child.fields = split_fields(scene, strip_idx, verts, children.len(), split_index);
+			children.push(child);
+		}
+		for child in children {
+			let kid_at = scene.strips.len();
+			scene.strips.push(child);
+			scene.sequences[seq_at].vals.push(Node::Strip(kid_at));
+		}
+	}
+	scene.invalidate_bounds();
+}
+
+/// Whether the box from `min` to `max`, once moved by `transform`, lies entirely outside the clip
+/// region. Empty bounds (NaN) are never considered outside.
+fn box_outside(
+	min: &Point3D,
+	max: &Point3D,
+	transform: &SquareMat,
+	clip: &(Point3D, Point3D),
+) -> bool {
+	if min.x.is_nan() {
+		return false;
+	}
+	let mut wmin = new_point(f64::INFINITY);
+	let mut wmax = new_point(f64::NEG_INFINITY);
+	for i in 0..8 {
+		let mut point = new_point(0.0);
+		for j in 0..3 {
+			point[j] = if ((i >> j) & 1) == 1 { max[j] } else { min[j] }
+		}
+		let vert = transform * homogenize_pt(&point);
+		for j in 0..3 {
+			wmin[j] = f64::min(wmin[j], vert[j]);
+			wmax[j] = f64::max(wmax[j], vert[j]);
+		}
+	}
+	(0..3).any(|j| wmax[j] < clip.0[j] || wmin[j] > clip.1[j])
+}
+
+/// Whether the bounds of `node` (in the space given by `transform`) lie entirely outside the clip
+/// region. Assumes that bounds have already been set on all mappings.
+fn node_outside(
+	scene: &mut Scene,
+	node: &Node,
+	transform: &SquareMat,
+	clip: &(Point3D, Point3D),
+	bounding: &Bounding,
+) -> bool {
+	match node {
+		Node::Strip(_) | Node::Point(_) | Node::Ray(_) | Node::Line(_) | Node::Sphere(_) => {
+			let (min, max) = node.set_bounds(scene, bounding);
+			box_outside(&min, &max, transform, clip)
+		},
+		Node::Instance(idx) => {
+			let instance = &scene.instances[*idx];
+			let mult = transform * homogenize(&instance.obj_to_world());
+			let affected = instance.affected;
+			node_outside(scene, &affected, &mult, clip, bounding)
+		},
+		Node::Mapping(idx) => {
+			let map = &scene.mappings[*idx];
+			map.is_box && box_outside(&map.min, &map.max, transform, clip)
+		},
+		_ => false,
+	}
+}
+
+/// Mark which elements of each reachable data sequence have bounds overlapping the clip region.
+/// Since a sequence may be reached through several instances, an element is kept if it overlaps
+/// through any of them.
+fn mark_clip(
+	scene: &mut Scene,
+	node: &Node,
+	transform: &SquareMat,
+	clip: &(Point3D, Point3D),
+	bounding: &Bounding,
+	keep: &mut HashMap<usize, Vec<bool>>,
+) {
+	match node {
+		Node::Instance(idx) => {
+			let instance = &scene.instances[*idx];
+			let mult = transform * homogenize(&instance.obj_to_world());
+			let affected = instance.affected;
+			mark_clip(scene, &affected, &mult, clip, bounding, keep);
+		},
+		Node::Mapping(idx) => {
+			if let Some(Node::Sequence(seq_idx)) = scene.mappings[*idx].fields.get("data") {
+				let seq_idx = *seq_idx;
+				let elements = scene.sequences[seq_idx].vals.clone();
+				keep.entry(seq_idx)
+					.or_insert_with(|| vec![false; elements.len()]);
+				for (i, element) in elements.iter().enumerate() {
+					if !node_outside(scene, element, transform, clip, bounding) {
+						keep.get_mut(&seq_idx).unwrap()[i] = true;
+						mark_clip(scene, element, transform, clip, bounding, keep);
+					}
+				}
+			}
+		},
+		_ => {},
+	}
+}
+
+/// Remove any node whose bounds lie entirely outside the clip region. Nodes partially inside are
+/// kept whole. Bounds must have been set before calling.
+fn clip(scene: &mut Scene, clip: &(Point3D, Point3D), bounding: &Bounding) {
+	let world = scene.world;
+	let identity = SquareMat::identity();
+	if node_outside(scene, &world, &identity, clip, bounding) {
+		let map_at = scene.mappings.len();
+		scene.mappings.push(Mapping::new());
+		scene.world = Node::Mapping(map_at);
+		return;
+	}
+
+	let mut keep = HashMap::new();
+	mark_clip(scene, &world, &identity, clip, bounding, &mut keep);
+	for (seq_idx, kept) in keep {
+		let mut kept = kept.into_iter();
+		scene.sequences[seq_idx]
+			.vals
+			.retain(|_| kept.next().unwrap());
+	}
+}
+
+/// Replace each array with a mapping of instances of its (shared) child, one per grid position.
+pub fn expand_arrays(scene: &mut Scene) {
+	for array_idx in 0..scene.arrays.len() {
+		let array = &scene.arrays[array_idx];
+		let mut instances = vec![];
+		for k in 0..array.count[2] {
+			for j in 0..array.count[1] {
+				for i in 0..array.count[0] {
+					let steps = Point3D::new(i as f64, j as f64, k as f64);
+					instances.push(Instance {
+						affected: array.affected,
+						scale: Point3D::new(
+							array.scale.x.powf(steps.x),
+							array.scale.y.powf(steps.y),
+							array.scale.z.powf(steps.z),
+						),
+						rotate: array.rotate.component_mul(&steps),
+						translate: array.translate.component_mul(&steps),
+						baked: None,
+						fields: array.fields.clone(),
+						bounds: None,
+					});
+				}
+			}
+		}
+
+		let seq_at = scene.sequences.len();
+		scene.sequences.push(Sequence::new());
+		for instance in instances {
+			scene.sequences[seq_at]
+				.vals
+				.push(Node::Instance(scene.instances.len()));
+			scene.instances.push(instance);
+		}
+		let map_at = scene.mappings.len();
+		scene.mappings.push(Mapping::new());
+		scene.mappings[map_at]
+			.fields
+			.insert("data".into(), Node::Sequence(seq_at));
+
+		let world = scene.world;
+		let before = Node::Array(array_idx);
+		let after = Node::Mapping(map_at);
+		if world == before {
+			scene.world = after;
+		} else {
+			replace(scene, &before, &after, &world);
+		}
+	}
+}
+
+/// Mirror the whole scene across the xy plane by negating every z coordinate, as converts between
+/// left- and right-handed coordinate systems.
+fn negate_z(scene: &mut Scene) {
+	scene.invalidate_bounds();
+	let mirror = Point3D::new(1.0, 1.0, -1.0);
+	for strip in scene.strips.iter_mut() {
+		for vert in strip.vals.iter_mut() {
+			vert.z = -vert.z;
+		}
+	}
+	for point in scene.points.iter_mut() {
+		point.loc.z = -point.loc.z;
+	}
+	for ray in scene.rays.iter_mut() {
+		ray.origin.z = -ray.origin.z;
+		ray.direction.z = -ray.direction.z;
+	}
+	for line in scene.lines.iter_mut() {
+		for vert in line.vals.iter_mut() {
+			vert.z = -vert.z;
+		}
+	}
+	for sphere in scene.spheres.iter_mut() {
+		sphere.center.z = -sphere.center.z;
+	}
+	for instance in scene.instances.iter_mut() {
+		// Conjugating by the mirror reverses the rotations about the axes within the plane
+		instance.rotate.x = -instance.rotate.x;
+		instance.rotate.y = -instance.rotate.y;
+		instance.translate.z = -instance.translate.z;
+		if let Some(baked) = &instance.baked {
+			let mirror = SquareMat::from_diagonal(&homogenize_pt(&mirror));
+			let mirrored = mirror * homogenize(baked) * mirror;
+			instance.baked = Some(mirrored.fixed_view::<3, 4>(0, 0).into_owned());
+		}
+	}
+	for map_idx in 0..scene.mappings.len() {
+		let fields = &scene.mappings[map_idx].fields;
+		let (Some(min), Some(max)) = (fields.get("min"), fields.get("max")) else {
+			continue;
+		};
+		let (Ok(min), Ok(max)) = (as_3d(scene, min), as_3d(scene, max)) else {
+			continue;
+		};
+		// Mirrored, the old max is the new min along z (and the corners may be shared)
+		let corners = [
+			("min", Point3D::new(min.x, min.y, -max.z)),
+			("max", Point3D::new(max.x, max.y, -min.z)),
+		];
+		for (name, corner) in corners {
+			let seq_at = scene.sequences.len();
+			let mut seq = Sequence::new();
+			seq.vals.extend(corner.iter().map(|c| Node::Number(*c)));
+			scene.sequences.push(seq);
+			scene.mappings[map_idx]
+				.fields
+				.insert(name.into(), Node::Sequence(seq_at));
+		}
+	}
+}
+
+/// Wrap `node` in an instance which applies the object-to-world transform `transform` directly.
+fn baked_instance(scene: &mut Scene, node: Node, transform: &SquareMat) -> Node {
+	let world_to_obj = match transform.try_inverse() {
+		Some(inverse) => inverse.fixed_view::<3, 4>(0, 0).into_owned(),
+		None => TransformMat::from_element(f64::NAN),
+	};
+	let inst_at = scene.instances.len();
+	scene.instances.push(Instance {
+		affected: node,
+		scale: new_point(1.0),
+		rotate: new_point(0.0),
+		translate: new_point(0.0),
+		baked: Some(world_to_obj),
+		fields: HashMap::new(),
+		bounds: None,
+	});
+	Node::Instance(inst_at)
+}
+
+/// The first mapping or instance reachable from `node` which has the name `name`, found depth-first,
+/// together with the object-to-world transform of the space it is within
+fn find_named(
+	scene: &Scene,
+	node: &Node,
+	name: &str,
+	transform: &SquareMat,
+) -> Option<(Node, SquareMat)> {
+	let fields = match node {
+		Node::Instance(idx) => &scene.instances[*idx].fields,
+		Node::Mapping(idx) => &scene.mappings[*idx].fields,
+		Node::Sequence(idx) => {
+			return scene.sequences[*idx]
+				.vals
+				.iter()
+				.find_map(|val| find_named(scene, val, name, transform));
+		},
+		_ => return None,
+	};
+	if let Some(Node::String(str_at)) = fields.get("name")
+		&& scene.strings[*str_at] == name
+	{
+		return Some((*node, *transform));
+	}
+	match node {
+		Node::Instance(idx) => {
+			let instance = &scene.instances[*idx];
+			let inner = transform * homogenize(&instance.obj_to_world());
+			find_named(scene, &instance.affected, name, &inner)
+		},
+		_ => fields
+			.get("data")
+			.and_then(|data| find_named(scene, data, name, transform)),
+	}
+}
+
+/// Replace the world by the mapping or instance named `name` within it. The object is in its own
+/// space, such that a named instance gives what it affects untransformed, unless `keep_transform`,
+/// where the object keeps the transform it had in the world.
+pub fn extract(scene: &mut Scene, name: &str, keep_transform: bool) -> Result<(), String> {
+	let Some((found, mut transform)) =
+		find_named(scene, &scene.world, name, &SquareMat::identity())
+	else {
+		return Err(format!(
+			"Cannot extract \"{name}\" since no mapping or instance in the world has that name!"
+		));
+	};
+	let object = match found {
+		Node::Instance(idx) => {
+			let instance = &scene.instances[idx];
+			transform *= homogenize(&instance.obj_to_world());
+			instance.affected
+		},
+		_ => found,
+	};
+	scene.world = if !keep_transform || transform == SquareMat::identity() {
+		object
+	} else {
+		baked_instance(scene, object, &transform)
+	};
+	Ok(())
+}
+
+/// Fields which only describe an instance itself, so they are not given to what it affects once
+/// flattened
+const INSTANCE_ONLY_FIELDS: &[&str] = &["custom_index", "id", "mask", "sbt_record_offset"];
+
+/// The fields of a flattened object: its own, over those `inherited` from the enclosing instances
+/// which were flattened away. An inherited `color_override` instead takes precedence, as it would
+/// have in the instance.
+fn inherit_fields(own: &Fields, inherited: &Fields) -> Fields {
+	let mut fields = inherited.clone();
+	fields.extend(own.iter().map(|(name, val)| (name.clone(), *val)));
+	if let Some(color) = inherited.get("color_override") {
+		fields.insert("color_override".into(), *color);
+		fields.insert("color".into(), *color);
+	}
+	fields
+}
+
+/// Copy `node` and all of its children with `transform` applied directly to the geometry, such that
+/// no instances are needed. Returns the copy. The fields of the instances flattened away are given
+/// by `inherited`, which the copy takes (its own fields taking precedence). Anything which cannot be
+/// represented once transformed (such as a sphere under non-uniform scale) is kept under a single
+/// instance with the inherited fields, with a warning.
+fn flatten_node(
+	scene: &mut Scene,
+	node: &Node,
+	transform: &SquareMat,
+	inherited: &Fields,
+	reporter: &mut Reporter,
+) -> Node {
+	// Only instances may override the color, so other objects take the override as their color
+	let leaf_fields = |own: &Fields| {
+		let mut fields = inherit_fields(own, inherited);
+		fields.remove("color_override");
+		fields
+	};
+	let kept = |scene: &mut Scene, node: &Node| {
+		let kept = baked_instance(scene, *node, transform);
+		if let Node::Instance(idx) = kept {
+			scene.instances[idx].fields = inherited.clone();
+		}
+		kept
+	};
+	let apply = |pt: &Point3D| (transform * homogenize_pt(pt)).xyz();
+	let linear = transform.fixed_view::<3, 3>(0, 0).into_owned();
+	match node {
+		Node::Strip(idx) => {
+			let strip = &scene.strips[*idx];
+			let flat = Strip {
+				vals: strip.vals.iter().map(apply).collect(),
+				topology: strip.topology,
+				restarts: strip.restarts.clone(),
+				fields: leaf_fields(&strip.fields),
+			};
+			scene.strips.push(flat);
+			Node::Strip(scene.strips.len() - 1)
+		},
+		Node::Point(idx) => {
+			let point = &scene.points[*idx];
+			let flat = Point {
+				loc: apply(&point.loc),
+				fields: leaf_fields(&point.fields),
+			};
+			scene.points.push(flat);
+			Node::Point(scene.points.len() - 1)
+		},
+		Node::Ray(idx) => {
+			let ray = &scene.rays[*idx];
+			// The direction is transformed without normalizing so the extents remain valid
+			let flat = Ray {
+				origin: apply(&ray.origin),
+				direction: linear * ray.direction,
+				extent: ray.extent,
+				min: ray.min,
+				fields: leaf_fields(&ray.fields),
+			};
+			scene.rays.push(flat);
+			Node::Ray(scene.rays.len() - 1)
+		},
+		Node::Line(idx) => {
+			let line = &scene.lines[*idx];
+			let flat = Line {
+				vals: line.vals.iter().map(apply).collect(),
+				fields: leaf_fields(&line.fields),
+			};
+			scene.lines.push(flat);
+			Node::Line(scene.lines.len() - 1)
+		},
+		Node::Sphere(idx) => {
+			// Only a uniform scale (with any rotation) keeps a sphere spherical
+			let gram = linear.transpose() * linear;
+			let scale_sq = gram[(0, 0)];
+			if (gram - nalgebra::Matrix3::identity() * scale_sq).amax() > 1e-9 * scale_sq {
+				reporter.warn(&format!(
+					"Sphere {idx} cannot be flattened since it is not scaled uniformly!"
+				));
+				return kept(scene, node);
+			}
+			let sphere = &scene.spheres[*idx];
+			let flat = Sphere {
+				center: apply(&sphere.center),
+				radius: sphere.radius * scale_sq.sqrt(),
+				fields: leaf_fields(&sphere.fields),
+			};
+			scene.spheres.push(flat);
+			Node::Sphere(scene.spheres.len() - 1)
+		},
+		Node::Instance(idx) => {
+			let instance = &scene.instances[*idx];
+			let mult = transform * homogenize(&instance.obj_to_world());
+			let mut own = instance.fields.clone();
+			own.retain(|name, _| !INSTANCE_ONLY_FIELDS.contains(&name.as_str()));
+			let inner = inherit_fields(&own, inherited);
+			let affected = instance.affected;
+			flatten_node(scene, &affected, &mult, &inner, reporter)
+		},
+		Node::Mapping(idx) => {
+			let mut fields = leaf_fields(&scene.mappings[*idx].fields);
+			if fields.contains_key("min") || fields.contains_key("max") {
+				// Explicit bounds can only stay axis-aligned if there is no rotation
+				let mut rotated = false;
+				for r in 0..3 {
+					for c in 0..3 {
+						rotated |= r != c && linear[(r, c)] != 0.0;
+					}
+				}
+				if rotated {
+					reporter.warn(&format!(
+						"Mapping {idx} cannot be flattened since its bounds would be rotated!"
+					));
+					return kept(scene, node);
+				}
+				for name in ["min", "max"] {
+					if let Some(bound) = fields.get(name)
+						&& let Ok(pt) = as_3d(scene, bound)
+					{
+						let moved = apply(&pt);
+						let seq_at = scene.sequences.len();
+						let mut seq = Sequence::new();
+						seq.vals.extend(moved.iter().map(|c| Node::Number(*c)));
+						scene.sequences.push(seq);
+						fields.insert(name.into(), Node::Sequence(seq_at));
+					}
+				}
+			}
+			if let Some(Node::Sequence(seq_idx)) = fields.get("data") {
+				// The mapping holds the other inherited fields for its children, as the instance did
+				let mut overridden = Fields::new();
+				if let Some(color) = inherited.get("color_override") {
+					overridden.insert("color_override".into(), *color);
+				}
+				let mut seq = Sequence::new();
+				for element in scene.sequences[*seq_idx].vals.clone() {
+					seq.vals.push(flatten_node(
+						scene,
+						&element,
+						transform,
+						&overridden,
+						reporter,
+					));
+				}
+				scene.sequences.push(seq);
+				fields.insert("data".into(), Node::Sequence(scene.sequences.len() - 1));
+			}
+			let mut map = Mapping::new();
+			map.fields = fields;
+			scene.mappings.push(map);
+			Node::Mapping(scene.mappings.len() - 1)
+		},
+		_ => *node,
+	}
+}
+
+/// A small pseudo-random number generator (SplitMix64). It is used instead of any system source so
+/// that the same seed gives the same sequence on every machine.
+struct Rng {
+	state: u64,
+}
+impl Rng {
+	fn new(seed: u64) -> Rng {
+		Rng { state: seed }
+	}
+
+	fn next_u64(&mut self) -> u64 {
+		self.state = self.state.wrapping_add(0x9E3779B97F4A7C15);
+		let mut z = self.state;
+		z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+		z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+		z ^ (z >> 31)
+	}
+
+	/// A uniformly distributed value in [-1, 1)
+	fn next_signed(&mut self) -> f64 {
+		// Use the top 53 bits so that every value is exactly representable
+		let unit = (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64;
+		unit * 2.0 - 1.0
+	}
+}
+
+/// Move every vertex of every reachable strip by a pseudo-random offset of up to `amount` in each
+/// component.
+fn jitter(scene: &mut Scene, amount: f64, seed: u64) {
+	scene.invalidate_bounds();
+	let mut rng = Rng::new(seed);
+	let mut strips = vec![];
+	reachable_strips(scene, &scene.world, &mut strips);
+	for strip_idx in strips {
+		for vert in scene.strips[strip_idx].vals.iter_mut() {
+			for i in 0..3 {
+				vert[i] += rng.next_signed() * amount;
+			}
+		}
+	}
+}
+
 /// Transformation "main", so to speak. Launches all requested transformations on the scene.
 /// @param scene The scene to transform
 /// @param args Program arguments which are used to enable various options
 /// @param triangle Whether to split tri-strips into individual triangles
-pub fn transform(scene: &mut Scene, args: &crate::args::Args, triangle: bool) {
+/// @param reporter Collects any warnings about the scene
+/// The surface area of the box bounded by `min` and `max`
+pub fn surface_area(min: &Point3D, max: &Point3D) -> f64 {
+	let d = max - min;
+	2.0 * (d.x * d.y + d.y * d.z + d.z * d.x)
+}
+
+/// Choose where to split the elements (with their bounds) in two by the surface area heuristic.
+/// Returns the number of elements which go into the first half, after the elements have been
+/// sorted along the chosen axis, and the estimated cost of the split. The cost of intersecting
+/// each element is taken to be 1, as is the cost of traversing the new box.
+fn split_sah(elements: &mut [(Node, Point3D, Point3D)]) -> (usize, f64) {
+	let count = elements.len();
+	let centroid = |elem: &(Node, Point3D, Point3D), axis: usize| elem.1[axis] + elem.2[axis];
+	let union = |(min, max): (Point3D, Point3D), elem: &(Node, Point3D, Point3D)| {
+		(min.inf(&elem.1), max.sup(&elem.2))
+	};
+	let empty = (new_point(f64::INFINITY), new_point(f64::NEG_INFINITY));
+	let (total_min, total_max) = elements.iter().fold(empty, union);
+	let total_area = surface_area(&total_min, &total_max);
+
+	let mut best = (0, 0, f64::INFINITY);
+	for axis in 0..3 {
+		elements.sort_by(|a, b| centroid(a, axis).total_cmp(&centroid(b, axis)));
+		// The area of every suffix of the elements, to be compared against each prefix
+		let mut after = vec![0.0; count];
+		let mut bounds = empty;
+		for i in (1..count).rev() {
+			bounds = union(bounds, &elements[i]);
+			after[i] = surface_area(&bounds.0, &bounds.1);
+		}
+		let mut bounds = empty;
+		for i in 1..count {
+			bounds = union(bounds, &elements[i - 1]);
+			let before = surface_area(&bounds.0, &bounds.1);
+			let cost = if total_area > 0.0 {
+				1.0 + (before * i as f64 + after[i] * (count - i) as f64) / total_area
+			} else {
+				// Without area to compare, prefer an even split
+				1.0 + f64::max(i as f64, (count - i) as f64)
+			};
+			if cost < best.2 {
+				best = (axis, i, cost);
+			}
+		}
+	}
+	let (axis, at_split, cost) = best;
+	elements.sort_by(|a, b| centroid(a, axis).total_cmp(&centroid(b, axis)));
+	(at_split, cost)
+}
+
+/// Choose where to split the elements (with their bounds) in two at the median centroid along the
+/// axis where centroids are most spread. Returns the number of elements which go into the first
+/// half, after the elements have been reordered to put them first. Takes linear time.
+fn split_median(elements: &mut [(Node, Point3D, Point3D)]) -> usize {
+	let centroid = |elem: &(Node, Point3D, Point3D)| elem.1 + elem.2;
+	let mut min = new_point(f64::INFINITY);
+	let mut max = new_point(f64::NEG_INFINITY);
+	for elem in elements.iter() {
+		min = min.inf(&centroid(elem));
+		max = max.sup(&centroid(elem));
+	}
+	let axis = (max - min).imax();
+	let mid = elements.len() / 2;
+	elements.select_nth_unstable_by(mid, |a, b| centroid(a)[axis].total_cmp(&centroid(b)[axis]));
+	mid
+}
+
+/// Partition the elements (with their bounds) into a tree of boxes, returning the nodes which
+/// should be held by the box enclosing all of them. Any group of more than `leaf_size` elements is
+/// split. If `leaf_size` is 0, groups are split wherever `mode` finds it worthwhile.
+fn partition(
+	scene: &mut Scene,
+	elements: &mut [(Node, Point3D, Point3D)],
+	leaf_size: usize,
+	mode: AutoBvhMode,
+) -> Vec<Node> {
+	let count = elements.len();
+	if count <= 1 || (leaf_size != 0 && count <= leaf_size) {
+		return elements.iter().map(|elem| elem.0).collect();
+	}
+	let (at_split, cost) = match mode {
+		AutoBvhMode::Sah => split_sah(elements),
+		// The median split has no estimate, so it is always taken
+		AutoBvhMode::Median => (split_median(elements), 0.0),
+	};
+	if leaf_size == 0 && cost >= count as f64 {
+		return elements.iter().map(|elem| elem.0).collect();
+	}
+
+	let (first, second) = elements.split_at_mut(at_split);
+	let mut children = vec![];
+	for half in [first, second] {
+		if half.len() == 1 {
+			children.push(half[0].0);
+			continue;
+		}
+		let seq = Sequence {
+			vals: partition(scene, half, leaf_size, mode),
+		};
+		let seq_at = scene.sequences.len();
+		scene.sequences.push(seq);
+		let mut map = Mapping::new();
+		map.fields.insert("data".into(), Node::Sequence(seq_at));
+		children.push(Node::Mapping(scene.mappings.len()));
+		scene.mappings.push(map);
+	}
+	children
+}
+
+/// Replace the data of each mapping reachable from `node` with a tree of boxes partitioning it.
+/// Mappings which already exist are kept whole, so any authored grouping is respected.
+fn auto_bvh(
+	scene: &mut Scene,
+	node: &Node,
+	bounding: &Bounding,
+	leaf_size: usize,
+	mode: AutoBvhMode,
+	visited: &mut [bool],
+) {
+	match node {
+		Node::Instance(idx) => {
+			let affected = scene.instances[*idx].affected;
+			auto_bvh(scene, &affected, bounding, leaf_size, mode, visited);
+		},
+		Node::Mapping(idx) => {
+			if visited[*idx] {
+				return;
+			}
+			visited[*idx] = true;
+			let Some(Node::Sequence(seq_at)) = scene.mappings[*idx].fields.get("data") else {
+				return;
+			};
+			let vals = scene.sequences[*seq_at].vals.clone();
+			let mut elements = vec![];
+			let mut unbounded = vec![];
+			for element in vals {
+				auto_bvh(scene, &element, bounding, leaf_size, mode, visited);
+				let (min, max) = element.set_bounds(scene, bounding);
+				if min.x.is_nan() {
+					unbounded.push(element);
+				} else {
+					elements.push((element, min, max));
+				}
+			}
+			// The data sequence may be shared, so the partition gets a sequence of its own
+			let mut seq = Sequence::new();
+			seq.vals = partition(scene, &mut elements, leaf_size, mode);
+			seq.vals.extend(unbounded);
+			let seq_at = scene.sequences.len();
+			scene.sequences.push(seq);
+			scene.mappings[*idx]
+				.fields
+				.insert("data".into(), Node::Sequence(seq_at));
+		},
+		_ => {},
+	}
+}
+
+/// Collapse each box whose only child is a box with the same bounds and fields (other than `data`)
+/// by taking the child's data as its own, which saves a traversal step. The child is left in place
+/// for any other references to it.
+fn merge_coincident_boxes(scene: &mut Scene) {
+	let is_plain_box = |map: &Mapping| map.is_box && !map.fields.contains_key("min");
+	for map_idx in 0..scene.mappings.len() {
+		// A chain of coincident boxes collapses one link at a time
+		loop {
+			let map = &scene.mappings[map_idx];
+			if !is_plain_box(map) {
+				break;
+			}
+			let Some(Node::Sequence(seq_at)) = map.fields.get("data") else {
+				break;
+			};
+			let [Node::Mapping(child_idx)] = scene.sequences[*seq_at].vals[..] else {
+				break;
+			};
+			let child = &scene.mappings[child_idx];
+			let same_fields = map.fields.len() == child.fields.len()
+				&& map
+					.fields
+					.iter()
+					.all(|(key, val)| *key == "data" || child.fields.get(key) == Some(val));
+			if child_idx == map_idx
+				|| !is_plain_box(child)
+				|| child.min != map.min
+				|| child.max != map.max
+				|| !same_fields
+			{
+				break;
+			}
+			let Some(data) = child.fields.get("data").copied() else {
+				break;
+			};
+			scene.mappings[map_idx].fields.insert("data".into(), data);
+		}
+	}
+}
+
+/// Remove each box which holds a single child (and has no fields other than `data`), linking the
+/// box's parents to the child directly. The world is kept, as are the boxes held by instances if
+/// `wrap` requires them.
+fn flatten_single_child_boxes(scene: &mut Scene, wrap: bool) {
+	/// The node in place of `node` once any chain of removable boxes is skipped over
+	fn skip_boxes(scene: &Scene, mut node: Node) -> Node {
+		while let Node::Mapping(idx) = node {
+			let map = &scene.mappings[idx];
+			let Some(Node::Sequence(seq_at)) = map.fields.get("data") else {
+				break;
+			};
+			match scene.sequences[*seq_at].vals[..] {
+				[child] if map.is_box && map.fields.len() == 1 => node = child,
+				_ => break,
+			}
+		}
+		node
+	}
+
+	fn relink(scene: &mut Scene, node: &Node, wrap: bool, visited: &mut Vec<bool>) {
+		match node {
+			Node::Instance(idx) => {
+				if !wrap {
+					scene.instances[*idx].affected =
+						skip_boxes(scene, scene.instances[*idx].affected);
+				}
+				let affected = scene.instances[*idx].affected;
+				relink(scene, &affected, wrap, visited);
+			},
+			Node::Mapping(idx) => {
+				if visited[*idx] {
+					return;
+				}
+				visited[*idx] = true;
+				let Some(Node::Sequence(seq_at)) = scene.mappings[*idx].fields.get("data").copied()
+				else {
+					return;
+				};
+				for i in 0..scene.sequences[seq_at].vals.len() {
+					let child = skip_boxes(scene, scene.sequences[seq_at].vals[i]);
+					scene.sequences[seq_at].vals[i] = child;
+					relink(scene, &child, wrap, visited);
+				}
+			},
+			_ => {},
+		}
+	}
+
+	let world = scene.world;
+	let mut visited = vec![false; scene.mappings.len()];
+	relink(scene, &world, wrap, &mut visited);
+}
+
+/// An instance of the node with the identity transform
+fn world_instance(world: Node) -> Instance {
+	Instance {
+		affected: world,
+		scale: new_point(1.0),
+		rotate: new_point(0.0),
+		translate: new_point(0.0),
+		baked: None,
+		fields: HashMap::new(),
+		bounds: None,
+	}
+}
+
+/// Transform the world by the instance (of the world). If `flattened`, the transform is baked into
+/// the world's geometry, else the instance becomes the world.
+fn transform_world(
+	scene: &mut Scene,
+	instance: Instance,
+	flattened: bool,
+	reporter: &mut Reporter,
+) {
+	let world = instance.affected;
+	scene.world = if flattened {
+		let transform = homogenize(&instance.obj_to_world());
+		flatten_node(scene, &world, &transform, &HashMap::new(), reporter)
+	} else {
+		scene.instances.push(instance);
+		Node::Instance(scene.instances.len() - 1)
+	};
+	scene.invalidate_bounds();
+}
+
+/// Verify that no box has an authored `min` greater than its `max` on any axis. If `repair`, the
+/// inverted components are swapped instead.
+fn check_bounds(scene: &mut Scene, repair: bool) -> Result<(), String> {
+	for map_idx in 0..scene.mappings.len() {
+		let map = &scene.mappings[map_idx];
+		if !map.is_box {
+			continue;
+		}
+		let (Some(min_node), Some(max_node)) = (map.fields.get("min"), map.fields.get("max"))
+		else {
+			continue;
+		};
+		let (Ok(mut min), Ok(mut max)) = (as_3d(scene, min_node), as_3d(scene, max_node)) else {
+			continue;
+		};
+		let mut inverted = false;
+		for (i, axis) in ["x", "y", "z"].iter().enumerate() {
+			if min[i] <= max[i] {
+				continue;
+			}
+			if !repair {
+				let name = match map.fields.get("name") {
+					Some(Node::String(str_at)) => {
+						format!("Procedural \"{}\"", scene.strings[*str_at])
+					},
+					_ => format!("Procedural {map_idx}"),
+				};
+				return Err(format!(
+					"{name} has `min` ({}) greater than its `max` ({}) on the {axis} axis!",
+					min[i], max[i]
+				));
+			}
+			std::mem::swap(&mut min[i], &mut max[i]);
+			inverted = true;
+		}
+		if inverted {
+			// The repaired corners get their own sequences, since the originals may be shared
+			for (name, corner) in [("min", min), ("max", max)] {
+				let seq_at = scene.sequences.len();
+				let mut seq = Sequence::new();
+				seq.vals.extend(corner.iter().map(|c| Node::Number(*c)));
+				scene.sequences.push(seq);
+				scene.mappings[map_idx]
+					.fields
+					.insert(name.into(), Node::Sequence(seq_at));
+			}
+		}
+	}
+	Ok(())
+}
+
+/// Whether preparing the scene for the BVH target (as `transform` does if `bvh`) can change it from
+/// what other targets are given. Only spheres and strips not already split are prepared differently.
+pub fn differs_for_bvh(scene: &Scene, args: &crate::args::Args) -> bool {
+	!scene.spheres.is_empty()
+		|| (!args.split && scene.strips.iter().any(|strip| strip.vals.len() > 3))
+}
+
+/// Apply the transformations requested by `args` to the scene. If `bvh`, the scene is also prepared
+/// for the BVH target: strips are split into triangles (as they are with --split) and spheres become
+/// procedurals.
+pub fn transform(
+	scene: &mut Scene,
+	args: &crate::args::Args,
+	bvh: bool,
+	reporter: &mut Reporter,
+) -> Result<(), String> {
+	// Bounds cached before now may not hold once the scene is transformed
+	scene.invalidate_bounds();
+	expand_arrays(scene);
+
+	if args.negate_z {
+		negate_z(scene);
+	}
+
+	if args.fan {
+		for strip in scene.strips.iter_mut() {
+			if strip.topology == Topology::TriStrip {
+				strip.topology = Topology::TriFan;
+			}
+		}
+		scene.invalidate_bounds();
+	}
+
+	if args.flatten {
+		let world = scene.world;
+		scene.world = flatten_node(
+			scene,
+			&world,
+			&SquareMat::identity(),
+			&HashMap::new(),
+			reporter,
+		);
+		scene.invalidate_bounds();
+	}
+
+	// Changing the up axis and centering follow flattening, so that they may bake into the flattened
+	// geometry rather than reintroduce an instance
+	if args.up_axis == UpAxis::Z {
+		// The exact inverse of the rotation from z-up to y-up, which avoids any rounding by trig
+		let world_to_obj = matrix![
+			1.0, 0.0, 0.0, 0.0;
+			0.0, 0.0, -1.0, 0.0;
+			0.0, 1.0, 0.0, 0.0;
+		];
+		let mut instance = world_instance(scene.world);
+		instance.baked = Some(world_to_obj);
+		transform_world(scene, instance, args.flatten, reporter);
+	}
+
+	if args.center {
+		let world = scene.world;
+		let (min, max) = world.bounds(scene, &Bounding::new(args));
+		if !min.x.is_nan() {
+			let mut instance = world_instance(world);
+			instance.translate = -(min + max) / 2.0;
+			transform_world(scene, instance, args.flatten, reporter);
+		}
+	}
+
 	if args.root {
 		let should_box = match scene.world {
 			Node::Mapping(_) => {
@@ -174,6 +1324,7 @@ pub fn transform(scene: &mut Scene, args: &crate::args::Args, triangle: bool) {
 			// World root must be an object
 			Node::Number(_) => panic!("Cannot box number root!"),
 			Node::Bool(_) => panic!("Cannot box bool root!"),
+			Node::String(_) => panic!("Cannot box string root!"),
 			_ => true,
 		};
 		if should_box {
@@ -185,15 +1336,61 @@ pub fn transform(scene: &mut Scene, args: &crate::args::Args, triangle: bool) {
 			scene.mappings.push(Mapping::new());
 			scene.mappings[name_at]
 				.fields
-				.insert("data".to_string(), Node::Sequence(seq_at));
+				.insert("data".into(), Node::Sequence(seq_at));
 
 			// Replace the old world reference with the newly created one
 			scene.world = Node::Mapping(name_at);
 		}
 	}
 
+	// Warn about any triangles which have no area, since they are useless in any target
+	let degenerate = find_degenerate(scene);
+	for (strip_idx, i) in degenerate.iter() {
+		reporter.warn(&format!(
+			"Triangle {i} of strip {strip_idx} is degenerate (has zero area)!"
+		));
+	}
+	if args.drop_degenerate && !degenerate.is_empty() {
+		drop_degenerate(scene, &degenerate, args.split_index);
+	}
+
+	// Spheres have no direct counterpart in the BVH target, so they become procedurals with tight
+	// bounds
+	if bvh {
+		let world = scene.world;
+		for sphere_idx in 0..scene.spheres.len() {
+			let sphere = &scene.spheres[sphere_idx];
+			let mut fields = sphere.fields.clone();
+			let corners = [
+				("min", sphere.center - new_point(sphere.radius)),
+				("max", sphere.center + new_point(sphere.radius)),
+			];
+			for (name, corner) in corners {
+				let seq_at = scene.sequences.len();
+				let mut seq = Sequence::new();
+				seq.vals.extend(corner.iter().map(|c| Node::Number(*c)));
+				scene.sequences.push(seq);
+				fields.insert(name.into(), Node::Sequence(seq_at));
+			}
+
+			let map_at = scene.mappings.len();
+			let mut map = Mapping::new();
+			map.fields = fields;
+			scene.mappings.push(map);
+
+			let before = Node::Sphere(sphere_idx);
+			let after = Node::Mapping(map_at);
+			if world == before {
+				scene.world = after;
+			} else {
+				replace(scene, &before, &after, &world);
+			}
+		}
+		scene.invalidate_bounds();
+	}
+
 	// Split tri-nodes with more than 3 vertices into individual triangles
-	if triangle {
+	if bvh || args.split {
 		let mut tris = vec![];
 		fn find_to_split(scene: &Scene, tris: &mut Vec<usize>, node: &Node) {
 			match node {
@@ -216,8 +1413,13 @@ pub fn transform(scene: &mut Scene, args: &crate::args::Args, triangle: bool) {
 			}
 		}
 		find_to_split(scene, &mut tris, &scene.world);
+		// A strip reached several times (such as by reference) is only split once
+		tris.sort_unstable();
+		tris.dedup();
 
 		let world = scene.world;
+		let mut total = 0;
+		reporter.start_stage("Splitting strips", tris.len());
 		for tri_idx in tris {
 			let seq_at = scene.sequences.len();
 			scene.sequences.push(Sequence::new());
@@ -226,29 +1428,34 @@ pub fn transform(scene: &mut Scene, args: &crate::args::Args, triangle: bool) {
 			scene.mappings.push(Mapping::new());
 			scene.mappings[map_at]
 				.fields
-				.insert("data".to_string(), Node::Sequence(seq_at));
+				.insert("data".into(), Node::Sequence(seq_at));
 
 			let before = Node::Strip(tri_idx);
 			let after = Node::Mapping(map_at);
-			replace(scene, &before, &after, &world);
+			if world == before {
+				scene.world = after;
+			} else {
+				replace(scene, &before, &after, &world);
+			}
 
-			let triangle = &scene.strips[tri_idx];
 			let mut children = vec![];
-			for i in 2..triangle.vals.len() {
-				let idx = children.len();
-				children.push(Strip::new());
-				if i % 2 == 0 {
-					children[idx].vals.push(triangle.vals[i - 2]);
-					children[idx].vals.push(triangle.vals[i - 1]);
-				} else {
-					children[idx].vals.push(triangle.vals[i - 1]);
-					children[idx].vals.push(triangle.vals[i - 2]);
-				}
-				children[idx].vals.push(triangle.vals[i]);
-
-				for (name, val) in triangle.fields.iter() {
-					children[idx].fields.insert(name.clone(), *val);
+			for verts in scene.strips[tri_idx].triangle_indices() {
+				total += 1;
+				if let Some(limit) = args.max_triangles
+					&& total > limit
+				{
+					reporter.end_stage();
+					return Err(format!(
+						"Splitting strips into triangles exceeds the limit of {limit} triangles!"
+					));
 				}
+				let mut child = Strip::new();
+				child
+					.vals
+					.extend(verts.map(|vert| scene.strips[tri_idx].vals[vert]));
+				child.fields =
+					split_fields(scene, tri_idx, verts, children.len(), args.split_index);
+				children.push(child);
 			}
 
 			for child in children {
@@ -256,7 +1463,14 @@ pub fn transform(scene: &mut Scene, args: &crate::args::Args, triangle: bool) {
 				scene.strips.push(child);
 				scene.sequences[seq_at].vals.push(Node::Strip(kid_at));
 			}
+			reporter.step();
 		}
+		reporter.end_stage();
+		scene.invalidate_bounds();
+	}
+
+	if args.jitter != 0.0 {
+		jitter(scene, args.jitter, args.seed);
 	}
 
 	if args.wrap {
@@ -283,7 +1497,7 @@ pub fn transform(scene: &mut Scene, args: &crate::args::Args, triangle: bool) {
 							scene.mappings.push(Mapping::new());
 							scene.mappings[map_at]
 								.fields
-								.insert("data".to_string(), Node::Sequence(seq_at));
+								.insert("data".into(), Node::Sequence(seq_at));
 							instance.affected = Node::Mapping(map_at);
 						},
 					}
@@ -295,7 +1509,18 @@ pub fn transform(scene: &mut Scene, args: &crate::args::Args, triangle: bool) {
 		wrap_inst_kid(scene, &scene.world.clone());
 	}
 
-	if args.box_size != 0 {
+	if args.auto_bvh {
+		let world = scene.world;
+		let mut visited = vec![false; scene.mappings.len()];
+		auto_bvh(
+			scene,
+			&world,
+			&Bounding::new(args),
+			args.box_size as usize,
+			args.auto_bvh_mode,
+			&mut visited,
+		);
+	} else if args.box_size != 0 {
 		// Split any box which has too many children
 		todo!();
 	}
@@ -305,12 +1530,502 @@ pub fn transform(scene: &mut Scene, args: &crate::args::Args, triangle: bool) {
 	}
 
 	// The last transformation is to add box data to mappings where necessary
+	let bounding = Bounding::new(args);
 	let world = scene.world;
-	world.set_bounds(scene, args.total_box);
+	world.set_bounds(scene, &bounding);
+
+	if let (Some(min), Some(max)) = (args.clip_min, args.clip_max) {
+		let region = (Point3D::from(min), Point3D::from(max));
+		clip(scene, &region, &bounding);
+		// Boxes must shrink to fit whatever remains
+		let world = scene.world;
+		world.set_bounds(scene, &bounding);
+	}
+	check_bounds(scene, args.repair_bounds)?;
+
+	if args.merge_coincident_boxes {
+		merge_coincident_boxes(scene);
+	}
+
+	if args.flatten_single_child_boxes {
+		flatten_single_child_boxes(scene, args.wrap);
+	}
 
 	if args.raw {
 		// If raw is enabled, we must flatten all mappings
 		// Note, this cannot be used in generating BVH output, since that doesn't make sense
 		todo!();
 	}
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::ir::HomoPoint;
+
+	fn parse_str(text: &str) -> Scene {
+		let docs = crate::load::load_from_str(text).unwrap();
+		crate::ir::to_ir(&docs[0]).unwrap()
+	}
+
+	#[test]
+	fn repeated_vertex_degenerate() {
+		let scene = parse_str("strip:\n- [0, 0, 0]\n- [1, 0, 0]\n- [0, 0, 0]\n- [0, 1, 0]");
+		assert_eq!(find_degenerate(&scene), vec![(0, 0)]);
+	}
+
+	#[test]
+	fn drop_degenerate_triangle() {
+		let mut scene = parse_str("strip:\n- [0, 0, 0]\n- [1, 0, 0]\n- [0, 0, 0]\n- [0, 1, 0]");
+		let degenerate = find_degenerate(&scene);
+		drop_degenerate(&mut scene, &degenerate, SplitIndex::Increment);
+		let mut strips = vec![];
+		reachable_strips(&scene, &scene.world, &mut strips);
+		assert_eq!(strips.len(), 1);
+		assert!(find_degenerate(&scene).is_empty());
+	}
+
+	#[test]
+	fn clip_outside_strip() {
+		let mut scene = parse_str(
+			"data:\n- strip: [[0, 0, 0], [1, 0, 0], [0, 1, 0]]\n- strip: [[5, 5, 5], [6, 5, 5], [5, 6, \
+			 5]]\n- instance:\n    strip: [[0, 0, 0], [1, 0, 0], [0, 1, 0]]\n  translate: [0, 0, 9]",
+		);
+		let world = scene.world;
+		world.set_bounds(&mut scene, &Bounding::default());
+		clip(
+			&mut scene,
+			&(new_point(-1.0), new_point(1.0)),
+			&Bounding::default(),
+		);
+		let mut strips = vec![];
+		reachable_strips(&scene, &scene.world, &mut strips);
+		assert_eq!(strips, vec![0]);
+	}
+
+	#[test]
+	fn expand_grid() {
+		let mut scene = parse_str(
+			"tri:\n  strip: [[0, 0, 0], [1, 0, 0], [0, 1, 0]]\ndata:\n- array: tri\n  count: [3, 3, \
+			 1]\n  translate: [2, 5, 0]",
+		);
+		expand_arrays(&mut scene);
+		assert_eq!(scene.instances.len(), 9);
+		for (n, instance) in scene.instances.iter().enumerate() {
+			let (i, j) = (n % 3, n / 3);
+			assert_eq!(
+				instance.translate,
+				Point3D::new(2.0 * i as f64, 5.0 * j as f64, 0.0)
+			);
+			assert_eq!(instance.affected, Node::Strip(0));
+		}
+		assert_eq!(scene.strips.len(), 1);
+	}
+
+	#[test]
+	fn expand_nested_grid() {
+		let mut scene = parse_str(
+			"tri:\n  strip: [[0, 0, 0], [1, 0, 0], [0, 1, 0]]\nrow:\n  array: tri\n  count: [3, 1, \
+			 1]\n  translate: [2, 0, 0]\ndata:\n- array: row\n  count: [1, 2, 1]\n  translate: [0, 5, 0]",
+		);
+		expand_arrays(&mut scene);
+		let mut strips = vec![];
+		reachable_strips(&scene, &scene.world, &mut strips);
+		assert_eq!(strips, vec![0]);
+		// Every copy of the row holds the expanded row, not the array it came from
+		let copies: Vec<&Instance> = scene
+			.instances
+			.iter()
+			.filter(|instance| instance.translate.y != 0.0)
+			.collect();
+		assert_eq!(copies.len(), 1);
+		assert!(matches!(copies[0].affected, Node::Mapping(_)));
+		assert!(
+			scene
+				.instances
+				.iter()
+				.all(|instance| !matches!(instance.affected, Node::Array(_)))
+		);
+	}
+
+	#[test]
+	fn flatten_keeps_instance_fields() {
+		let text = "data:\n- instance:\n    strip: [[0, 0, 0], [1, 0, 0], [0, 1, 0]]\n  color: [0, 0, \
+		            255]\n  name: blue\n  mask: 3\n- instance:\n    strip: [[0, 0, 1], [1, 0, 1], [0, 1, \
+		            1]]\n    color: [255, 0, 0]\n  color: [0, 0, 255]\n- instance:\n    strip: [[0, 0, 2], \
+		            [1, 0, 2], [0, 1, 2]]\n    color: [255, 0, 0]\n  color_override: [0, 255, 0]";
+		let mut scene = parse_str(text);
+		let world = scene.world;
+		scene.world = flatten_node(
+			&mut scene,
+			&world,
+			&SquareMat::identity(),
+			&HashMap::new(),
+			&mut Reporter::new(),
+		);
+		let mut strips = vec![];
+		reachable_strips(&scene, &scene.world, &mut strips);
+		let color = |strip: usize| match scene.strips[strip].fields.get("color") {
+			Some(node) => as_3d(&scene, node).unwrap(),
+			None => panic!("Strip {strip} should have a color!"),
+		};
+		let first = &scene.strips[strips[0]].fields;
+		assert!(matches!(first.get("name"), Some(Node::String(_))));
+		assert!(!first.contains_key("mask"));
+		assert_eq!(color(strips[0]), Point3D::new(0.0, 0.0, 255.0));
+		// The strip's own color takes precedence over the instance's, but not over an override
+		assert_eq!(color(strips[1]), Point3D::new(255.0, 0.0, 0.0));
+		assert_eq!(color(strips[2]), Point3D::new(0.0, 255.0, 0.0));
+		assert!(
+			!scene.strips[strips[2]]
+				.fields
+				.contains_key("color_override")
+		);
+	}
+
+	#[test]
+	fn flatten_matches_instanced() {
+		use clap::Parser;
+		let args = crate::args::Args::parse_from(["scene-builder", "in.yaml"]);
+		let text = "tri:\n  strip: [[0, 0, 0], [1, 0, 0], [0, 1, 0]]\ninner:\n  instance: tri\n  \
+		            rotate: [0, 0, 90]\n  translate: [1, 2, 3]\ndata:\n- inner\n- instance: inner\n  \
+		            scale: [1, 2, 3]\n  rotate: [30, 0, 0]\n  translate: [-4, 0, 0]";
+		let vertices = |scene: &Scene| -> Vec<String> {
+			crate::obj::to_obj(scene, &args, &mut Reporter::new())
+				.into_iter()
+				.filter(|line| line.starts_with("v "))
+				.collect()
+		};
+		let instanced = parse_str(text);
+		let mut flat = parse_str(text);
+		let world = flat.world;
+		flat.world = flatten_node(
+			&mut flat,
+			&world,
+			&SquareMat::identity(),
+			&HashMap::new(),
+			&mut Reporter::new(),
+		);
+		assert_eq!(vertices(&flat), vertices(&instanced));
+
+		let mut strips = vec![];
+		reachable_strips(&flat, &flat.world, &mut strips);
+		assert_eq!(strips.len(), 2);
+	}
+
+	#[test]
+	fn jitter_reproducible() {
+		let text = "strip: [[0, 0, 0], [1, 0, 0], [0, 1, 0], [1, 1, 0]]";
+		let mut first = parse_str(text);
+		let mut second = parse_str(text);
+		let mut other = parse_str(text);
+		jitter(&mut first, 0.1, 7);
+		jitter(&mut second, 0.1, 7);
+		jitter(&mut other, 0.1, 8);
+		let original = parse_str(text);
+		for (i, vert) in first.strips[0].vals.iter().enumerate() {
+			assert_eq!(*vert, second.strips[0].vals[i]);
+			assert_ne!(*vert, other.strips[0].vals[i]);
+			assert!((vert - original.strips[0].vals[i]).amax() <= 0.1);
+		}
+	}
+
+	fn auto_bvh_groups_nearby(mode: &str) {
+		use clap::Parser;
+		let args = crate::args::Args::parse_from([
+			"scene-builder",
+			"in.yaml",
+			"--auto-bvh",
+			"--auto-bvh-mode",
+			mode,
+			"--box-size",
+			"2",
+		]);
+		let mut scene = parse_str(
+			"data:\n- strip: [[0, 0, 0], [1, 0, 0], [0, 1, 0]]\n- strip: [[10, 0, 0], [11, 0, 0], \
+			 [10, 1, 0]]\n- strip: [[1, 0, 0], [2, 0, 0], [1, 1, 0]]\n- strip: [[11, 0, 0], [12, 0, \
+			 0], [11, 1, 0]]",
+		);
+		transform(&mut scene, &args, true, &mut Reporter::new()).unwrap();
+		let children = |scene: &Scene, node: &Node| {
+			let Node::Mapping(idx) = node else {
+				panic!("Expected a mapping, got {node}!");
+			};
+			let Some(Node::Sequence(seq_at)) = scene.mappings[*idx].fields.get("data") else {
+				panic!("Mapping should have data!");
+			};
+			scene.sequences[*seq_at].vals.clone()
+		};
+		let mut groups: Vec<Vec<Node>> = children(&scene, &scene.world)
+			.iter()
+			.map(|child| children(&scene, child))
+			.collect();
+		groups.sort_by_key(|group| group[0].to_string());
+		assert_eq!(
+			groups,
+			[
+				[Node::Strip(0), Node::Strip(2)],
+				[Node::Strip(1), Node::Strip(3)]
+			]
+		);
+	}
+
+	#[test]
+	fn auto_bvh_sah() {
+		auto_bvh_groups_nearby("sah");
+	}
+
+	#[test]
+	fn auto_bvh_median() {
+		auto_bvh_groups_nearby("median");
+	}
+
+	#[test]
+	fn epsilon_pads_flat_box() {
+		let mut scene = parse_str("data:\n- strip: [[0, 0, 0], [1, 0, 0], [0, 1, 0], [1, 1, 0]]");
+		let bounding = Bounding {
+			epsilon: 0.5,
+			..Default::default()
+		};
+		let world = scene.world;
+		world.set_bounds(&mut scene, &bounding);
+		let map = &scene.mappings[0];
+		assert_eq!(map.min, Point3D::new(0.0, 0.0, -0.5));
+		assert_eq!(map.max, Point3D::new(1.0, 1.0, 0.5));
+
+		let bounding = Bounding {
+			epsilon_all: true,
+			..bounding
+		};
+		world.set_bounds(&mut scene, &bounding);
+		let map = &scene.mappings[0];
+		assert_eq!(map.min, Point3D::new(-0.5, -0.5, -0.5));
+		assert_eq!(map.max, Point3D::new(1.5, 1.5, 0.5));
+	}
+
+	#[test]
+	fn split_keeps_spheres() {
+		use clap::Parser;
+		let text = "data:\n- sphere: [0, 0, 0]\n  radius: 1\n- strip: [[0, 0, 0], [1, 0, 0], [0, 1, 0], \
+		            [1, 1, 0]]";
+		let args = crate::args::Args::parse_from(["scene-builder", "in.yaml", "--split"]);
+		for (bvh, spheres) in [(false, 1), (true, 0)] {
+			let mut scene = parse_str(text);
+			transform(&mut scene, &args, bvh, &mut Reporter::new()).unwrap();
+			let data = match scene.world {
+				Node::Mapping(idx) => match scene.mappings[idx].fields.get("data") {
+					Some(Node::Sequence(seq)) => scene.sequences[*seq].vals.clone(),
+					_ => panic!("The world should have data!"),
+				},
+				_ => panic!("The world should be a mapping!"),
+			};
+			let kept = data
+				.iter()
+				.filter(|node| matches!(node, Node::Sphere(_)))
+				.count();
+			assert_eq!(kept, spheres);
+			// Strips are split either way
+			assert!(!data.contains(&Node::Strip(0)));
+		}
+	}
+
+	#[test]
+	fn single_child_boxes() {
+		use clap::Parser;
+		let text = "data:\n- data:\n  - data:\n    - strip: [[0, 0, 0], [1, 0, 0], [0, 1, 0]]\n- data:\n  - \
+		            strip: [[0, 0, 1], [1, 0, 1], [0, 1, 1]]\n  - strip: [[0, 0, 2], [1, 0, 2], [0, 1, \
+		            2]]\n- data:\n  - strip: [[0, 0, 3], [1, 0, 3], [0, 1, 3]]\n  opaque: true";
+		let box_nodes = |extra: &[&str]| {
+			let args =
+				crate::args::Args::parse_from(["scene-builder", "in.yaml"].iter().chain(extra));
+			let mut scene = parse_str(text);
+			transform(&mut scene, &args, true, &mut Reporter::new()).unwrap();
+			let bvh = crate::bvh::to_bvh(&scene, &args, &mut Reporter::new());
+			bvh.iter()
+				.filter(|line| line.contains("child_nodes"))
+				.count()
+		};
+		assert_eq!(box_nodes(&[]), 5);
+		// The chain collapses to the strip it holds, but the box with a field of its own is kept
+		assert_eq!(box_nodes(&["--flatten-single-child-boxes"]), 3);
+	}
+
+	#[test]
+	fn coincident_boxes() {
+		use clap::Parser;
+		let text = "data:\n- data:\n  - strip: [[0, 0, 0], [1, 0, 0], [0, 1, 0]]\n  - strip: [[0, 0, 1], \
+		            [1, 0, 1], [0, 1, 1]]";
+		let args = crate::args::Args::parse_from(["scene-builder", "in.yaml"]);
+		let mut scene = parse_str(text);
+		transform(&mut scene, &args, true, &mut Reporter::new()).unwrap();
+		assert_eq!(
+			crate::bvh::to_bvh(&scene, &args, &mut Reporter::new())
+				.iter()
+				.filter(|line| line.contains("child_nodes"))
+				.count(),
+			2
+		);
+
+		let args =
+			crate::args::Args::parse_from(["scene-builder", "in.yaml", "--merge-coincident-boxes"]);
+		let mut scene = parse_str(text);
+		transform(&mut scene, &args, true, &mut Reporter::new()).unwrap();
+		let bvh = crate::bvh::to_bvh(&scene, &args, &mut Reporter::new());
+		assert_eq!(
+			bvh.iter()
+				.filter(|line| line.contains("child_nodes"))
+				.count(),
+			1
+		);
+
+		// A box with fields of its own is kept
+		let mut scene = parse_str(&format!("{text}\n  opaque: true"));
+		transform(&mut scene, &args, true, &mut Reporter::new()).unwrap();
+		let bvh = crate::bvh::to_bvh(&scene, &args, &mut Reporter::new());
+		assert_eq!(
+			bvh.iter()
+				.filter(|line| line.contains("child_nodes"))
+				.count(),
+			2
+		);
+	}
+
+	#[test]
+	fn inverted_bounds() {
+		use clap::Parser;
+		let text = "min: [0, 2, 0]\nmax: [1, 1, 1]\nname: bad";
+		let args = crate::args::Args::parse_from(["scene-builder", "in.yaml"]);
+		let mut scene = parse_str(text);
+		assert_eq!(
+			transform(&mut scene, &args, true, &mut Reporter::new()),
+			Err(
+				"Procedural \"bad\" has `min` (2) greater than its `max` (1) on the y axis!"
+					.to_string()
+			)
+		);
+
+		let args = crate::args::Args::parse_from(["scene-builder", "in.yaml", "--repair-bounds"]);
+		let mut scene = parse_str(text);
+		transform(&mut scene, &args, true, &mut Reporter::new()).unwrap();
+		let map = &scene.mappings[0];
+		assert_eq!(
+			as_3d(&scene, &map.fields["min"]),
+			Ok(Point3D::new(0.0, 1.0, 0.0))
+		);
+		assert_eq!(
+			as_3d(&scene, &map.fields["max"]),
+			Ok(Point3D::new(1.0, 2.0, 1.0))
+		);
+	}
+
+	#[test]
+	fn negate_z_mirrors_instance() {
+		let mut scene = parse_str(
+			"instance:\n  strip: [[0, 0, 1], [1, 0, 1], [0, 1, 1]]\nscale: [2, 1, 3]\nrotate: [20, \
+			 60, 100]\ntranslate: [-1, 2, 3]",
+		);
+		let before = homogenize(&scene.instances[0].obj_to_world());
+		negate_z(&mut scene);
+		let after = homogenize(&scene.instances[0].obj_to_world());
+		let mirror = SquareMat::from_diagonal(&HomoPoint::new(1.0, 1.0, -1.0, 1.0));
+		assert!((after - mirror * before * mirror).abs().max() < 1e-9);
+		assert_eq!(scene.strips[0].vals[0], Point3D::new(0.0, 0.0, -1.0));
+	}
+
+	fn centered_bounds(extra: &[&str]) -> (Point3D, Point3D) {
+		use clap::Parser;
+		let args = crate::args::Args::parse_from(
+			["scene-builder", "in.yaml", "--center"].iter().chain(extra),
+		);
+		let mut scene = parse_str(
+			"data:\n- strip: [[100, 50, 7], [101, 50, 7], [100, 52, 7]]\n- instance:\n    strip: [[0, \
+			 0, 0], [1, 0, 0], [0, 1, 0]]\n  translate: [103, 50, 9]",
+		);
+		transform(&mut scene, &args, true, &mut Reporter::new()).unwrap();
+		let world = scene.world;
+		world.set_bounds(&mut scene, &Bounding::default())
+	}
+
+	#[test]
+	fn center() {
+		for extra in [&[][..], &["--flatten"][..]] {
+			let (min, max) = centered_bounds(extra);
+			assert!((min + max).amax() < 1e-9);
+			assert!((max - Point3D::new(2.0, 1.0, 1.0)).amax() < 1e-9);
+		}
+	}
+
+	#[test]
+	fn z_up() {
+		use clap::Parser;
+		for extra in [&[][..], &["--flatten"][..]] {
+			let args = crate::args::Args::parse_from(
+				["scene-builder", "in.yaml", "--up-axis", "z"]
+					.iter()
+					.chain(extra),
+			);
+			let mut scene = parse_str("strip: [[0, 0, 1], [1, 0, 0], [0, 1, 0]]");
+			transform(&mut scene, &args, false, &mut Reporter::new()).unwrap();
+			let lines = crate::obj::to_obj(&scene, &args, &mut Reporter::new());
+			let verts: Vec<&String> = lines.iter().filter(|line| line.starts_with("v ")).collect();
+			assert_eq!(verts, vec!["v 0 1 0", "v 1 0 0", "v 0 0 -1"]);
+		}
+	}
+
+	#[test]
+	fn max_triangles() {
+		use clap::Parser;
+		let args =
+			crate::args::Args::parse_from(["scene-builder", "in.yaml", "--max-triangles", "10"]);
+		let verts: Vec<String> = (0..22)
+			.map(|i| format!("[{}, {}, 0]", i / 2, i % 2))
+			.collect();
+		let mut scene = parse_str(&format!("strip: [{}]", verts.join(", ")));
+		assert_eq!(
+			transform(&mut scene, &args, true, &mut Reporter::new()),
+			Err("Splitting strips into triangles exceeds the limit of 10 triangles!".to_string())
+		);
+	}
+
+	#[test]
+	fn max_triangles_shared() {
+		use clap::Parser;
+		let args =
+			crate::args::Args::parse_from(["scene-builder", "in.yaml", "--max-triangles", "2"]);
+		let mut scene = parse_str(
+			"data:\n- &q\n  strip: [[0, 0, 0], [1, 0, 0], [0, 1, 0], [1, 1, 0]]\n- instance: *q",
+		);
+		transform(&mut scene, &args, true, &mut Reporter::new()).unwrap();
+		// The quad and its two triangles, with none split twice
+		assert_eq!(scene.strips.len(), 3);
+		let mut strips = vec![];
+		reachable_strips(&scene, &scene.world, &mut strips);
+		strips.sort_unstable();
+		strips.dedup();
+		assert_eq!(strips, [1, 2]);
+	}
+
+	#[test]
+	fn split_distinct_primitive_indices() {
+		use clap::Parser;
+		let args = crate::args::Args::parse_from(["scene-builder", "in.yaml"]);
+		let mut scene = parse_str(
+			"data:\n- strip: [[0, 0, 0], [1, 0, 0], [0, 1, 0], [1, 1, 0], [0, 2, 0]]\n  \
+			 primitive_index: 10",
+		);
+		transform(&mut scene, &args, true, &mut Reporter::new()).unwrap();
+		let mut strips = vec![];
+		reachable_strips(&scene, &scene.world, &mut strips);
+		let indices: Vec<f64> = strips
+			.iter()
+			.map(
+				|idx| match scene.strips[*idx].fields.get("primitive_index") {
+					Some(Node::Number(v)) => *v,
+					_ => panic!("primitive_index missing!"),
+				},
+			)
+			.collect();
+		assert_eq!(indices, vec![10.0, 11.0, 12.0]);
+	}
 }
@@ -0,0 +1,137 @@
+use crate::args::Args;
+use crate::ir::{Fields, Node, Point3D, Scene, fmt_float};
+
+/// The kind of the object followed by its `name` field (quoted) if it has a nonempty one, else its
+/// index within the scene
+fn label(kind: &str, fields: &Fields, scene: &Scene, idx: usize) -> String {
+	match fields.get("name") {
+		Some(Node::String(str_at)) if !scene.strings[*str_at].is_empty() => {
+			format!("{kind} \"{}\"", scene.strings[*str_at])
+		},
+		_ => format!("{kind} {idx}"),
+	}
+}
+
+fn fmt_point(point: &Point3D, args: &Args) -> String {
+	format!(
+		"[{}, {}, {}]",
+		fmt_float(point.x, args.precision),
+		fmt_float(point.y, args.precision),
+		fmt_float(point.z, args.precision)
+	)
+}
+
+fn handle_node(node: &Node, depth: usize, lines: &mut Vec<String>, scene: &Scene, args: &Args) {
+	let indent = "  ".repeat(depth);
+	match node {
+		Node::Strip(idx) => {
+			let strip = &scene.strips[*idx];
+			lines.push(format!(
+				"{indent}{} ({} vertices)",
+				label("strip", &strip.fields, scene, *idx),
+				strip.vals.len()
+			));
+		},
+		Node::Point(idx) => {
+			lines.push(format!(
+				"{indent}{}",
+				label("point", &scene.points[*idx].fields, scene, *idx)
+			));
+		},
+		Node::Ray(idx) => {
+			lines.push(format!(
+				"{indent}{}",
+				label("ray", &scene.rays[*idx].fields, scene, *idx)
+			));
+		},
+		Node::Line(idx) => {
+			lines.push(format!(
+				"{indent}{}",
+				label("line", &scene.lines[*idx].fields, scene, *idx)
+			));
+		},
+		Node::Sphere(idx) => {
+			lines.push(format!(
+				"{indent}{}",
+				label("sphere", &scene.spheres[*idx].fields, scene, *idx)
+			));
+		},
+		Node::Instance(idx) => {
+			let instance = &scene.instances[*idx];
+			lines.push(format!(
+				"{indent}{}",
+				label("instance", &instance.fields, scene, *idx)
+			));
+			handle_node(&instance.affected, depth + 1, lines, scene, args);
+		},
+		Node::Mapping(idx) => {
+			let map = &scene.mappings[*idx];
+			let label = if !map.is_box {
+				label("mapping", &map.fields, scene, *idx)
+			} else {
+				let kind = if map.fields.contains_key("min") {
+					"procedural"
+				} else {
+					"box"
+				};
+				format!(
+					"{}: {} to {}",
+					label(kind, &map.fields, scene, *idx),
+					fmt_point(&map.min, args),
+					fmt_point(&map.max, args)
+				)
+			};
+			lines.push(format!("{indent}{label}"));
+			if let Some(Node::Sequence(seq)) = map.fields.get("data") {
+				for child in scene.sequences[*seq].vals.iter() {
+					handle_node(child, depth + 1, lines, scene, args);
+				}
+			}
+		},
+		// Any other value in the hierarchy is shown as it would be in the IR
+		node => lines.push(format!("{indent}{node}")),
+	}
+}
+
+/// List the hierarchy of the world, one object per line, indented by its depth. Objects referenced
+/// multiple times are listed wherever they are referenced.
+pub fn to_tree(scene: &Scene, args: &Args) -> Vec<String> {
+	let mut lines = vec![];
+	handle_node(&scene.world, 0, &mut lines, scene, args);
+	lines
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn two_levels() {
+		use clap::Parser;
+		let args = Args::parse_from(["scene-builder", "in.yaml", "--tree"]);
+		let docs = crate::load::load_from_str(
+			"data:\n- instance:\n    strip: [[0, 0, 0], [1, 0, 0], [0, 1, 0]]\n    name: tri\n  \
+			 translate: [0, 0, 2]\n- origin: [0, 0, 0]\n  direction: [1, 0, 0]\n  max: 1",
+		)
+		.unwrap();
+		let mut scene = crate::ir::to_ir(&docs[0]).unwrap();
+		crate::transform::transform(
+			&mut scene,
+			&args,
+			false,
+			&mut crate::report::Reporter::new(),
+		)
+		.unwrap();
+		let lines = to_tree(&scene, &args);
+		// The ray does not affect the bounds of the world box, since --total-box is not given
+		assert_eq!(
+			lines,
+			[
+				"box 0: [0, 0, 2] to [1, 1, 2]",
+				"  instance 0",
+				"    strip \"tri\" (3 vertices)",
+				"  ray 0",
+			]
+		);
+	}
+}
@@ -84,7 +84,7 @@ for (root, dirs, files) in os.walk(example_path, topdown=True):
                 obj_out = file
             elif file.endswith(".json"):
                 bvh_json_out = file
-        elif file.endswith(".yaml"):
+        elif file.endswith(".yaml") or file.endswith(".yaml.gz"):
             scene = file
         elif file == "args.txt":
             with open(os.path.join(root, file), "r") as f:
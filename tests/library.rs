@@ -0,0 +1,29 @@
+use scene_builder::args::OutputFormat;
+use scene_builder::{CompileOptions, compile, recompile_bvh};
+
+#[test]
+fn compile_to_bvh() {
+	let options = CompileOptions::default()
+		.format(OutputFormat::Bvh)
+		.root(true);
+	let out = compile("strip: [[0, 0, 0], [1, 0, 0], [0, 1, 0]]", options).unwrap();
+	assert_eq!(out.lines.first().unwrap(), "{");
+	assert!(out.lines.contains(&"\t\"tlas\" : [ 0, 0 ],".to_string()));
+	assert!(
+		out.lines
+			.iter()
+			.any(|line| line.contains("\"triangle_nodes\""))
+	);
+	assert_eq!(out.lines.last().unwrap(), "}");
+}
+
+#[test]
+fn bvh_round_trip() {
+	let scene = "data:\n- strip: [[0, 0, 0], [1, 0, 0], [0, 1, 0]]\n- instance:\n    sphere: [0, 0, 0]\n    radius: 1\n  \
+	             translate: [3, 0, 0]";
+	let options = || CompileOptions::parse(["--format", "bvh"]).unwrap();
+	let out = compile(scene, options()).unwrap();
+	let again = recompile_bvh(&out.lines.join("\n"), options()).unwrap();
+	assert_eq!(again.lines, out.lines);
+	assert!(recompile_bvh("{\"tlas\": 5}", options()).is_err());
+}